@@ -1,17 +1,28 @@
-use rand::thread_rng;
+use rand::RngCore;
 
 use crate::{Command, Decision, Decider};
 use crate::ApiDoc;
+use crate::distribution::{Distribution, Outcome};
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 const COIN_SIDES: [&str; 2] = ["Heads", "Tails"];
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Coin;
 
 impl Decider for Coin {
-    fn decide(&self) -> Decision {
-        let mut rng = thread_rng();
-        Decision::Text(super::pick_one(&mut rng, &COIN_SIDES))
+    fn decide_with(&self, rng: &mut dyn RngCore) -> Decision {
+        Decision::Text(super::pick_one(rng, &COIN_SIDES))
+    }
+
+    /// Heads and Tails are equally likely.
+    fn distribution(&self) -> Option<Distribution> {
+        Some(Distribution::uniform(
+            COIN_SIDES.iter().map(|side| Outcome::Text(side.to_string())).collect()
+        ))
     }
 }
 
@@ -40,6 +51,7 @@ pub fn api_doc() -> ApiDoc
 mod tests
 {
     use spectral::prelude::*;
+    use rand::rngs::mock::StepRng;
 
     const NUM_TRIES: u32 = 3;
     use crate::Decision;
@@ -76,4 +88,38 @@ mod tests
             }
         }
     }
+
+    #[test]
+    fn lowest_draw_is_heads()
+    {
+        assert_that!(Coin{}.decide_with(&mut StepRng::new(0, 0))).is_equal_to(Decision::Text("Heads".into()));
+    }
+
+    #[test]
+    fn coin_distribution()
+    {
+        let dist = Coin{}.distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(2);
+        assert_that!(dist.probability(&Outcome::Text("Heads".into()))).is_equal_to(0.5);
+        assert_that!(dist.probability(&Outcome::Text("Tails".into()))).is_equal_to(0.5);
+    }
+
+    #[test]
+    fn coin_is_fair()
+    {
+        let fit = crate::fairness::chi_square(&command().unwrap(), 2000, 42).unwrap();
+        assert_that!(fit.is_consistent(0.001)).is_true();
+    }
+
+    #[test]
+    fn decide_from_source()
+    {
+        use crate::rng::RngSource;
+
+        let report = Coin{}.decide_from(RngSource::Seeded(42));
+        assert_that!(report.source).is_equal_to(RngSource::Seeded(42));
+        assert_that!(report.decision).is_equal_to(Coin{}.decide_with(&mut crate::rng::seeded(42)));
+        assert_that!(report.to_string()).is_equal_to("'Tails' (source: seeded(42))".to_string());
+        assert_that!(Coin{}.decide_from(RngSource::Os).decision).is_text_decision();
+    }
 }
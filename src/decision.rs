@@ -1,8 +1,16 @@
 use derive_more::{Display, IsVariant};
 use crate::deck;
+use crate::rng::RngSource;
+
+use std::cmp::Ordering;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 /// The Decision enum encapsulates values returned from the decide method.
-#[derive(Debug, IsVariant, Display)]
+#[derive(Debug, PartialEq, IsVariant, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(tag = "type", content = "value"))]
 pub enum Decision
 {
     #[display(fmt = "'{}'", _0)]
@@ -12,11 +20,134 @@ pub enum Decision
     #[display(fmt = "'{}'", _0)]
     Num(i32),
     #[display(fmt = "{}: '{}'", value, extra)]
-    AnnotatedNum{ value: u32, extra: String },
+    AnnotatedNum{ value: i64, extra: String },
     #[display(fmt = "{}", _0)]
     Bool(bool),
     #[display(fmt = "'{:?}'", _0)]
     List(Vec<String>),
     #[display(fmt = "{}", _0)]
     Card(deck::Card),
+    #[display(fmt = "{}", "join(_0)")]
+    LabelledNums(Vec<LabelledNum>),
+    #[display(fmt = "{}: {} vs {}", result, left, right)]
+    Opposed{ result: Versus, left: LabelledNum, right: LabelledNum },
+    #[display(fmt = "{}: {} vs {}", value, left, right)]
+    Compared{ value: bool, left: LabelledNum, right: LabelledNum },
+}
+
+/// The result of an opposed roll, from the point of view of the first roll.
+#[derive(Debug, Clone, Copy, PartialEq, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Versus
+{
+    Win,
+    Lose,
+    Tie,
+}
+
+impl From<Ordering> for Versus
+{
+    /// Convert the ordering of the first total against the second.
+    fn from(ordering: Ordering) -> Versus
+    {
+        match ordering
+        {
+            Ordering::Greater => Versus::Win,
+            Ordering::Less    => Versus::Lose,
+            Ordering::Equal   => Versus::Tie,
+        }
+    }
+}
+
+/// A labelled number with a description, one of several in a Decision.
+#[derive(Debug, Clone, PartialEq, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[display(fmt = "{}: {}: '{}'", label, value, extra)]
+pub struct LabelledNum
+{
+    pub label: String,
+    pub value: i64,
+    pub extra: String,
+}
+
+/// Join the items with commas.
+fn join<T: fmt::Display>(items: &[T]) -> String
+{
+    items.iter().map(|item| item.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+/// A Decision together with the source of the randomness used to make it.
+#[derive(Debug, PartialEq, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[display(fmt = "{} (source: {})", decision, source)]
+pub struct Report
+{
+    pub decision: Decision,
+    pub source: RngSource,
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests
+{
+    use spectral::prelude::*;
+
+    use super::*;
+
+    fn round_trip(decision: Decision, json: &str)
+    {
+        assert_that!(serde_json::to_string(&decision).unwrap()).is_equal_to(json.to_string());
+        assert_that!(serde_json::from_str::<Decision>(json).unwrap()).is_equal_to(decision);
+    }
+
+    #[test]
+    fn serde_text()
+    {
+        round_trip(Decision::Text("Heads".into()), r#"{"type":"Text","value":"Heads"}"#);
+        round_trip(Decision::LabelledText{value: "Yes".into(), label: "The Oracle says".into()},
+            r#"{"type":"LabelledText","value":{"value":"Yes","label":"The Oracle says"}}"#);
+        round_trip(Decision::List(vec!["a".into(), "b".into()]), r#"{"type":"List","value":["a","b"]}"#);
+    }
+
+    #[test]
+    fn serde_numbers()
+    {
+        round_trip(Decision::Num(-3), r#"{"type":"Num","value":-3}"#);
+        round_trip(Decision::AnnotatedNum{value: 7, extra: "2d6(3+4)".into()},
+            r#"{"type":"AnnotatedNum","value":{"value":7,"extra":"2d6(3+4)"}}"#);
+        round_trip(Decision::Bool(true), r#"{"type":"Bool","value":true}"#);
+    }
+
+    #[test]
+    fn serde_labelled_nums()
+    {
+        round_trip(Decision::LabelledNums(vec![LabelledNum{label: "hit".into(), value: 17, extra: "1d20(12) + 5".into()}]),
+            r#"{"type":"LabelledNums","value":[{"label":"hit","value":17,"extra":"1d20(12) + 5"}]}"#);
+    }
+
+    #[test]
+    fn serde_contests()
+    {
+        let left = LabelledNum{label: "1d20 + 3".into(), value: 15, extra: "1d20(12) + 3".into()};
+        let right = LabelledNum{label: "7".into(), value: 7, extra: "7".into()};
+        round_trip(Decision::Opposed{result: Versus::Win, left: left.clone(), right: right.clone()},
+            r#"{"type":"Opposed","value":{"result":"Win","left":{"label":"1d20 + 3","value":15,"extra":"1d20(12) + 3"},"right":{"label":"7","value":7,"extra":"7"}}}"#);
+        round_trip(Decision::Compared{value: false, left, right},
+            r#"{"type":"Compared","value":{"value":false,"left":{"label":"1d20 + 3","value":15,"extra":"1d20(12) + 3"},"right":{"label":"7","value":7,"extra":"7"}}}"#);
+    }
+
+    #[test]
+    fn serde_card()
+    {
+        round_trip(Decision::Card(deck::Card::Trump{glyph: Some('\u{1F0E9}'), name: "The Hermit", number: 9}),
+            r#"{"type":"Card","value":{"kind":"Trump","glyph":"🃩","name":"The Hermit","number":9}}"#);
+    }
+
+    #[test]
+    fn serde_report()
+    {
+        let report = Report{ decision: Decision::Bool(true), source: RngSource::Os };
+        let json = r#"{"decision":{"type":"Bool","value":true},"source":"Os"}"#;
+        assert_that!(serde_json::to_string(&report).unwrap()).is_equal_to(json.to_string());
+        assert_that!(serde_json::from_str::<Report>(json).unwrap()).is_equal_to(report);
+    }
 }
@@ -3,12 +3,16 @@ extern crate numerals;
 use crate::{Command, Decision, Decider};
 use crate::ApiDoc;
 use crate::Error;
+use crate::distribution::{Distribution, Outcome};
 
 use numerals::roman::Roman;
-use rand::seq::SliceRandom;
+use rand::RngCore;
 
 use std::fmt;
 
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
 mod standard;
 mod tarot;
 
@@ -17,7 +21,8 @@ mod tarot;
 /// - Card::Face describes the face or court cards
 /// - Card::Joker describes the joker or fool cards
 /// - Card::Trump describes the trump cards from a tarot deck
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(tag = "kind"))]
 pub enum Card
 {
     Pip{glyph: Option<char>, suit: &'static str, number: usize},
@@ -26,11 +31,60 @@ pub enum Card
     Trump{glyph: Option<char>, name: &'static str, number: usize},
 }
 
+/// Owned form of a Card, used when deserializing. It is converted into the
+/// matching Card from one of the supported decks.
+#[cfg(feature = "serde")]
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "kind")]
+enum CardRepr
+{
+    Pip{glyph: Option<char>, suit: String, number: usize},
+    Face{glyph: Option<char>, suit: String, number: usize, face: String},
+    Joker{glyph: Option<char>, name: String},
+    Trump{glyph: Option<char>, name: String, number: usize},
+}
+
+#[cfg(feature = "serde")]
+impl From<&Card> for CardRepr
+{
+    fn from(card: &Card) -> CardRepr
+    {
+        match *card
+        {
+            Card::Pip{glyph, suit, number} =>
+                CardRepr::Pip{glyph, suit: suit.to_owned(), number},
+            Card::Face{glyph, suit, number, face} =>
+                CardRepr::Face{glyph, suit: suit.to_owned(), number, face: face.to_owned()},
+            Card::Joker{glyph, name} =>
+                CardRepr::Joker{glyph, name: name.to_owned()},
+            Card::Trump{glyph, name, number} =>
+                CardRepr::Trump{glyph, name: name.to_owned(), number},
+        }
+    }
+}
+
+/// Cards are deserialized by finding the matching Card in one of the decks,
+/// since the Card only refers to static names.
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Card
+{
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Card, D::Error>
+        where D: serde::Deserializer<'de>
+    {
+        let repr = CardRepr::deserialize(deserializer)?;
+        standard::cards_and_jokers().into_iter()
+            .chain(tarot::cards())
+            .find(|card| CardRepr::from(card) == repr)
+            .ok_or_else(|| serde::de::Error::custom(Error::UnrecognizedCard(format!("{:?}", repr))))
+    }
+}
+
 /// Enum representing the supported kinds of decks
 /// - Deck::Standrd52: the standard 52-card French or poker deck, without jokers
 /// - Deck::Jokers: The same deck as above with 2 jokers
 /// - Deck::Tarot: the historical tarot deck
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Deck
 {
     Standard52,
@@ -138,28 +192,47 @@ pub fn command(deck: &str) -> crate::Result<Command>
 
 impl Decider for Deck {
     /// Draw a card from the deck
-    fn decide(&self) -> Decision {
-        let mut rng = rand::thread_rng();
+    fn decide_with(&self, rng: &mut dyn RngCore) -> Decision {
         let card = match self
         {
-            Deck::Standard52 => standard::draw_card(&mut rng),
-            Deck::Jokers     => standard::draw_card_or_joker(&mut rng),
-            Deck::Tarot      => tarot::draw_card(&mut rng),
+            Deck::Standard52 => standard::draw_card(rng),
+            Deck::Jokers     => standard::draw_card_or_joker(rng),
+            Deck::Tarot      => tarot::draw_card(rng),
         };
         Decision::Card(card)
     }
+
+    /// Each card in the deck is equally likely.
+    fn distribution(&self) -> Option<Distribution> {
+        Some(Distribution::uniform(cards(self).into_iter().map(Outcome::Card).collect()))
+    }
 }
 
-pub fn shuffled(deck: &Deck) -> Vec<Card>
+/// Return all of the cards in the deck in order.
+fn cards(deck: &Deck) -> Vec<Card>
 {
-    let cards = match deck
+    match deck
     {
         Deck::Standard52 => standard::cards(),
         Deck::Jokers     => standard::cards_and_jokers(),
         Deck::Tarot      => tarot::cards(),
-    };
-    let mut rng = &mut rand::thread_rng();
-    cards.choose_multiple(&mut rng, cards.len()).cloned().collect()
+    }
+}
+
+/// Return all of the cards in the deck in random order, using the default
+/// random number generator.
+pub fn shuffled(deck: &Deck) -> Vec<Card>
+{
+    crate::rng::with_source(crate::rng::default_source(), |rng| shuffled_with(deck, rng))
+}
+
+/// Return all of the cards in the deck in an order determined by the
+/// supplied random number generator.
+pub fn shuffled_with(deck: &Deck, rng: &mut dyn RngCore) -> Vec<Card>
+{
+    let mut cards = cards(deck);
+    crate::rng::shuffle(rng, &mut cards);
+    cards
 }
 
 /// Return an ApiDoc object containing a description of the DrawCard
@@ -183,8 +256,14 @@ pub fn api_doc() -> ApiDoc
 mod tests
 {
     use spectral::prelude::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use rand::rngs::mock::StepRng;
 
+    use crate::{Decider, Decision};
+    use crate::DecisionAssertions;
     use crate::deck;
+    use crate::distribution::Outcome;
     use crate::deck::standard;
     use crate::deck::tarot;
     use crate::deck::Deck;
@@ -334,4 +413,75 @@ mod tests
         let cards = deck::shuffled(&Deck::Tarot);
         assert_eq!(cards.len(), 78);
     }
+
+    #[test]
+    fn decide_check()
+    {
+        assert_that!(deck::command("tarot").unwrap().decide())
+            .is_card_decision();
+    }
+
+    #[test]
+    fn decide_with_rng()
+    {
+        let mut rng = StepRng::new(0, 0);
+        match Deck::Standard52.decide_with(&mut rng)
+        {
+            Decision::Card(card) => assert_that!(card.to_string()).is_equal_to("Ace of Spades".to_string()),
+            _ => panic!("Wrong decision type"),
+        }
+    }
+
+    #[test]
+    fn shuffled_with_rng()
+    {
+        let first = deck::shuffled_with(&Deck::Tarot, &mut StdRng::seed_from_u64(42));
+        let second = deck::shuffled_with(&Deck::Tarot, &mut StdRng::seed_from_u64(42));
+        assert_that!(first).is_equal_to(second);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_card_round_trip()
+    {
+        for card in standard::cards_and_jokers().into_iter().chain(tarot::cards())
+        {
+            let json = serde_json::to_string(&card).unwrap();
+            assert_that!(serde_json::from_str::<deck::Card>(&json).unwrap()).is_equal_to(card);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_card_format()
+    {
+        let card = standard::card(0).unwrap();
+        assert_that!(serde_json::to_string(&card).unwrap())
+            .is_equal_to(r#"{"kind":"Pip","glyph":"🂡","suit":"Spades","number":1}"#.to_string());
+        assert_that!(serde_json::from_str::<deck::Card>(r#"{"kind":"Pip","glyph":"🂡","suit":"Spades","number":14}"#))
+            .is_err();
+    }
+
+    #[test]
+    fn deck_distribution()
+    {
+        let sizes = [(Deck::Standard52, 52), (Deck::Jokers, 54), (Deck::Tarot, 78)];
+        for (deck, size) in sizes.iter()
+        {
+            let dist = deck.distribution().unwrap();
+            assert_that!(dist.len()).is_equal_to(*size);
+            let card = Outcome::Card(deck::cards(deck)[0].clone());
+            assert_that!(dist.probability(&card)).is_close_to(1.0 / *size as f64, 1e-12);
+        }
+    }
+
+    #[test]
+    fn decks_are_fair()
+    {
+        for name in &["52-card", "jokers", "tarot"]
+        {
+            let fit = crate::fairness::chi_square(&deck::command(name).unwrap(), 8000, 42).unwrap();
+            assert_that!(fit.is_consistent(0.001)).is_true();
+        }
+    }
 }
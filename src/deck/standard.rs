@@ -1,5 +1,7 @@
 use super::Card;
-use rand::Rng;
+use rand::RngCore;
+
+use crate::rng;
 
 const DECK_SIZE:  usize = 52;
 const DECK_MAX:   usize = DECK_SIZE-1;
@@ -70,17 +72,17 @@ pub(crate) fn cards_and_jokers() -> Vec<Card>
 
 /// Randomly choose a card from a standard 52 card deck without jokers
 pub(crate) fn draw_card<T>(rng: &mut T) -> Card
-    where T: Rng
+    where T: RngCore + ?Sized
 {
-    let num = rng.gen_range(0, DECK_SIZE);
+    let num = rng::gen_index(rng, DECK_SIZE);
     card(num).unwrap()
 }
 
 /// Randomly choose a card from a standard 52 card deck with jokers
 pub(crate) fn draw_card_or_joker<T>(rng: &mut T) -> Card
-    where T: Rng
+    where T: RngCore + ?Sized
 {
-    let num = rng.gen_range(0, JDECK_SIZE);
+    let num = rng::gen_index(rng, JDECK_SIZE);
     card_or_joker(num).unwrap()
 }
 
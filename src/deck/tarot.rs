@@ -1,5 +1,7 @@
 use super::Card;
-use rand::Rng;
+use rand::RngCore;
+
+use crate::rng;
 
 const DECK_SIZE: usize = 78;
 const NUM_MINOR: usize = 56;
@@ -75,9 +77,9 @@ pub(crate) fn cards() -> Vec<Card>
 
 /// Randomly select a Tarot Card
 pub(crate) fn draw_card<T>(rng: &mut T) -> Card
-    where T: Rng
+    where T: RngCore + ?Sized
 {
-    let num = rng.gen_range(0, DECK_SIZE);
+    let num = rng::gen_index(rng, DECK_SIZE);
     card(num).unwrap()
 }
 
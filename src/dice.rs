@@ -1,22 +1,294 @@
 use crate::{Command, Decision, Decider};
 use crate::ApiDoc;
 use crate::Error;
+use crate::distribution::Distribution;
 
-use rand::Rng;
-use regex::Regex;
+use rand::{Rng, RngCore};
 
-type RollStep = (String, u32);
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
 
-#[derive(Debug, PartialEq)]
+#[cfg(feature = "serde")]
+use ::serde::{Serialize, Deserialize};
+
+/// The number of times an exploding die may explode when computing the
+/// distribution of a Command.
+pub const DEFAULT_EXPLODE_DEPTH: u32 = 5;
+
+/// The default largest number of sides allowed on a die.
+pub const DEFAULT_MAX_SIDES: u32 = 1_000_000;
+
+/// The default largest number of dice allowed in a single term.
+pub const DEFAULT_MAX_DICE: u32 = 10_000;
+
+/// The default largest number of dice a Command may roll in all.
+pub const DEFAULT_MAX_TOTAL_DICE: u32 = 100_000;
+
+/// The default largest number of terms allowed in an expression.
+pub const DEFAULT_MAX_TERMS: u32 = 1000;
+
+/// The most times a single die is re-rolled by a reroll modifier that is not
+/// limited to once.
+pub const MAX_REROLLS: usize = 100;
+
+/// The default most times a single die may explode when it is rolled.
+pub const MAX_EXPLOSIONS: u32 = 100;
+
+/// The most times an expression may be repeated with #.
+pub const MAX_REPEAT: u32 = 1000;
+
+mod dist;
+mod labelled;
+mod parser;
+#[cfg(feature = "serde")]
+mod serde;
+
+pub use labelled::{Contest, Labelled, Repeat, RollSet, Sort};
+
+#[cfg(feature = "serde")]
+use self::serde::{UncheckedDie, UncheckedExpr, UncheckedRoll};
+
+/// A single term of a dice expression.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "UncheckedRoll"))]
 pub enum Roll
 {
     Dice(u32, u32),
     ExplodingDice(u32, u32),
     Incr(u32),
+    /// Fate/Fudge dice, with faces of -1, 0, and +1.
+    Fudge(u32),
+    /// Dice with modifiers changing how they are rolled or totalled.
+    Modified(Box<Roll>, Modifiers),
+    /// Dice with faces defined by the user, such as symbols.
+    Custom(u32, CustomDie),
+}
+
+/// A named die with faces of the user's choosing, rolled as `3dBoost`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "UncheckedDie"))]
+pub struct CustomDie
+{
+    name: String,
+    faces: Vec<Face>,
+}
+
+/// One face of a custom die, adding its value to the total and showing any
+/// symbols, which are tallied across the roll.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Face
+{
+    pub value: i64,
+    pub symbols: Vec<String>,
+}
+
+/// The modifiers applied to a roll of dice.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Modifiers
+{
+    pub reroll: Option<Reroll>,
+    pub minimum: Option<i64>,
+    pub explode: Option<Explode>,
+    pub keep: Option<Keep>,
+    /// Count the kept dice matching this condition, instead of summing them.
+    pub successes: Option<Condition>,
+    /// Subtract one success for each kept die matching this condition.
+    pub failures: Option<Condition>,
+    /// The most times an exploding die may explode, if not MAX_EXPLOSIONS.
+    pub max_explosions: Option<u32>,
+}
+
+/// Re-roll any die whose roll matches the condition, either once or until it
+/// no longer matches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Reroll
+{
+    pub condition: Condition,
+    pub once: bool,
+}
+
+/// Re-roll a die each time it rolls a face matching the condition, or its
+/// maximum if there is no condition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Explode
+{
+    pub condition: Option<Condition>,
+    pub style: ExplodeStyle,
+}
+
+/// How the re-rolls of an exploding die are combined with the original roll.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ExplodeStyle
+{
+    /// Each re-roll is added to the die and shown separately.
+    Chained,
+    /// The re-rolls are added to the die and shown as a single value.
+    Compounding,
+    /// Each re-roll is added to the die less one.
+    Penetrating,
+}
+
+/// A comparison of a die roll against a value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Condition
+{
+    pub compare: Compare,
+    pub value: i64,
+}
+
+/// The comparison made by a Condition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Compare
+{
+    Equal,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+/// Which of the dice in a roll are kept for the total.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Keep
+{
+    Highest(u32),
+    Lowest(u32),
+    DropHighest(u32),
+    DropLowest(u32),
+}
+
+/// Limits on the dice expressions accepted by `command_with_limits()`.
+/// Deserialized expressions are checked against the default limits, so an
+/// expression only accepted by larger limits can't be deserialized.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits
+{
+    pub max_sides: u32,
+    pub max_dice: u32,
+    /// The most dice rolled by the whole Command, counting the dice of each
+    /// expression of a RollSet as often as it is repeated.
+    pub max_total_dice: u32,
+    /// The most terms, dice or numbers, in an expression or in both
+    /// expressions of a contest.
+    pub max_terms: u32,
+    /// The most times each exploding die may explode when it is rolled,
+    /// recorded in the modifiers of every exploding term. None leaves the
+    /// limit at MAX_EXPLOSIONS without recording it.
+    pub max_explosions: Option<u32>,
+}
+
+impl Default for Limits
+{
+    fn default() -> Limits
+    {
+        Limits{
+            max_sides: DEFAULT_MAX_SIDES,
+            max_dice: DEFAULT_MAX_DICE,
+            max_total_dice: DEFAULT_MAX_TOTAL_DICE,
+            max_terms: DEFAULT_MAX_TERMS,
+            max_explosions: None,
+        }
+    }
+}
+
+/// An error in a dice expression, giving where in the expression it was
+/// found and what is wrong there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError
+{
+    /// The text of the expression being parsed.
+    pub expr: String,
+    /// The byte range of the text in error, empty where something is missing.
+    pub span: Range<usize>,
+    /// What is wrong with the text in the span.
+    pub problem: ParseProblem,
+}
+
+/// What is wrong with the text in the span of a ParseError.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseProblem
+{
+    /// The text does not fit the grammar, with a description of what was
+    /// expected instead.
+    Expected(&'static str),
+    /// The text is well formed, but describes dice that can't be rolled.
+    Invalid(Box<Error>),
+}
+
+/// How the result of dividing a dice expression is rounded to an integer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Rounding
+{
+    Down,
+    Up,
+    Nearest,
+}
+
+/// A dice expression, combining the terms with arithmetic. Dividing by zero
+/// gives zero, but `command()` rejects any expression whose divisor could be
+/// zero.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "UncheckedExpr"))]
+pub enum Expr
+{
+    Roll(Roll),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>, Rounding),
+}
+
+/// The result of rolling a single die. The first face is the original roll,
+/// any further faces are re-rolls added because the die exploded. Rolls
+/// discarded by a reroll modifier are kept in rerolled. A dropped die does not
+/// count towards the total.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Die
+{
+    pub faces: Vec<i64>,
+    pub rerolled: Vec<i64>,
+    pub dropped: bool,
+    /// The symbols on the face rolled, for a custom die.
+    pub symbols: Vec<String>,
+}
+
+/// The result of rolling one term of a dice expression, with the dice rolled
+/// (empty for an increment) and the subtotal of the term.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RolledTerm
+{
+    pub term: Roll,
+    pub dice: Vec<Die>,
+    pub subtotal: i64,
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Expr(Vec<Roll>);
+/// The result of rolling a full dice expression, with each term in the order
+/// it appears in the expression. The Display form is the roll description
+/// returned in the Decision.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rolled
+{
+    pub expr: Expr,
+    pub terms: Vec<RolledTerm>,
+    pub total: i64,
+}
 
 /// Return an ApiDoc object describing the Dice decider.
 pub fn api_doc() -> ApiDoc
@@ -27,301 +299,2301 @@ pub fn api_doc() -> ApiDoc
         hint: "Roll the described combination of dice",
         help: vec![
             "Roll the described combination of dice, returning a number and description of the",
-            "roll. The {dice expr} is a combination of terms of one of three forms joined by +:",
+            "roll. The {dice expr} is made of terms of one of these forms:",
             "  - {n}d{s}: roll n s-sided dice (3d6)",
             "  - {n}x{s}: roll n s-sided exploding dice (2x8)",
+            "  - {n}dF: roll n Fate/Fudge dice, each -1, 0, or +1 (4dF)",
+            "  - {n}d{name}: roll n custom dice supplied by the caller (3dBoost)",
             "  - {n}: an increment.",
-            "The number of sides supported are 3, 4, 6, 8, 10, 12, 20, or 100. Exploding dice",
-            "work much like normal, except when a die rolls the maximum value for the die,",
-            "then it is re-rolled to generate a value to add to the original roll. This may",
-            "happen more than once.",
-            "The return is the sum of all of the rolls followed by a string representing the",
-            "individual rolls.",
+            "Dice may have any number of sides up to 1000000, and d% is the same as d100. A term",
+            "may roll at most 10000 dice, an expression may have at most 1000 terms, and a",
+            "command may roll at most 100000 dice in all, counting each repeat. Exploding dice",
+            "work much like normal, except when a die rolls its maximum value, it is re-rolled",
+            "to generate a value to add to the original roll. This may happen again, up to 100",
+            "times for each die.",
+            "Dice may be followed by a modifier keeping the highest or lowest k dice (4d6kh3,",
+            "2d20kl1), or dropping the highest or lowest k dice (4d6dl1, 3d6dh1). They may also",
+            "re-roll dice matching a value or comparison until they don't (2d6r1, 4d6r<3), or",
+            "only once (2d6ro<3), and raise any die below a minimum to that value (4d6min2).",
+            "Dice followed by ! explode like x dice, on the maximum or on any roll matching a",
+            "value or comparison (4d6!, 4d10!>=8). !! compounds the re-rolls into a single value",
+            "(4d6!!), and !p subtracts one from each re-roll (4d6!p).",
+            "A comparison after the dice counts the dice meeting it as successes instead of",
+            "summing them (10d10>=8), and f{v} subtracts a success for each die rolling v",
+            "(10d10>=8f1). A roll with no successes and at least one failure is a botch.",
+            "Terms are combined with +, -, *, and / (rounding down), /^ (rounding up), or",
+            "/~ (rounding to nearest), grouped with parens, and negated with a leading -:",
+            "(1d4+1)*2 or d20 - 2. A divisor that could be zero is not allowed, and parens and",
+            "minus signs may enclose a term at most 100 deep.",
+            "The lowest and highest totals, mean, and variance of an expression are returned",
+            "without rolling by the min(), max(), mean(), and variance() methods of dice::Expr:",
+            "2d6 has a min of 2, a max of 12, a mean of 7, and a variance of about 5.83.",
+            "The highest total has each exploding die exploding as often as it may, but exploding",
+            "dice are treated as exploding at most 5 times for the mean and variance.",
+            "Several expressions separated by ; or , are rolled together, each optionally",
+            "preceded by a label and a colon: hit: d20+5; dmg: 2d6+3.",
+            "An expression preceded by {n}# is rolled n times, giving n totals, sorted lowest",
+            "first if followed by the word sa or highest first by sd: 6#4d6kh3 sd.",
+            "Two expressions separated by vs are rolled against each other, and the first",
+            "wins, loses, or ties: d20+3 vs d20+5. Separated by a comparison, the result is",
+            "whether the comparison holds: 2d6 > 7. A comparison after dice must have spaces",
+            "around it, since 2d6>7 counts successes. Either way, the total and description",
+            "of both expressions are returned.",
+            "The return is the total of all of the rolls followed by a string representing the",
+            "individual rolls, or the label, total, and description of each expression.",
             "Normal dice are represented by the expression, followed by the results of the",
-            "individual die rolls in parens: 3d6(3+5+1).",
-            "Exploding dice are represented by the dice expression followed by an expression",
-            "in angle brackets. The expression is made of the results of each individual die",
-            "in parens, with any die that exploded being followed by an !, and the re-roll",
-            "added as needed: 3x6<(1)+(6!+5)+(2)>."
+            "individual die rolls in parens: 3d6(3+5+1). Exploding dice are followed by the",
+            "results in angle brackets instead, each die in parens, with any die that exploded",
+            "being followed by an !, and the re-roll added as needed: 3x6<(1)+(6!+5)+(2)>.",
+            "Dice exploded with ! are grouped in parens: 3d6!(1+(6!+5)+2), and compounded",
+            "dice are shown as their total followed by !!: 3d6!!(1+11!!+2). Fudge dice show",
+            "the sign of each die: 4dF(+1-1+0+1). Dice that were dropped are marked with ~:",
+            "4d6kh3(6+5+~1~+4). Each roll discarded by a reroll modifier is followed by an r:",
+            "2d6r1(1r4+3). A botched dice pool is followed by [botch]:",
+            "5d10>=8f1(3+1+2+5+4)[botch].",
+            "Custom dice show the face rolled on each die, and a tally of the symbols rolled",
+            "follows the roll: 2dBoost(success, 1 advantage) [advantage: 1, success: 1]."
         ],
     }
 }
 
-fn uint_from_match(m: regex::Match) -> crate::Result<u32>
-{
-    match m.as_str()
-    {
-        ""   => Ok(1),
-        nstr => nstr
-            .parse::<u32>()
-            .map_err(|_| Error::NotANumber(nstr.to_owned())),
-    }
-}
-
-fn make_dice(dice: regex::Match, sides: regex::Match) -> crate::Result<Roll>
+/// Construct a Command object representing the dice to roll.
+/// Expects a string containing a dice expression.
+pub fn command(expr: String) -> crate::Result<Command>
 {
-    Ok(Roll::Dice(uint_from_match(dice)?, uint_from_match(sides)?))
+    command_with_limits(expr, &Limits::default())
 }
 
-fn make_exploding_dice(dice: regex::Match, sides: regex::Match) -> crate::Result<Roll>
+/// Construct a Command object representing the dice to roll, rejecting
+/// expressions that exceed the supplied limits.
+///
+/// Two expressions separated by vs (`d20+3 vs d20+5`), or by a comparison
+/// (`2d6 > 7`), construct a Contest. A comparison directly after dice counts
+/// successes instead (`2d6>7`), so one after dice that has whitespace on only
+/// one side is rejected.
+///
+/// Several expressions separated by semicolons or commas, each with an
+/// optional label (`hit: d20+5; dmg: 2d6+3`), or a repeated expression
+/// (`6#4d6kh3`), construct a RollSet. An unlabelled expression in a RollSet is
+/// labelled with its own text, and the totals of an unlabelled repeated
+/// expression are labelled with their position.
+///
+/// A Command accepted only because of limits larger than the default can be
+/// serialized, but not deserialized.
+pub fn command_with_limits(expr: String, limits: &Limits) -> crate::Result<Command>
 {
-    Ok(Roll::ExplodingDice(
-        uint_from_match(dice)?,
-        uint_from_match(sides)?,
-    ))
+    command_with_dice(expr, limits, &[])
 }
 
-/// Construct a Command object representing the dice to roll.
-/// Expects a string containing a dice expression.
-pub fn command(expr: String) -> crate::Result<Command>
+/// Construct a Command object representing the dice to roll, as for
+/// `command_with_limits()`, where the expression may also roll any of the
+/// supplied custom dice by name (`3dBoost`).
+pub fn command_with_dice(expr: String, limits: &Limits, dice: &[CustomDie]) -> crate::Result<Command>
 {
-    if expr.is_empty()
+    if expr.trim().is_empty()
     {
         return Err(Error::DiceMissingExpr);
     }
 
-    let re = Regex::new(r"^\s*(?:(?P<num>(?:[1-9][0-9]*)?)(?P<type>[dDxX])(?P<sides>[3468]|1[02]|20|100)|(?P<val>[1-9][0-9]*))\s*$").unwrap();
-    let mut descr: Vec<Roll> = Vec::new();
-    for term in expr.split("+")
+    let parts: Vec<&str> = expr.split(&[';', ','][..]).collect();
+    if let [part] = parts[..]
     {
-        let cap = re.captures(&term)
-                    .ok_or_else(|| Error::DiceBadExpr(term.to_owned()))?;
-        descr.push(match (cap.name("num"), cap.name("sides"))
+        if !part.contains(&[':', '#'][..])
         {
-            (Some(n), Some(s)) => match cap.name("type").unwrap().as_str()
-            {
-                "x" | "X" => make_exploding_dice(n, s)?,
-                "d" | "D" => make_dice(n, s)?,
-                t => return Err(Error::DiceBadType(t.to_owned())),
-            },
-            (Some(_), None) => return Err(Error::DiceBadSides),
-            (None, _) => match cap.name("val")
+            let command = parser::parse_command(part, limits, dice)?;
+            let total = match &command
             {
-                Some(n) => Roll::Incr(uint_from_match(n)?),
-                None => return Err(Error::UnparseableTerm),
+                Command::RollContest(contest) => contest.total_dice(),
+                Command::RollDice(expr)       => expr.total_dice(),
+                _                             => 0,
+            };
+            check_total_dice(total, limits)?;
+            return Ok(command);
+        }
+    }
+    let mut rolls = Vec::new();
+    let mut start = 0;
+    for part in parts
+    {
+        // Errors are located in the whole expression, not just this part.
+        let roll = match part.split_once(':')
+        {
+            Some((label, _)) if label.trim().is_empty() => {
+                let colon = start + label.len();
+                Err(parser::located(&expr, colon..colon + 1, ParseProblem::Expected("a label before ':'")))
             },
-        });
+            Some((label, text)) => labelled(Some(label.trim()), text, limits, dice)
+                .map_err(|error| parser::relocate(error, &expr, start + label.len() + 1)),
+            None => labelled(None, part, limits, dice)
+                .map_err(|error| parser::relocate(error, &expr, start)),
+        };
+        rolls.push(roll?);
+        start += part.len() + 1;
     }
-
-    Ok(Command::RollDice(Expr(descr)))
+    let set = RollSet{ rolls };
+    check_total_dice(set.total_dice(), limits)?;
+    Ok(Command::RollDiceSet(set))
 }
 
-fn roll_die<T>(rng: &mut T, sides: u32) -> RollStep
-    where T: Rng
+/// Check that a Command rolling the total number of dice is within the
+/// limits.
+fn check_total_dice(total: u64, limits: &Limits) -> crate::Result<()>
 {
-    incr_step(rng.gen_range(1, sides + 1))
+    if total > u64::from(limits.max_total_dice)
+    {
+        return Err(Error::DiceTooManyTotalDice(total, limits.max_total_dice));
+    }
+    Ok(())
 }
 
-fn accum_roll((desc, val): RollStep, (rdesc, roll): RollStep, sep: &str) -> RollStep
+/// Parse one expression of a RollSet, which may be repeated. Errors are
+/// located in text.
+fn labelled(label: Option<&str>, text: &str, limits: &Limits, dice: &[CustomDie]) -> crate::Result<Labelled>
 {
-    if desc.is_empty()
+    let (repeat, expr, offset) = match text.split_once('#')
+    {
+        None => (None, text, 0),
+        Some((count, expr)) => {
+            let offset = count.len() + 1;
+            let span = parser::trimmed(text, 0..count.len());
+            let bad_repeat = |expected| parser::located(text, span.clone(), ParseProblem::Expected(expected));
+            let count: u32 = count.trim().parse().map_err(|_| bad_repeat("a repeat count"))?;
+            if count == 0
+            {
+                return Err(bad_repeat("at least one repeat"));
+            }
+            if count > MAX_REPEAT
+            {
+                return Err(bad_repeat("a smaller repeat count"));
+            }
+            // The sort order is a separate word, so it can't be part of a die name.
+            let expr = expr.trim_end();
+            let (rest, word) = expr.rsplit_once(char::is_whitespace).unwrap_or(("", expr));
+            let sort = match word.to_ascii_lowercase().as_str()
+            {
+                "sa" => Some(Sort::Ascending),
+                "sd" => Some(Sort::Descending),
+                _    => None,
+            };
+            (Some(Repeat{ count, sort }), if sort.is_some() { rest } else { expr }, offset)
+        },
+    };
+    if expr.trim().is_empty()
     {
-        return (rdesc, val + roll);
+        return Err(Error::DiceMissingExpr);
     }
-    (desc + sep + &rdesc, val + roll)
+    let label = match (label, repeat)
+    {
+        (Some(label), _) => label.to_owned(),
+        (None, Some(_))  => String::new(),
+        (None, None)     => text.trim().to_owned(),
+    };
+    let expr = parser::parse_with_dice(expr, limits, dice).map_err(|error| parser::relocate(error, text, offset))?;
+    Ok(Labelled{ label, expr, repeat })
 }
 
-fn roll_step<T>(rng: &mut T, num: u32, sides: u32) -> RollStep
-    where T: Rng
+fn roll_die<T>(rng: &mut T, sides: u32) -> i64
+    where T: Rng + ?Sized
 {
-    let (desc, roll) = (1..=num)
-        .map(|_| roll_die(rng, sides))
-        .fold((String::new(), 0), |acc, r| accum_roll(acc, r, "+"));
-    (format!("{}d{}({})", num, sides, desc), roll)
+    i64::from(rng.gen_range(0, sides)) + 1
 }
 
-fn roll_explode_step<T>(rng: &mut T, num: u32, sides: u32) -> RollStep
-    where T: Rng
+fn roll_fudge_die<T>(rng: &mut T) -> i64
+    where T: Rng + ?Sized
 {
-    let (desc, roll) = (1..=num)
-        .map(|_| roll_exploded_step(rng, sides))
-        .map(|(d, r)| (format!(" ({}) ", d), r))
-        .fold((String::new(), 0), |acc, r| accum_roll(acc, r, "+"));
-    (format!("{}x{}<{}>", num, sides, desc.trim()), roll)
+    i64::from(rng.gen_range(0, 3)) - 1
 }
 
-fn roll_exploded_step<T>(rng: &mut T, sides: u32) -> RollStep
-    where T: Rng
+/// Roll a single face of the base die of a term.
+fn roll_face<T>(rng: &mut T, base: &Roll) -> i64
+    where T: Rng + ?Sized
 {
-    let roll = roll_die(rng, sides);
-    explode(rng, roll, sides)
+    match *base
+    {
+        Roll::Dice(_, sides) | Roll::ExplodingDice(_, sides) => roll_die(rng, sides),
+        Roll::Fudge(_) => roll_fudge_die(rng),
+        Roll::Incr(num) => i64::from(num),
+        Roll::Modified(ref base, _) => roll_face(rng, base),
+        Roll::Custom(_, ref die) => roll_custom(rng, die).value,
+    }
 }
 
-fn incr_step(num: u32) -> RollStep
+/// Roll a custom die, returning the face rolled.
+fn roll_custom<'a, T>(rng: &mut T, die: &'a CustomDie) -> &'a Face
+    where T: Rng + ?Sized
 {
-    (num.to_string(), num)
+    &die.faces[crate::rng::gen_index(rng, die.faces.len())]
 }
 
-fn explode<T>(rng: &mut T, (desc, val): RollStep, sides: u32) -> RollStep
-    where T: Rng
+/// Roll one die of the base term, applying any reroll and minimum modifiers to
+/// the original roll. An exploding die is re-rolled and added each time it
+/// rolls the maximum.
+fn roll_one_die<T>(rng: &mut T, base: &Roll, mods: &Modifiers) -> Die
+    where T: Rng + ?Sized
 {
-    if val != sides { return (desc, val); }
-
-    let (rdesc, roll) = roll_exploded_step(rng, sides);
-    (format!("{}!+{}", desc, rdesc), val + roll)
+    if let Roll::Custom(_, die) = base
+    {
+        let face = roll_custom(rng, die);
+        return Die{ symbols: face.symbols.clone(), ..Die::new(vec![face.value]) };
+    }
+    let mut rerolled = Vec::new();
+    let mut face = roll_face(rng, base);
+    if let Some(reroll) = mods.reroll
+    {
+        let limit = if reroll.once { 1 } else { MAX_REROLLS };
+        while reroll.condition.matches(face) && rerolled.len() < limit
+        {
+            rerolled.push(face);
+            face = roll_face(rng, base);
+        }
+    }
+    if let Some(minimum) = mods.minimum
+    {
+        face = face.max(minimum);
+    }
+    let mut faces = vec![face];
+    if let Some((condition, style, sides)) = explosion(base, mods)
+    {
+        let limit = mods.explosion_limit() as usize;
+        let mut roll = face;
+        while condition.matches(roll) && faces.len() <= limit
+        {
+            roll = roll_die(rng, sides);
+            faces.push(if style == ExplodeStyle::Penetrating { roll - 1 } else { roll });
+        }
+    }
+    Die{ faces, rerolled, dropped: false, symbols: Vec::new() }
 }
 
-impl Decider for Expr {
-    /// Perform the random function and return a Decision object representing
-    /// the result.
-    fn decide(&self) -> Decision
+/// Return the condition, style, and sides of the dice if the term explodes.
+pub(super) fn explosion(base: &Roll, mods: &Modifiers) -> Option<(Condition, ExplodeStyle, u32)>
+{
+    let max = |sides| Condition{ compare: Compare::Equal, value: i64::from(sides) };
+    match (base, mods.explode)
     {
-        let mut rng = rand::thread_rng();
-        // { value: roll, description: roll_string }
-        let (desc, roll) = self.0
-            .iter()
-            .map(|ref x| match x
-            {
-                Roll::Dice(num, sides) => roll_step(&mut rng, *num, *sides),
-                Roll::ExplodingDice(num, sides) => roll_explode_step(&mut rng, *num, *sides),
-                Roll::Incr(num) => incr_step(*num),
-            })
-            .fold((String::new(), 0), |acc, r| accum_roll(acc, r, " + "));
-        Decision::AnnotatedNum{ value: roll, extra: desc.to_string() }
+        (Roll::ExplodingDice(_, sides), _) => Some((max(*sides), ExplodeStyle::Chained, *sides)),
+        (Roll::Dice(_, sides), Some(explode)) =>
+            Some((explode.condition.unwrap_or_else(|| max(*sides)), explode.style, *sides)),
+        _ => None,
     }
 }
 
-#[cfg(test)]
-mod tests
+/// The part of a dice term found to be in error by `check_term()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum TermPart
 {
-    use spectral::prelude::*;
-
-    use crate::Decision;
-    use crate::DecisionAssertions;
-    use crate::Decider;
-    use crate::Command;
-    use super::*;
+    Count,
+    Sides,
+    Modifiers,
+}
 
-    #[test]
-    fn command_empty_string()
+/// Check that the dice, with the modifiers, can be rolled within the limits,
+/// returning the part of the term in error and what is wrong with it.
+pub(super) fn check_term(base: &Roll, mods: &Modifiers, limits: &Limits) -> Result<(), (TermPart, ParseProblem)>
+{
+    let sides = match *base
     {
-        assert_that!(command(String::new()))
-            .is_err_containing(Error::DiceMissingExpr);
+        Roll::Dice(_, sides) | Roll::ExplodingDice(_, sides) => Some(sides),
+        _ => None,
+    };
+    let count = dice_count(base);
+    if count == 0
+    {
+        return Err((TermPart::Count, ParseProblem::Expected("at least one die")));
     }
-
-    #[test]
-    fn command_simple_roll()
+    if count > limits.max_dice
     {
-        assert_that!(command("3d8".into()))
-            .is_ok_containing(Command::RollDice(Expr(vec![Roll::Dice(3, 8)])))
+        return Err((TermPart::Count, ParseProblem::Invalid(Box::new(Error::DiceTooManyDice(count, limits.max_dice)))));
     }
-
-    #[test]
-    fn command_all_sides()
+    match sides
     {
-        for i in vec![3,4,6,8,10,12,20,100]
-        {
-            assert_that!(command(format!("1d{}", i)))
-                .is_ok_containing(Command::RollDice(Expr(vec![Roll::Dice(1, i)])))
-        }
+        Some(0) => return Err((TermPart::Sides, ParseProblem::Expected("at least one side"))),
+        Some(sides) if sides > limits.max_sides =>
+            return Err((TermPart::Sides, ParseProblem::Invalid(Box::new(Error::DiceTooManySides(sides, limits.max_sides))))),
+        _ => (),
+    }
+    let max_explosions = limits.max_explosions.unwrap_or(MAX_EXPLOSIONS);
+    if let Some(max) = mods.max_explosions.filter(|&max| max > max_explosions)
+    {
+        let error = Error::DiceTooManyExplosions(max, max_explosions);
+        return Err((TermPart::Modifiers, ParseProblem::Invalid(Box::new(error))));
+    }
+    let explodes_all = explosion(base, mods)
+        .is_some_and(|(condition, _, sides)| condition.unmatched(1, i64::from(sides)).is_none());
+    if explodes_all && mods.explode.is_none()
+    {
+        return Err((TermPart::Sides, ParseProblem::Expected("an explosion that some faces don't trigger")));
     }
 
-    #[test]
-    fn command_exploding_roll()
+    let rerolls_all = match mods.reroll
     {
-        assert_that!(command("3x6".into()))
-            .is_ok_containing(Command::RollDice(Expr(vec![Roll::ExplodingDice(3, 6)])))
+        Some(Reroll{ condition, once: false }) => {
+            let (low, high) = face_bounds(base);
+            condition.unmatched(low, high).is_none()
+        },
+        _ => false,
+    };
+    let problem = if matches!(base, Roll::Custom(..)) && *mods != Modifiers::default()
+    {
+        "no modifiers on custom dice"
+    }
+    else if rerolls_all
+    {
+        "a reroll that leaves some faces"
+    }
+    else if mods.keep.is_some_and(|keep| keep.dropped(count).0 == count)
+    {
+        "at least one die kept"
+    }
+    else if mods.explode.is_some() && !matches!(base, Roll::Dice(..))
+    {
+        "modifiers these dice accept"
+    }
+    else if explodes_all
+    {
+        "an explosion that some faces don't trigger"
+    }
+    else if mods.failures.is_some() && mods.successes.is_none()
+    {
+        "a success condition before the failures"
     }
+    else
+    {
+        return Ok(());
+    };
+    Err((TermPart::Modifiers, ParseProblem::Expected(problem)))
+}
 
-    #[test]
-    fn command_multiterm_expresion()
+fn roll_term<T>(rng: &mut T, term: &Roll) -> RolledTerm
+    where T: Rng + ?Sized
+{
+    let unmodified = Modifiers::default();
+    let (base, mods) = match term
     {
-        assert_that!(command("2d12 + 3x6 + 2".into()))
-            .is_ok_containing(Command::RollDice(Expr(vec![Roll::Dice(2, 12), Roll::ExplodingDice(3, 6), Roll::Incr(2)])))
+        Roll::Modified(base, mods) => (&**base, mods),
+        term                       => (term, &unmodified),
+    };
+    let mut dice: Vec<Die> = (0..dice_count(base)).map(|_| roll_one_die(rng, base, mods)).collect();
+    if let Some(keep) = mods.keep
+    {
+        drop_dice(&mut dice, keep);
     }
+    let subtotal = match *term
+    {
+        Roll::Incr(num) => i64::from(num),
+        _ => dice.iter()
+            .filter(|die| !die.dropped)
+            .fold(0, |total: i64, die| total.saturating_add(mods.score(die.total()))),
+    };
+    RolledTerm{ term: term.clone(), dice, subtotal }
+}
 
-    #[test]
-    fn dice_roll_decision()
+/// Mark the dice that are not kept as dropped. Among dice with equal totals,
+/// the earliest are dropped first.
+fn drop_dice(dice: &mut [Die], keep: Keep)
+{
+    let (drop, highest) = keep.dropped(dice.len() as u32);
+    let mut order: Vec<usize> = (0..dice.len()).collect();
+    if highest
     {
-        assert_that!(command("2d12 + 3x6 + 2".into()).unwrap().decide())
-            .is_annotated_num_decision();
+        order.sort_by_key(|&i| std::cmp::Reverse(dice[i].total()));
+    }
+    else
+    {
+        order.sort_by_key(|&i| dice[i].total());
     }
+    for &i in order.iter().take(drop as usize)
+    {
+        dice[i].dropped = true;
+    }
+}
 
-    #[test]
-    fn simple_roll_value()
+impl Keep
+{
+    /// Return the number of dice dropped from a roll of num dice, and whether
+    /// the highest dice are the ones dropped.
+    fn dropped(self, num: u32) -> (u32, bool)
     {
-        match Expr(vec![Roll::Dice(1, 6)]).decide()
+        match self
         {
-            Decision::AnnotatedNum{value, extra} => {
-                assert_that!(&value).is_greater_than_or_equal_to(1);
-                assert_that!(&value).is_less_than_or_equal_to(6);
-                assert_that!(extra).starts_with("1d6(");
-                assert_that!(extra).ends_with(")");
-            },
-            _ => assert!(false, "Wrong decision type"),
+            Keep::Highest(keep)     => (num.saturating_sub(keep), false),
+            Keep::Lowest(keep)      => (num.saturating_sub(keep), true),
+            Keep::DropHighest(drop) => (drop.min(num), true),
+            Keep::DropLowest(drop)  => (drop.min(num), false),
         }
     }
+}
 
-    #[test]
-    fn explode_roll_value()
+impl Modifiers
+{
+    /// Return the amount a kept die adds to the total: its value, or when
+    /// counting successes 1 for a success, -1 for a failure, and 0 otherwise.
+    fn score(&self, value: i64) -> i64
     {
-        match Expr(vec![Roll::ExplodingDice(1, 6)]).decide()
+        match (self.successes, self.failures)
         {
-            Decision::AnnotatedNum{value, extra} => {
-                assert_that!(&value).is_greater_than_or_equal_to(1);
-                assert_that!(value % 6).is_not_equal_to(0);
-                assert_that!(extra).starts_with("1x6<");
-                assert_that!(extra).ends_with(">");
-            },
-            _ => assert!(false, "Wrong decision type"),
+            (None, _)                                     => value,
+            (Some(success), _) if success.matches(value)  => 1,
+            (_, Some(failure)) if failure.matches(value)  => -1,
+            _                                             => 0,
         }
     }
 
-    #[test]
-    fn incr_value()
+    /// Return the most times a die may explode.
+    fn explosion_limit(&self) -> u32
+    {
+        self.max_explosions.unwrap_or(MAX_EXPLOSIONS)
+    }
+
+    /// Return true if every die with a total of at least low scores the same.
+    fn scores_all_from(&self, low: i64) -> bool
     {
-        match Expr(vec![Roll::Incr(1)]).decide()
+        match self.successes.map(|success| success.matches_all_from(low))
         {
-            Decision::AnnotatedNum{value, extra} => {
-                assert_that!(value).is_equal_to(1);
-                assert_that!(extra).is_equal_to("1".to_string());
-            },
-            _ => assert!(false, "Wrong decision type"),
+            None              => false,
+            Some(Some(true))  => true,
+            Some(Some(false)) => self.failures.is_none_or(|failure| failure.matches_all_from(low).is_some()),
+            Some(None)        => false,
         }
     }
+}
 
-    #[test]
-    fn multi_roll_value()
+impl Condition
+{
+    /// Return true if the roll matches the condition.
+    pub fn matches(&self, roll: i64) -> bool
     {
-        match Expr(vec![Roll::Dice(3, 6)]).decide()
+        match self.compare
         {
-            Decision::AnnotatedNum{value, extra} => {
-                assert_that!(&value).is_greater_than_or_equal_to(3);
-                assert_that!(&value).is_less_than_or_equal_to(18);
-                assert_that!(extra).starts_with("3d6(");
-                assert_that!(extra).ends_with(")");
-            },
-            _ => assert!(false, "Wrong decision type"),
+            Compare::Equal        => roll == self.value,
+            Compare::Less         => roll < self.value,
+            Compare::LessEqual    => roll <= self.value,
+            Compare::Greater      => roll > self.value,
+            Compare::GreaterEqual => roll >= self.value,
         }
     }
 
-    #[test]
-    fn multi_exploding_roll_value()
+    /// Return whether the condition matches every roll of at least low, or
+    /// None if it matches only some of them.
+    fn matches_all_from(&self, low: i64) -> Option<bool>
     {
-        match Expr(vec![Roll::ExplodingDice(3, 6)]).decide()
+        match self.compare
         {
-            Decision::AnnotatedNum{value, extra} => {
-                assert_that!(&value).is_greater_than_or_equal_to(3);
-                assert_that!(extra).starts_with("3x6<");
-                assert_that!(extra).ends_with(">");
-            },
-            _ => assert!(false, "Wrong decision type"),
+            Compare::Greater | Compare::GreaterEqual => Some(true).filter(|_| self.matches(low)),
+            Compare::Less | Compare::LessEqual       => Some(false).filter(|_| !self.matches(low)),
+            Compare::Equal                           => Some(false).filter(|_| self.value < low),
         }
     }
 
-    #[test]
-    fn complex_roll_value()
+    /// Return the lowest and highest rolls from low to high that do not match
+    /// the condition, or None if they all match.
+    fn unmatched(&self, low: i64, high: i64) -> Option<(i64, i64)>
     {
-        match Expr(vec![Roll::Dice(3, 6), Roll::Dice(2, 8), Roll::ExplodingDice(1, 20), Roll::Incr(2)]).decide()
+        let value = self.value;
+        let (low, high) = match self.compare
         {
-            Decision::AnnotatedNum{value, extra} => {
-                assert_that!(&value).is_greater_than_or_equal_to(8);
-                assert_that!(extra).starts_with("3d6(");
-                assert_that!(extra).contains(") + 2d8(");
-                assert_that!(extra).contains(") + 1x20<");
-                assert_that!(extra).contains("> + 2");
-            },
-            _ => assert!(false, "Wrong decision type"),
+            Compare::Equal => (
+                if low == value { low + 1 } else { low },
+                if high == value { high - 1 } else { high },
+            ),
+            Compare::Less         => (low.max(value), high),
+            Compare::LessEqual    => (low.max(value.saturating_add(1)), high),
+            Compare::Greater      => (low, high.min(value)),
+            Compare::GreaterEqual => (low, high.min(value.saturating_sub(1))),
+        };
+        if low <= high { Some((low, high)) } else { None }
+    }
+}
+
+impl fmt::Display for Condition
+{
+    /// Show the condition as it is written in a dice expression, with the
+    /// comparison omitted for equality.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.compare
+        {
+            Compare::Equal => write!(f, "{}", self.value),
+            compare        => write!(f, "{}{}", compare, self.value),
+        }
+    }
+}
+
+impl fmt::Display for Compare
+{
+    /// Show the comparison as it is written in a dice expression.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let compare = match self
+        {
+            Compare::Equal        => "=",
+            Compare::Less         => "<",
+            Compare::LessEqual    => "<=",
+            Compare::Greater      => ">",
+            Compare::GreaterEqual => ">=",
+        };
+        write!(f, "{}", compare)
+    }
+}
+
+impl fmt::Display for Modifiers
+{
+    /// Show the modifiers as they are written in a dice expression.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(reroll) = self.reroll
+        {
+            write!(f, "{}{}", if reroll.once { "ro" } else { "r" }, reroll.condition)?;
         }
+        if let Some(minimum) = self.minimum
+        {
+            write!(f, "min{}", minimum)?;
+        }
+        // A comparison directly after a ! would be read as its condition, so
+        // the successes come first when the explode has no condition.
+        let bare_explode = matches!(self.explode, Some(Explode{ condition: None, .. }));
+        if bare_explode
+        {
+            self.write_successes(f)?;
+        }
+        if let Some(explode) = self.explode
+        {
+            let style = match explode.style
+            {
+                ExplodeStyle::Chained     => "!",
+                ExplodeStyle::Compounding => "!!",
+                ExplodeStyle::Penetrating => "!p",
+            };
+            write!(f, "{}", style)?;
+            if let Some(condition) = explode.condition
+            {
+                write!(f, "{}", condition)?;
+            }
+        }
+        match self.keep
+        {
+            Some(Keep::Highest(num))     => write!(f, "kh{}", num)?,
+            Some(Keep::Lowest(num))      => write!(f, "kl{}", num)?,
+            Some(Keep::DropHighest(num)) => write!(f, "dh{}", num)?,
+            Some(Keep::DropLowest(num))  => write!(f, "dl{}", num)?,
+            None                         => (),
+        }
+        if !bare_explode
+        {
+            self.write_successes(f)?;
+        }
+        if let Some(failure) = self.failures
+        {
+            write!(f, "f{}", failure)?;
+        }
+        Ok(())
+    }
+}
+
+impl Modifiers
+{
+    fn write_successes(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self.successes
+        {
+            Some(success) if success.compare == Compare::Equal => write!(f, "={}", success),
+            Some(success) => write!(f, "{}", success),
+            None          => Ok(()),
+        }
+    }
+}
+
+impl ParseError
+{
+    /// Return the expression with a line of carets beneath it marking where
+    /// the error was found:
+    ///
+    /// ```text
+    /// 3d7+?2
+    ///     ^
+    /// ```
+    pub fn render(&self) -> String
+    {
+        let indent = self.expr[..self.span.start].chars().count();
+        let width = self.expr[self.span.clone()].chars().count().max(1);
+        format!("{}\n{}{}", self.expr, " ".repeat(indent), "^".repeat(width))
+    }
+}
+
+impl fmt::Display for ParseError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.problem, &self.expr[self.span.clone()])
+        {
+            (ParseProblem::Expected(expected), "") if self.span.start == self.expr.len() =>
+                write!(f, "expected {} at the end of '{}'", expected, self.expr),
+            (ParseProblem::Expected(expected), "") =>
+                write!(f, "expected {} at byte {} of '{}'", expected, self.span.start, self.expr),
+            (ParseProblem::Expected(expected), found) =>
+                write!(f, "expected {} but found '{}' at byte {} of '{}'", expected, found, self.span.start, self.expr),
+            (ParseProblem::Invalid(error), _) =>
+                write!(f, "{} at byte {} of '{}'", error, self.span.start, self.expr),
+        }
+    }
+}
+
+impl CustomDie
+{
+    /// Construct a custom die with the name used to roll it and its faces.
+    /// The name must start with a letter, contain only letters, digits, and
+    /// underscores, and not be F, which rolls Fudge dice.
+    pub fn new(name: &str, faces: Vec<Face>) -> crate::Result<CustomDie>
+    {
+        let mut chars = name.chars();
+        let valid_name = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !name.eq_ignore_ascii_case("f");
+        if !valid_name || faces.is_empty()
+        {
+            return Err(Error::DiceBadCustom(name.to_owned()));
+        }
+        Ok(CustomDie{ name: name.to_owned(), faces })
+    }
+
+    pub fn name(&self) -> &str
+    {
+        &self.name
+    }
+
+    pub fn faces(&self) -> &[Face]
+    {
+        &self.faces
+    }
+}
+
+impl fmt::Display for CustomDie
+{
+    /// Show the die in the form parsed by `from_str()`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let faces: Vec<String> = self.faces.iter().map(Face::to_string).collect();
+        write!(f, "{}: {}", self.name, faces.join(", "))
+    }
+}
+
+impl FromStr for CustomDie
+{
+    type Err = Error;
+
+    /// Parse a custom die from its name, a colon, and its faces separated by
+    /// commas: `Boost: -, -, success, success advantage, advantage advantage, advantage`.
+    fn from_str(text: &str) -> crate::Result<CustomDie>
+    {
+        let (name, faces) = text.split_once(':').ok_or_else(|| Error::DiceBadCustom(text.trim().to_owned()))?;
+        let faces = faces.split(',').map(str::parse).collect::<crate::Result<Vec<Face>>>()?;
+        CustomDie::new(name.trim(), faces)
+    }
+}
+
+impl fmt::Display for Face
+{
+    /// Show any value followed by the symbols, or - for a blank face.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = self.symbols.clone();
+        if self.value != 0
+        {
+            parts.insert(0, self.value.to_string());
+        }
+        match parts.is_empty()
+        {
+            true  => write!(f, "-"),
+            false => write!(f, "{}", parts.join(" ")),
+        }
+    }
+}
+
+impl FromStr for Face
+{
+    type Err = Error;
+
+    /// Parse a face from words separated by whitespace. Numbers are added to
+    /// the value of the face, other words are its symbols, and - or nothing
+    /// at all is a blank face.
+    fn from_str(text: &str) -> crate::Result<Face>
+    {
+        let mut face = Face::default();
+        for word in text.split_whitespace().filter(|&word| word != "-")
+        {
+            match word.parse::<i64>()
+            {
+                Ok(value) => face.value = face.value.saturating_add(value),
+                Err(_)    => face.symbols.push(word.to_owned()),
+            }
+        }
+        Ok(face)
+    }
+}
+
+impl fmt::Display for Roll
+{
+    /// Show the term as it is written in a dice expression.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self
+        {
+            Roll::Dice(num, sides)          => write!(f, "{}d{}", num, sides),
+            Roll::ExplodingDice(num, sides) => write!(f, "{}x{}", num, sides),
+            Roll::Fudge(num)                => write!(f, "{}dF", num),
+            Roll::Incr(num)                 => write!(f, "{}", num),
+            Roll::Modified(base, mods)      => write!(f, "{}{}", base, mods),
+            Roll::Custom(num, die)          => write!(f, "{}d{}", num, die.name),
+        }
+    }
+}
+
+impl FromStr for Roll
+{
+    type Err = Error;
+
+    /// Parse a single term of a dice expression.
+    fn from_str(text: &str) -> crate::Result<Roll>
+    {
+        match text.parse::<Expr>()?
+        {
+            Expr::Roll(roll) => Ok(roll),
+            _                => Err(Error::DiceBadExpr(text.trim().to_owned())),
+        }
+    }
+}
+
+/// Divide num by denom, rounding as requested. Dividing by zero gives zero.
+fn divide(num: i64, denom: i64, rounding: Rounding) -> i64
+{
+    // Only dividing i64::MIN by -1 overflows, giving i64::MAX + 1.
+    checked_divide(num, denom, rounding).unwrap_or(i64::MAX)
+}
+
+/// Divide num by denom as for `divide()`, or return None if the result
+/// overflows.
+fn checked_divide(num: i64, denom: i64, rounding: Rounding) -> Option<i64>
+{
+    if denom == 0 { return Some(0); }
+
+    // Widened so that negating or doubling the numerator can't overflow.
+    let (num, denom) = match denom < 0
+    {
+        true  => (-i128::from(num), -i128::from(denom)),
+        false => (i128::from(num), i128::from(denom)),
+    };
+    let quotient = match rounding
+    {
+        Rounding::Down    => num.div_euclid(denom),
+        Rounding::Up      => -(-num).div_euclid(denom),
+        Rounding::Nearest => (2 * num + denom).div_euclid(2 * denom),
+    };
+    i64::try_from(quotient).ok()
+}
+
+impl Die
+{
+    /// Construct a die that rolled the supplied faces and was not dropped.
+    pub fn new(faces: Vec<i64>) -> Die
+    {
+        Die{ faces, rerolled: Vec::new(), dropped: false, symbols: Vec::new() }
+    }
+
+    /// Return the sum of all of the faces rolled for this die.
+    pub fn total(&self) -> i64
+    {
+        self.faces.iter().sum()
+    }
+
+    /// Return true if the die was re-rolled because it exploded.
+    pub fn exploded(&self) -> bool
+    {
+        self.faces.len() > 1
+    }
+}
+
+impl RolledTerm
+{
+    /// Return true if the term counts successes and failures, and rolled no
+    /// successes and at least one failure.
+    pub fn is_botch(&self) -> bool
+    {
+        let (success, failure) = match &self.term
+        {
+            Roll::Modified(_, Modifiers{ successes: Some(success), failures: Some(failure), .. }) =>
+                (success, failure),
+            _ => return false,
+        };
+        let kept: Vec<i64> = self.dice.iter().filter(|d| !d.dropped).map(Die::total).collect();
+        !kept.iter().any(|&v| success.matches(v)) && kept.iter().any(|&v| failure.matches(v))
+    }
+}
+
+impl Rolled
+{
+    /// Return true if any of the terms is a botch.
+    pub fn is_botch(&self) -> bool
+    {
+        self.terms.iter().any(RolledTerm::is_botch)
+    }
+
+    /// Return the number of times each symbol appears on the kept custom
+    /// dice, such as `{"advantage": 2, "success": 1}`.
+    pub fn tally(&self) -> BTreeMap<String, u32>
+    {
+        let mut tally = BTreeMap::new();
+        let kept = self.terms.iter().flat_map(|term| &term.dice).filter(|d| !d.dropped);
+        for symbol in kept.flat_map(|d| &d.symbols)
+        {
+            *tally.entry(symbol.clone()).or_insert(0) += 1;
+        }
+        tally
+    }
+}
+
+impl fmt::Display for Die
+{
+    /// Show the faces joined by +, marking each face that exploded with !.
+    /// Any rolls discarded by a reroll come first, each followed by r.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for face in &self.rerolled
+        {
+            write!(f, "{}r", face)?;
+        }
+        let faces: Vec<String> = self.faces.iter().map(|face| face.to_string()).collect();
+        write!(f, "{}", faces.join("!+"))
+    }
+}
+
+/// Show a Fudge die with the sign of each roll.
+fn fudge_die(die: &Die) -> String
+{
+    let rerolled: Vec<String> = die.rerolled.iter().map(|face| format!("{:+}r", face)).collect();
+    format!("{}{:+}", rerolled.concat(), die.total())
+}
+
+impl fmt::Display for RolledTerm
+{
+    /// Show the term followed by the dice rolled, marking dropped dice with ~
+    /// and a botched dice pool with `[botch]`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let base = match &self.term
+        {
+            Roll::Modified(base, _) => &**base,
+            term                    => term,
+        };
+        let dice = |show: &dyn Fn(&Die) -> String| -> Vec<String> {
+            self.dice.iter()
+                .map(|d| if d.dropped { format!("~{}~", show(d)) } else { show(d) })
+                .collect()
+        };
+        let compounding = matches!(&self.term,
+            Roll::Modified(_, Modifiers{ explode: Some(Explode{ style: ExplodeStyle::Compounding, .. }), .. }));
+        let show_die = |d: &Die| match d.exploded()
+        {
+            false                => d.to_string(),
+            true if compounding  => format!("{}!!", d.total()),
+            true                 => format!("({})", d),
+        };
+        match base
+        {
+            Roll::Dice(..) => write!(f, "{}({})", self.term, dice(&show_die).join("+")),
+            Roll::ExplodingDice(..) =>
+                write!(f, "{}<{}>", self.term, dice(&|d| format!("({})", d)).join(" + ")),
+            Roll::Fudge(_) => write!(f, "{}({})", self.term, dice(&fudge_die).concat()),
+            Roll::Custom(..) => {
+                let face = |d: &Die| Face{ value: d.total(), symbols: d.symbols.clone() }.to_string();
+                write!(f, "{}({})", self.term, dice(&face).join(", "))
+            },
+            Roll::Incr(_) | Roll::Modified(..) => write!(f, "{}", self.term),
+        }?;
+        if self.is_botch()
+        {
+            write!(f, "[botch]")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Rolled
+{
+    /// Show the expression with each term replaced by the dice rolled for it,
+    /// followed by the tally of any symbols rolled.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut terms = self.terms.iter();
+        self.expr.write_with(f, &mut |f, _| match terms.next()
+        {
+            Some(term) => write!(f, "{}", term),
+            None       => Err(fmt::Error),
+        })?;
+        let tally: Vec<String> = self.tally().iter()
+            .map(|(symbol, count)| format!("{}: {}", symbol, count))
+            .collect();
+        if !tally.is_empty()
+        {
+            write!(f, " [{}]", tally.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Expr
+{
+    /// Show the expression as it is written, with each term in lowercase and
+    /// only the parens needed to preserve its structure. Parsing the result
+    /// gives the same expression, unless it has custom dice or an explosion
+    /// limit, which come from the caller rather than the text.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_with(f, &mut |f, roll| write!(f, "{}", roll))
+    }
+}
+
+impl FromStr for Expr
+{
+    type Err = Error;
+
+    /// Parse a single dice expression using the default limits.
+    fn from_str(text: &str) -> crate::Result<Expr>
+    {
+        if text.trim().is_empty()
+        {
+            return Err(Error::DiceMissingExpr);
+        }
+        parser::parse(text, &Limits::default())
+    }
+}
+
+type TermWriter<'a> = dyn FnMut(&mut fmt::Formatter<'_>, &Roll) -> fmt::Result + 'a;
+
+impl Expr
+{
+    /// Construct an expression adding all of the terms together.
+    pub fn sum<I>(terms: I) -> Expr
+        where I: IntoIterator<Item=Roll>
+    {
+        terms.into_iter()
+            .map(Expr::Roll)
+            .fold(None, |acc, term| Some(match acc
+            {
+                None       => term,
+                Some(expr) => Expr::Add(Box::new(expr), Box::new(term)),
+            }))
+            .unwrap_or(Expr::Roll(Roll::Incr(0)))
+    }
+
+    /// Return an expression with the same distribution of totals in a
+    /// canonical form. Like terms of each sum are merged (`d6 + 2d6` becomes
+    /// `3d6`) and sorted, with dice from most to fewest sides, then any other
+    /// terms, and finally a single constant.
+    pub fn canonical(&self) -> Expr
+    {
+        match self
+        {
+            Expr::Roll(roll) => Expr::Roll(roll.clone()),
+            Expr::Neg(_) | Expr::Add(..) | Expr::Sub(..) => canonical_sum(self),
+            Expr::Mul(lhs, rhs) => Expr::Mul(Box::new(lhs.canonical()), Box::new(rhs.canonical())),
+            Expr::Div(lhs, rhs, rounding) =>
+                Expr::Div(Box::new(lhs.canonical()), Box::new(rhs.canonical()), *rounding),
+        }
+    }
+
+    /// Roll the dice described by the expression, returning the structured
+    /// result with each die rolled.
+    pub fn roll_with(&self, rng: &mut dyn RngCore) -> Rolled
+    {
+        let mut terms = Vec::new();
+        let total = self.eval(rng, &mut terms);
+        Rolled{ expr: self.clone(), terms, total }
+    }
+
+    /// Return the terms of the expression in order.
+    fn terms(&self) -> Vec<&Roll>
+    {
+        match self
+        {
+            Expr::Roll(roll) => vec![roll],
+            Expr::Neg(expr)  => expr.terms(),
+            Expr::Add(lhs, rhs) | Expr::Sub(lhs, rhs) | Expr::Mul(lhs, rhs) | Expr::Div(lhs, rhs, _) => {
+                let mut terms = lhs.terms();
+                terms.extend(rhs.terms());
+                terms
+            },
+        }
+    }
+
+    /// Return the number of dice rolled by all of the terms.
+    fn total_dice(&self) -> u64
+    {
+        self.terms().into_iter().map(|term| u64::from(dice_count(term))).sum()
+    }
+
+    /// Roll each term in order, collecting the results, and return the total.
+    /// The arithmetic saturates instead of overflowing, though `command()`
+    /// rejects any expression whose total could overflow.
+    fn eval(&self, rng: &mut dyn RngCore, terms: &mut Vec<RolledTerm>) -> i64
+    {
+        match self
+        {
+            Expr::Roll(roll) => {
+                let term = roll_term(rng, roll);
+                let subtotal = term.subtotal;
+                terms.push(term);
+                subtotal
+            },
+            Expr::Neg(expr)   => expr.eval(rng, terms).saturating_neg(),
+            Expr::Add(lhs, rhs) => lhs.eval(rng, terms).saturating_add(rhs.eval(rng, terms)),
+            Expr::Sub(lhs, rhs) => lhs.eval(rng, terms).saturating_sub(rhs.eval(rng, terms)),
+            Expr::Mul(lhs, rhs) => lhs.eval(rng, terms).saturating_mul(rhs.eval(rng, terms)),
+            Expr::Div(lhs, rhs, rounding) => {
+                let num = lhs.eval(rng, terms);
+                divide(num, rhs.eval(rng, terms), *rounding)
+            },
+        }
+    }
+
+    /// Return the precedence of the top operation in the expression, where
+    /// higher numbers bind more tightly.
+    fn precedence(&self) -> u8
+    {
+        match self
+        {
+            Expr::Add(..) | Expr::Sub(..) => 1,
+            Expr::Mul(..) | Expr::Div(..) => 2,
+            Expr::Neg(_)                  => 3,
+            Expr::Roll(_)                 => 4,
+        }
+    }
+
+    /// Write the expression, using the supplied function to write each term
+    /// and adding parens where needed to preserve the structure.
+    fn write_with(&self, f: &mut fmt::Formatter<'_>, term: &mut TermWriter<'_>) -> fmt::Result
+    {
+        let prec = self.precedence();
+        let child = |f: &mut fmt::Formatter<'_>, expr: &Expr, min: u8, term: &mut TermWriter<'_>| {
+            if expr.precedence() < min
+            {
+                write!(f, "(")?;
+                expr.write_with(f, term)?;
+                write!(f, ")")
+            }
+            else
+            {
+                expr.write_with(f, term)
+            }
+        };
+        let (lhs, op, rhs) = match self
+        {
+            Expr::Roll(roll) => return term(f, roll),
+            Expr::Neg(expr)  => {
+                write!(f, "-")?;
+                return child(f, expr, prec, term);
+            },
+            Expr::Add(lhs, rhs) => (lhs, "+", rhs),
+            Expr::Sub(lhs, rhs) => (lhs, "-", rhs),
+            Expr::Mul(lhs, rhs) => (lhs, "*", rhs),
+            Expr::Div(lhs, rhs, Rounding::Down)    => (lhs, "/", rhs),
+            Expr::Div(lhs, rhs, Rounding::Up)      => (lhs, "/^", rhs),
+            Expr::Div(lhs, rhs, Rounding::Nearest) => (lhs, "/~", rhs),
+        };
+        child(f, lhs, prec, term)?;
+        write!(f, " {} ", op)?;
+        child(f, rhs, prec + 1, term)
+    }
+
+}
+
+/// Return the canonical form of a sum, see `Expr::canonical()`.
+fn canonical_sum(expr: &Expr) -> Expr
+{
+    let mut summands = Vec::new();
+    sum_terms(expr, false, &mut summands);
+
+    let mut constant: i64 = 0;
+    let mut terms: Vec<(bool, Expr)> = Vec::new();
+    for (negated, term) in summands
+    {
+        match term
+        {
+            Expr::Roll(Roll::Incr(num)) =>
+                constant += if negated { -i64::from(num) } else { i64::from(num) },
+            Expr::Roll(roll) => {
+                let merged = terms.iter_mut()
+                    .filter(|(neg, _)| *neg == negated)
+                    .find_map(|(_, term)| match term
+                    {
+                        Expr::Roll(other) => merge_rolls(other, &roll).map(|sum| *other = sum),
+                        _ => None,
+                    });
+                if merged.is_none()
+                {
+                    terms.push((negated, Expr::Roll(roll)));
+                }
+            },
+            term => terms.push((negated, term)),
+        }
+    }
+    terms.sort_by_cached_key(|(negated, term)| (*negated, term_order(term)));
+
+    // A constant too large for a single increment is split into several.
+    let mut remaining = constant.unsigned_abs();
+    while remaining > 0 || terms.is_empty()
+    {
+        let num = remaining.min(u64::from(u32::MAX));
+        terms.push((constant < 0, Expr::Roll(Roll::Incr(num as u32))));
+        remaining -= num;
+    }
+
+    let mut terms = terms.into_iter();
+    let first = match terms.next()
+    {
+        Some((true, term))  => Expr::Neg(Box::new(term)),
+        Some((false, term)) => term,
+        None                => unreachable!("a sum always has a term"),
+    };
+    terms.fold(first, |acc, (negated, term)| match negated
+    {
+        true  => Expr::Sub(Box::new(acc), Box::new(term)),
+        false => Expr::Add(Box::new(acc), Box::new(term)),
+    })
+}
+
+/// Collect the canonical terms of a sum, each marked with whether it is
+/// subtracted.
+fn sum_terms(expr: &Expr, negated: bool, terms: &mut Vec<(bool, Expr)>)
+{
+    match expr
+    {
+        Expr::Neg(expr) => sum_terms(expr, !negated, terms),
+        Expr::Add(lhs, rhs) => {
+            sum_terms(lhs, negated, terms);
+            sum_terms(rhs, negated, terms);
+        },
+        Expr::Sub(lhs, rhs) => {
+            sum_terms(lhs, negated, terms);
+            sum_terms(rhs, !negated, terms);
+        },
+        term => terms.push((negated, term.canonical())),
+    }
+}
+
+/// Return a single term rolling the dice of both terms, if they are the same
+/// kind of unmodified dice.
+fn merge_rolls(a: &Roll, b: &Roll) -> Option<Roll>
+{
+    match (a, b)
+    {
+        (Roll::Dice(anum, asides), Roll::Dice(bnum, bsides)) if asides == bsides =>
+            Some(Roll::Dice(anum.checked_add(*bnum)?, *asides)),
+        (Roll::ExplodingDice(anum, asides), Roll::ExplodingDice(bnum, bsides)) if asides == bsides =>
+            Some(Roll::ExplodingDice(anum.checked_add(*bnum)?, *asides)),
+        (Roll::Fudge(anum), Roll::Fudge(bnum)) => Some(Roll::Fudge(anum.checked_add(*bnum)?)),
+        (Roll::Custom(anum, adie), Roll::Custom(bnum, bdie)) if adie == bdie =>
+            Some(Roll::Custom(anum.checked_add(*bnum)?, adie.clone())),
+        _ => None,
+    }
+}
+
+/// Return the key ordering the terms of a canonical sum.
+fn term_order(term: &Expr) -> (u8, Reverse<i64>, String)
+{
+    let rank = match term
+    {
+        Expr::Roll(Roll::Dice(..))          => 0,
+        Expr::Roll(Roll::ExplodingDice(..)) => 1,
+        Expr::Roll(Roll::Fudge(_))          => 2,
+        Expr::Roll(Roll::Custom(..))        => 3,
+        Expr::Roll(Roll::Modified(..))      => 4,
+        _                                   => 5,
+    };
+    let sides = match term
+    {
+        Expr::Roll(roll) => face_bounds(roll).1,
+        _                => 0,
+    };
+    (rank, Reverse(sides), term.to_string())
+}
+
+/// Return the lowest and highest roll of a single face of the term's dice.
+pub(super) fn face_bounds(term: &Roll) -> (i64, i64)
+{
+    match term
+    {
+        Roll::Dice(_, sides) | Roll::ExplodingDice(_, sides) => (1, i64::from(*sides)),
+        Roll::Fudge(_)          => (-1, 1),
+        Roll::Incr(num)         => (i64::from(*num), i64::from(*num)),
+        Roll::Modified(base, _) => face_bounds(base),
+        Roll::Custom(_, die)    => {
+            let values = die.faces.iter().map(|face| face.value);
+            (values.clone().min().unwrap_or(0), values.max().unwrap_or(0))
+        },
+    }
+}
+
+/// Return the number of dice rolled for the term.
+fn dice_count(term: &Roll) -> u32
+{
+    match term
+    {
+        Roll::Dice(num, _) | Roll::ExplodingDice(num, _) | Roll::Fudge(num) | Roll::Custom(num, _) => *num,
+        Roll::Incr(_)           => 0,
+        Roll::Modified(base, _) => dice_count(base),
+    }
+}
+
+impl Decider for Expr {
+    /// Perform the random function and return a Decision object representing
+    /// the result.
+    fn decide_with(&self, rng: &mut dyn RngCore) -> Decision
+    {
+        let rolled = self.roll_with(rng);
+        Decision::AnnotatedNum{ value: rolled.total, extra: rolled.to_string() }
+    }
+
+    /// Return the distribution of totals, limiting exploding dice to
+    /// DEFAULT_EXPLODE_DEPTH explosions, as for `distribution_to_depth()`. A
+    /// die that would explode further is given the lowest total it could
+    /// reach, so the probability of each total from the lowest such total up
+    /// is approximate, though their combined probability is exact where the
+    /// accuracy of the distribution says so.
+    fn distribution(&self) -> Option<Distribution>
+    {
+        self.distribution_to_depth(DEFAULT_EXPLODE_DEPTH)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use spectral::prelude::*;
+    use rand::rngs::mock::StepRng;
+
+    use crate::Decision;
+    use crate::DecisionAssertions;
+    use crate::Decider;
+    use crate::Command;
+    use super::*;
+    use crate::distribution::Outcome;
+    use crate::fairness;
+    use crate::rng;
+
+    #[test]
+    fn command_empty_string()
+    {
+        assert_that!(command(String::new()))
+            .is_err_containing(Error::DiceMissingExpr);
+    }
+
+    #[test]
+    fn command_simple_roll()
+    {
+        assert_that!(command("3d8".into()))
+            .is_ok_containing(Command::RollDice(Expr::sum(vec![Roll::Dice(3, 8)])))
+    }
+
+    #[test]
+    fn command_all_sides()
+    {
+        for i in vec![3,4,6,8,10,12,20,100]
+        {
+            assert_that!(command(format!("1d{}", i)))
+                .is_ok_containing(Command::RollDice(Expr::sum(vec![Roll::Dice(1, i)])))
+        }
+    }
+
+    #[test]
+    fn command_exploding_roll()
+    {
+        assert_that!(command("3x6".into()))
+            .is_ok_containing(Command::RollDice(Expr::sum(vec![Roll::ExplodingDice(3, 6)])))
+    }
+
+    #[test]
+    fn command_multiterm_expresion()
+    {
+        assert_that!(command("2d12 + 3x6 + 2".into()))
+            .is_ok_containing(Command::RollDice(Expr::sum(vec![Roll::Dice(2, 12), Roll::ExplodingDice(3, 6), Roll::Incr(2)])))
+    }
+
+    #[test]
+    fn dice_roll_decision()
+    {
+        assert_that!(command("2d12 + 3x6 + 2".into()).unwrap().decide())
+            .is_annotated_num_decision();
+    }
+
+    #[test]
+    fn simple_roll_value()
+    {
+        match Expr::sum(vec![Roll::Dice(1, 6)]).decide()
+        {
+            Decision::AnnotatedNum{value, extra} => {
+                assert_that!(&value).is_greater_than_or_equal_to(1);
+                assert_that!(&value).is_less_than_or_equal_to(6);
+                assert_that!(extra).starts_with("1d6(");
+                assert_that!(extra).ends_with(")");
+            },
+            _ => assert!(false, "Wrong decision type"),
+        }
+    }
+
+    #[test]
+    fn explode_roll_value()
+    {
+        match Expr::sum(vec![Roll::ExplodingDice(1, 6)]).decide()
+        {
+            Decision::AnnotatedNum{value, extra} => {
+                assert_that!(&value).is_greater_than_or_equal_to(1);
+                assert_that!(value % 6).is_not_equal_to(0);
+                assert_that!(extra).starts_with("1x6<");
+                assert_that!(extra).ends_with(">");
+            },
+            _ => assert!(false, "Wrong decision type"),
+        }
+    }
+
+    #[test]
+    fn incr_value()
+    {
+        match Expr::sum(vec![Roll::Incr(1)]).decide()
+        {
+            Decision::AnnotatedNum{value, extra} => {
+                assert_that!(value).is_equal_to(1);
+                assert_that!(extra).is_equal_to("1".to_string());
+            },
+            _ => assert!(false, "Wrong decision type"),
+        }
+    }
+
+    #[test]
+    fn multi_roll_value()
+    {
+        match Expr::sum(vec![Roll::Dice(3, 6)]).decide()
+        {
+            Decision::AnnotatedNum{value, extra} => {
+                assert_that!(&value).is_greater_than_or_equal_to(3);
+                assert_that!(&value).is_less_than_or_equal_to(18);
+                assert_that!(extra).starts_with("3d6(");
+                assert_that!(extra).ends_with(")");
+            },
+            _ => assert!(false, "Wrong decision type"),
+        }
+    }
+
+    #[test]
+    fn multi_exploding_roll_value()
+    {
+        match Expr::sum(vec![Roll::ExplodingDice(3, 6)]).decide()
+        {
+            Decision::AnnotatedNum{value, extra} => {
+                assert_that!(&value).is_greater_than_or_equal_to(3);
+                assert_that!(extra).starts_with("3x6<");
+                assert_that!(extra).ends_with(">");
+            },
+            _ => assert!(false, "Wrong decision type"),
+        }
+    }
+
+    #[test]
+    fn complex_roll_value()
+    {
+        match Expr::sum(vec![Roll::Dice(3, 6), Roll::Dice(2, 8), Roll::ExplodingDice(1, 20), Roll::Incr(2)]).decide()
+        {
+            Decision::AnnotatedNum{value, extra} => {
+                assert_that!(&value).is_greater_than_or_equal_to(8);
+                assert_that!(extra).starts_with("3d6(");
+                assert_that!(extra).contains(") + 2d8(");
+                assert_that!(extra).contains(") + 1x20<");
+                assert_that!(extra).contains("> + 2");
+            },
+            _ => assert!(false, "Wrong decision type"),
+        }
+    }
+
+    #[test]
+    fn decide_with_rng()
+    {
+        let mut rng = StepRng::new(0, 0);
+        match Expr::sum(vec![Roll::Dice(3, 6), Roll::Incr(2)]).decide_with(&mut rng)
+        {
+            Decision::AnnotatedNum{value, extra} => {
+                assert_that!(value).is_equal_to(5);
+                assert_that!(extra).is_equal_to("3d6(1+1+1) + 2".to_string());
+            },
+            _ => panic!("Wrong decision type"),
+        }
+    }
+
+    #[test]
+    fn structured_roll()
+    {
+        let rolled = Expr::sum(vec![Roll::Dice(3, 6), Roll::Incr(2)]).roll_with(&mut StepRng::new(0, 0));
+        assert_that!(rolled.total).is_equal_to(5);
+        assert_that!(rolled.terms).has_length(2);
+        assert_that!(rolled.terms[0].subtotal).is_equal_to(3);
+        assert_that!(rolled.terms[0].dice.clone()).is_equal_to(vec![Die::new(vec![1]); 3]);
+        assert_that!(rolled.terms[1].subtotal).is_equal_to(2);
+        assert_that!(rolled.terms[1].dice.clone()).is_empty();
+        assert_that!(rolled.to_string()).is_equal_to("3d6(1+1+1) + 2".to_string());
+    }
+
+    #[test]
+    fn structured_exploding_roll()
+    {
+        // The first value rolls a 6 on a d6, the second rolls a 1.
+        let mut rng = StepRng::new(0xD555_5556, 0x2AAA_AAAA);
+        let rolled = Expr::sum(vec![Roll::ExplodingDice(1, 6), Roll::Incr(1)]).roll_with(&mut rng);
+        let die = &rolled.terms[0].dice[0];
+        assert_that!(die.faces.clone()).is_equal_to(vec![6, 1]);
+        assert_that!(die.exploded()).is_true();
+        assert_that!(die.total()).is_equal_to(7);
+        assert_that!(rolled.terms[0].subtotal).is_equal_to(7);
+        assert_that!(rolled.total).is_equal_to(8);
+        assert_that!(rolled.to_string()).is_equal_to("1x6<(6!+1)> + 1".to_string());
+    }
+
+    fn roll_min(expr: &str) -> Rolled
+    {
+        match command(expr.into()).unwrap()
+        {
+            Command::RollDice(expr) => expr.roll_with(&mut StepRng::new(0, 0)),
+            _ => panic!("Wrong command type"),
+        }
+    }
+
+    fn parse_expr(text: &str) -> Expr
+    {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn command_arithmetic()
+    {
+        assert_that!(command("2d6-1".into())).is_ok_containing(Command::RollDice(
+            Expr::Sub(Box::new(Expr::Roll(Roll::Dice(2, 6))), Box::new(Expr::Roll(Roll::Incr(1))))
+        ));
+        assert_that!(command("(1d4+1)*2".into())).is_ok();
+        assert_that!(command("d20 - 2".into())).is_ok();
+        assert_that!(command("2d6 ** 2".into())).is_err_containing(Error::UnparseableTerm(ParseError{
+            expr: "2d6 ** 2".into(), span: 5..6, problem: ParseProblem::Expected("a number, dice, or '('"),
+        }));
+    }
+
+    #[test]
+    fn command_zero_divisor()
+    {
+        assert_that!(command("d6 / (d4 - 2)".into())).is_err_containing(Error::UnparseableTerm(ParseError{
+            expr: "d6 / (d4 - 2)".into(), span: 5..13,
+            problem: ParseProblem::Invalid(Box::new(Error::DiceDivideByZero("(d4 - 2)".into()))),
+        }));
+    }
+
+    #[test]
+    fn negative_total()
+    {
+        let rolled = roll_min("d4 - 5");
+        assert_that!(rolled.total).is_equal_to(-4);
+        assert_that!(rolled.to_string()).is_equal_to("1d4(1) - 5".to_string());
+        assert_that!(roll_min("-(2d6 + 3)").total).is_equal_to(-5);
+    }
+
+    #[test]
+    fn grouped_description()
+    {
+        let rolled = roll_min("(1d4+1)*2 - 3/-(1+1)");
+        assert_that!(rolled.total).is_equal_to(6);
+        assert_that!(rolled.terms).has_length(6);
+        assert_that!(rolled.to_string()).is_equal_to("(1d4(1) + 1) * 2 - 3 / -(1 + 1)".to_string());
+    }
+
+    #[test]
+    fn division_rounding()
+    {
+        assert_that!(divide(7, 2, Rounding::Down)).is_equal_to(3);
+        assert_that!(divide(7, 2, Rounding::Up)).is_equal_to(4);
+        assert_that!(divide(7, 2, Rounding::Nearest)).is_equal_to(4);
+        assert_that!(divide(-7, 2, Rounding::Down)).is_equal_to(-4);
+        assert_that!(divide(-7, 2, Rounding::Up)).is_equal_to(-3);
+        assert_that!(divide(-7, 2, Rounding::Nearest)).is_equal_to(-3);
+        assert_that!(divide(7, -2, Rounding::Down)).is_equal_to(-4);
+        assert_that!(divide(5, 3, Rounding::Nearest)).is_equal_to(2);
+        assert_that!(divide(5, 0, Rounding::Down)).is_equal_to(0);
+        assert_that!(roll_min("d6 /^ 2").total).is_equal_to(1);
+    }
+
+    #[test]
+    fn expression_bounds()
+    {
+        let bounds = |expr: &str| match command(expr.into()).unwrap()
+        {
+            Command::RollDice(expr) => expr.bounds(),
+            _ => panic!("Wrong command type"),
+        };
+        assert_that!(bounds("2d6 - 1")).is_equal_to((1, 11));
+        assert_that!(bounds("d4 * -d6")).is_equal_to((-24, -1));
+        assert_that!(bounds("d20 / 2")).is_equal_to((0, 10));
+        assert_that!(bounds("1 - x6").0).is_equal_to(1 - i64::MAX);
+    }
+
+    #[test]
+    fn expression_stats()
+    {
+        let stats = |text: &str| {
+            let expr = parse_expr(text);
+            (expr.min(), expr.max(), expr.mean().unwrap(), expr.variance().unwrap())
+        };
+        let (min, max, mean, variance) = stats("2d6 - 1");
+        assert_that!((min, max)).is_equal_to((Some(1), Some(11)));
+        assert_that!(mean).is_close_to(6.0, 1e-12);
+        assert_that!(variance).is_close_to(35.0 / 6.0, 1e-12);
+
+        let (min, max, mean, variance) = stats("d4 * -d6");
+        assert_that!((min, max)).is_equal_to((Some(-24), Some(-1)));
+        assert_that!(mean).is_close_to(-8.75, 1e-12);
+        assert_that!(variance).is_close_to(7.5 * 91.0 / 6.0 - 8.75 * 8.75, 1e-12);
+
+        let (_, _, mean, variance) = stats("4dF + 3");
+        assert_that!(mean).is_close_to(3.0, 1e-12);
+        assert_that!(variance).is_close_to(8.0 / 3.0, 1e-12);
+
+        assert_that!(stats("d20 / 2").2).is_close_to(5.0, 1e-12);
+        // Each exploding die explodes at most MAX_EXPLOSIONS times.
+        assert_that!(parse_expr("x6").max()).is_equal_to(Some(606));
+        assert_that!(parse_expr("1 - x6").min()).is_equal_to(Some(-605));
+        assert_that!(parse_expr("1 - x6").max()).is_equal_to(Some(0));
+        assert_that!(parse_expr("d6 * (2 - d6!)").min()).is_equal_to(Some(-3624));
+        assert_that!(parse_expr("d6 * (2 - d6!)").max()).is_equal_to(Some(6));
+        assert_that!(parse_expr("d6! / 2").min()).is_equal_to(Some(0));
+        assert_that!(parse_expr("d6!p").max()).is_equal_to(Some(506));
+        let limits = Limits{ max_explosions: Some(2), ..Limits::default() };
+        assert_that!(parser::parse("2d6!", &limits).unwrap().max()).is_equal_to(Some(36));
+        let big = || Box::new(Expr::Roll(Roll::Dice(10_000, 1_000_000)));
+        let huge = Expr::Mul(big(), big());
+        assert_that!(huge.min()).is_none();
+        assert_that!(parse_expr("d6!").mean().unwrap()).is_close_to(4.2, 1e-3);
+        assert_that!(parse_expr("1000d1000000").mean()).is_equal_to(Some(500_000_500.0));
+    }
+
+    #[test]
+    fn target_probability()
+    {
+        assert_that!(parse_expr("d20+4").probability_at_least(15).unwrap()).is_close_to(0.5, 1e-12);
+        assert_that!(parse_expr("2d20kh1+4").probability_at_least(15).unwrap()).is_close_to(0.75, 1e-12);
+        assert_that!(parse_expr("2d20kl1+4").probability_at_least(15).unwrap()).is_close_to(0.25, 1e-12);
+        assert_that!(parse_expr("3d6").probability_at_most(3).unwrap()).is_close_to(1.0 / 216.0, 1e-12);
+        assert_that!(parse_expr("3d6").probability_at_most(18).unwrap()).is_close_to(1.0, 1e-12);
+        assert_that!(parse_expr("2d6").probability_exactly(7).unwrap()).is_close_to(1.0 / 6.0, 1e-12);
+        assert_that!(parse_expr("d6!").probability_exactly(6).unwrap()).is_equal_to(0.0);
+        assert_that!(parse_expr("d6!").probability_exactly(7).unwrap()).is_close_to(1.0 / 36.0, 1e-12);
+        assert_that!(parse_expr("d6 - 2").probability_at_least(5).unwrap()).is_equal_to(0.0);
+        assert_that!(parse_expr("1000d1000000").probability_at_least(1)).is_none();
+    }
+
+    #[test]
+    fn modified_stats()
+    {
+        for text in ["4d6kh3", "10d10>=8f1", "3d6!!", "2d6r1 + d4min2", "4d6kh3 * 2"]
+        {
+            let expr = parse_expr(text);
+            let dist = expr.distribution().unwrap();
+            let mean: f64 = dist.outcomes().iter()
+                .map(|(outcome, p)| outcome.as_num().unwrap() as f64 * p)
+                .sum();
+            let variance: f64 = dist.outcomes().iter()
+                .map(|(outcome, p)| (outcome.as_num().unwrap() as f64 - mean).powi(2) * p)
+                .sum();
+            assert_that!(expr.mean().unwrap()).is_close_to(mean, 1e-9);
+            assert_that!(expr.variance().unwrap()).is_close_to(variance, 1e-9);
+        }
+        assert_that!(parse_expr("4d6kh3").mean().unwrap()).is_close_to(15869.0 / 1296.0, 1e-12);
+        assert_that!(parse_expr("10d10>=8").variance().unwrap()).is_close_to(2.1, 1e-12);
+        assert_that!(parse_expr("10d10>=8").max()).is_equal_to(Some(10));
+    }
+
+    #[test]
+    fn arithmetic_distribution()
+    {
+        let dist = command("d4 * 2 - 1".into()).unwrap().distribution().unwrap();
+        let totals: Vec<Outcome> = dist.outcomes().iter().map(|(o, _)| o.clone()).collect();
+        assert_that!(totals).is_equal_to(vec![Outcome::Num(1), Outcome::Num(3), Outcome::Num(5), Outcome::Num(7)]);
+
+        let dist = command("d6 / 2".into()).unwrap().distribution().unwrap();
+        assert_that!(dist.probability(&Outcome::Num(0))).is_close_to(1.0 / 6.0, 1e-12);
+        assert_that!(dist.probability(&Outcome::Num(1))).is_close_to(2.0 / 6.0, 1e-12);
+        assert_that!(dist.probability(&Outcome::Num(3))).is_close_to(1.0 / 6.0, 1e-12);
+    }
+
+    #[test]
+    fn display_expressions()
+    {
+        let display = |text: &str| text.parse::<Expr>().unwrap().to_string();
+        assert_that!(display("D6 + 2D6")).is_equal_to("1d6 + 2d6".to_string());
+        assert_that!(display("(1d4+1)*2 - 2d6")).is_equal_to("(1d4 + 1) * 2 - 2d6".to_string());
+        assert_that!(display("d20 - (2 - d4)")).is_equal_to("1d20 - (2 - 1d4)".to_string());
+        assert_that!(display("-d4 * -2")).is_equal_to("-1d4 * -2".to_string());
+        assert_that!(display("d% /^ 2")).is_equal_to("1d100 /^ 2".to_string());
+        assert_that!(display("4df + 3X6")).is_equal_to("4dF + 3x6".to_string());
+        assert_that!(display("4D6!>=5KH3")).is_equal_to("4d6!>=5kh3".to_string());
+        assert_that!(display("10d10>=8!")).is_equal_to("10d10>=8!".to_string());
+        assert_that!(display("2d6ro<3min2")).is_equal_to("2d6ro<3min2".to_string());
+
+        assert_that!("3D6".parse::<Roll>()).is_ok_containing(Roll::Dice(3, 6));
+        assert_that!("3d6 + 1".parse::<Roll>()).is_err_containing(Error::DiceBadExpr("3d6 + 1".into()));
+        assert_that!(" ".parse::<Expr>()).is_err_containing(Error::DiceMissingExpr);
+    }
+
+    #[test]
+    fn canonical_expressions()
+    {
+        let canonical = |text: &str| text.parse::<Expr>().unwrap().canonical().to_string();
+        assert_that!(canonical("D6 + 2d6")).is_equal_to("3d6".to_string());
+        assert_that!(canonical("2 + d4 + d6 - 1")).is_equal_to("1d6 + 1d4 + 1".to_string());
+        assert_that!(canonical("2 + d4 + D6 + 2d6 - 1")).is_equal_to("3d6 + 1d4 + 1".to_string());
+        assert_that!(canonical("d20 - 3 - d4 + 1")).is_equal_to("1d20 - 1d4 - 2".to_string());
+        assert_that!(canonical("d8 + 2d6kh1 + d8")).is_equal_to("2d8 + 2d6kh1".to_string());
+        assert_that!(canonical("x6 + 4dF + d6 + x6")).is_equal_to("1d6 + 2x6 + 4dF".to_string());
+        assert_that!(canonical("3d6 - d6")).is_equal_to("3d6 - 1d6".to_string());
+        assert_that!(canonical("-d6 - (d6 - 2)")).is_equal_to("-2d6 + 2".to_string());
+        assert_that!(canonical("(d6 + d6) * (1 + 1)")).is_equal_to("2d6 * 2".to_string());
+        assert_that!(canonical("3 - 3")).is_equal_to("0".to_string());
+
+        let expr: Expr = "d6 + 2d6 - 1 + d4 - (d4 - 3)".parse().unwrap();
+        let dist = expr.distribution().unwrap();
+        let canonical_dist = expr.canonical().distribution().unwrap();
+        assert_that!(canonical_dist.len()).is_equal_to(dist.len());
+        for ((outcome, p), (canonical_outcome, q)) in dist.outcomes().iter().zip(canonical_dist.outcomes())
+        {
+            assert_that!(canonical_outcome).is_equal_to(outcome);
+            assert_that!(*q).is_close_to(*p, 1e-12);
+        }
+    }
+
+    #[test]
+    fn parse_error_rendering()
+    {
+        let error = |text: &str| match text.parse::<Expr>()
+        {
+            Err(Error::UnparseableTerm(error)) => error,
+            other => panic!("Unexpected result {:?}", other),
+        };
+        assert_that!(error("3d7+?2").render()).is_equal_to("3d7+?2\n    ^".to_string());
+        assert_that!(error("3d7+?2").to_string())
+            .is_equal_to("expected a number, dice, or '(' but found '?' at byte 4 of '3d7+?2'".to_string());
+        assert_that!(error("4d6 kh3").render()).is_equal_to("4d6 kh3\n    ^^^".to_string());
+        assert_that!(error("2d6 +").render()).is_equal_to("2d6 +\n     ^".to_string());
+        assert_that!(error("2d6 +").to_string())
+            .is_equal_to("expected a number, dice, or '(' at the end of '2d6 +'".to_string());
+        assert_that!(error("(ä + 1").render()).is_equal_to("(ä + 1\n ^".to_string());
+        assert_that!(error("2d0+1").render()).is_equal_to("2d0+1\n  ^".to_string());
+        assert_that!(error("2d0+1").to_string())
+            .is_equal_to("expected at least one side but found '0' at byte 2 of '2d0+1'".to_string());
+        assert_that!(error("d20 / (d4 - 2)").render()).is_equal_to("d20 / (d4 - 2)\n      ^^^^^^^^".to_string());
+        let error = |text: &str| match command(text.into())
+        {
+            Err(Error::UnparseableTerm(error)) => error,
+            other => panic!("Unexpected result {:?}", other),
+        };
+        assert_that!(error("hit: d20+5; dmg: 2d6+x").render())
+            .is_equal_to("hit: d20+5; dmg: 2d6+x\n                      ^".to_string());
+        assert_that!(error("hit: d20+5; dmg: 2d6+?; x: d4").render())
+            .is_equal_to("hit: d20+5; dmg: 2d6+?; x: d4\n                     ^".to_string());
+        assert_that!(error("a: d6, 2#3d6 + 2d0 sd").render())
+            .is_equal_to("a: d6, 2#3d6 + 2d0 sd\n                 ^".to_string());
+        assert_that!(command("(d6".into()).unwrap_err().to_string())
+            .is_equal_to("Failed parsing dice expression: expected ')' at the end of '(d6'".to_string());
+    }
+
+    /// Return a random term that the parser accepts.
+    fn random_roll(rng: &mut rng::SeededRng) -> Roll
+    {
+        let num = rng.gen_range(1, 10);
+        match rng.gen_range(0, 5)
+        {
+            0 => Roll::Incr(rng.gen_range(0, 100)),
+            1 => Roll::Dice(num, rng.gen_range(1, 101)),
+            2 => Roll::ExplodingDice(num, rng.gen_range(2, 21)),
+            3 => Roll::Fudge(num),
+            _ => loop
+            {
+                let sides: u32 = rng.gen_range(4, 21);
+                let base = match rng.gen_range(0, 4)
+                {
+                    0 => Roll::ExplodingDice(num, sides),
+                    1 => Roll::Fudge(num),
+                    _ => Roll::Dice(num, sides),
+                };
+                let compares = [Compare::Equal, Compare::Less, Compare::LessEqual, Compare::Greater, Compare::GreaterEqual];
+                let styles = [ExplodeStyle::Chained, ExplodeStyle::Compounding, ExplodeStyle::Penetrating];
+                let keeps = [Keep::Highest, Keep::Lowest, Keep::DropHighest, Keep::DropLowest];
+                let condition = |rng: &mut rng::SeededRng| Condition{
+                    compare: compares[rng.gen_range(0u32, 5) as usize],
+                    value: i64::from(rng.gen_range(1, sides + 1)),
+                };
+                let mut mods = Modifiers::default();
+                if rng.gen()
+                {
+                    mods.reroll = Some(Reroll{ condition: condition(rng), once: rng.gen() });
+                }
+                if rng.gen()
+                {
+                    mods.minimum = Some(i64::from(rng.gen_range(0u32, 4)));
+                }
+                if rng.gen()
+                {
+                    mods.explode = Some(Explode{
+                        condition: if rng.gen() { Some(condition(rng)) } else { None },
+                        style: styles[rng.gen_range(0u32, 3) as usize],
+                    });
+                }
+                if rng.gen()
+                {
+                    mods.keep = Some(keeps[rng.gen_range(0u32, 4) as usize](rng.gen_range(1, num + 1)));
+                }
+                if rng.gen()
+                {
+                    mods.successes = Some(condition(rng));
+                    if rng.gen()
+                    {
+                        mods.failures = Some(condition(rng));
+                    }
+                }
+                if mods != Modifiers::default() && check_term(&base, &mods, &Limits::default()).is_ok()
+                {
+                    break Roll::Modified(Box::new(base), mods);
+                }
+            },
+        }
+    }
+
+    /// Return a random expression that the parser accepts, nested at most
+    /// depth deep.
+    fn random_expr(rng: &mut rng::SeededRng, depth: u32) -> Expr
+    {
+        let child = |rng: &mut rng::SeededRng| Box::new(random_expr(rng, depth - 1));
+        match if depth == 0 { 0 } else { rng.gen_range(0, 6) }
+        {
+            0 => Expr::Roll(random_roll(rng)),
+            1 => Expr::Neg(child(rng)),
+            2 => Expr::Add(child(rng), child(rng)),
+            3 => Expr::Sub(child(rng), child(rng)),
+            4 => Expr::Mul(child(rng), child(rng)),
+            _ => {
+                let divisor = match rng.gen()
+                {
+                    true  => Roll::Incr(rng.gen_range(1, 10)),
+                    false => Roll::Dice(rng.gen_range(1, 5), rng.gen_range(1, 21)),
+                };
+                let rounding = [Rounding::Down, Rounding::Up, Rounding::Nearest][rng.gen_range(0u32, 3) as usize];
+                Expr::Div(child(rng), Box::new(Expr::Roll(divisor)), rounding)
+            },
+        }
+    }
+
+    #[test]
+    fn display_round_trip()
+    {
+        let mut rng = rng::seeded(21);
+        for _ in 0..1000
+        {
+            let expr = random_expr(&mut rng, 4);
+            assert_that!(expr.to_string().parse::<Expr>()).is_ok_containing(expr.clone());
+
+            let canonical = expr.canonical();
+            assert_that!(canonical.to_string().parse::<Expr>()).is_ok_containing(canonical.clone());
+            assert_that!(canonical.canonical()).is_equal_to(canonical);
+        }
+    }
+
+    #[test]
+    fn unusual_sides()
+    {
+        let rolled = roll_min("d2 + 2d7 + d%");
+        assert_that!(rolled.total).is_equal_to(4);
+        assert_that!(rolled.to_string()).is_equal_to("1d2(1) + 2d7(1+1) + 1d100(1)".to_string());
+        let dist = command("d5".into()).unwrap().distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(5);
+    }
+
+    #[test]
+    fn fudge_dice()
+    {
+        let rolled = roll_min("4dF + 1");
+        assert_that!(rolled.total).is_equal_to(-3);
+        assert_that!(rolled.to_string()).is_equal_to("4dF(-1-1-1-1) + 1".to_string());
+
+        let die = RolledTerm{ term: Roll::Fudge(3), dice: vec![Die::new(vec![1]), Die::new(vec![0]), Die::new(vec![-1])], subtotal: 0 };
+        assert_that!(die.to_string()).is_equal_to("3dF(+1+0-1)".to_string());
+
+        let dist = command("4dF".into()).unwrap().distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(9);
+        assert_that!(dist.probability(&Outcome::Num(4))).is_close_to(1.0 / 81.0, 1e-12);
+        assert_that!(dist.probability(&Outcome::Num(0))).is_close_to(19.0 / 81.0, 1e-12);
+    }
+
+    #[test]
+    fn keep_description()
+    {
+        let term = Roll::Modified(Box::new(Roll::Dice(4, 6)), Modifiers{ keep: Some(Keep::Highest(3)), ..Modifiers::default() });
+        let mut dice: Vec<Die> = [6, 5, 1, 4].iter().map(|&f| Die::new(vec![f])).collect();
+        drop_dice(&mut dice, Keep::Highest(3));
+        let rolled = RolledTerm{ term, dice, subtotal: 15 };
+        assert_that!(rolled.to_string()).is_equal_to("4d6kh3(6+5+~1~+4)".to_string());
+
+        let rolled = roll_min("2d20kl1 + 4dFdl2");
+        assert_that!(rolled.total).is_equal_to(-1);
+        assert_that!(rolled.to_string()).is_equal_to("2d20kl1(~1~+1) + 4dFdl2(~-1~~-1~-1-1)".to_string());
+    }
+
+    #[test]
+    fn keep_ties()
+    {
+        let mut dice: Vec<Die> = [3, 5, 3, 5].iter().map(|&f| Die::new(vec![f])).collect();
+        drop_dice(&mut dice, Keep::DropHighest(1));
+        let dropped: Vec<bool> = dice.iter().map(|d| d.dropped).collect();
+        assert_that!(dropped).is_equal_to(vec![false, true, false, false]);
+
+        drop_dice(&mut dice, Keep::Highest(6));
+        assert_that!(dice.iter().filter(|d| d.dropped).count()).is_equal_to(1);
+    }
+
+    #[test]
+    fn keep_bounds()
+    {
+        let bounds = |text: &str| match command(text.into()).unwrap()
+        {
+            Command::RollDice(expr) => expr.bounds(),
+            _ => panic!("Wrong command type"),
+        };
+        assert_that!(bounds("4d6kh3")).is_equal_to((3, 18));
+        assert_that!(bounds("4d6dl3")).is_equal_to((1, 6));
+        assert_that!(bounds("2d6kh5")).is_equal_to((2, 12));
+        assert_that!(bounds("4dFkl2")).is_equal_to((-2, 2));
+    }
+
+    #[test]
+    fn keep_distribution()
+    {
+        let dist = command("4d6dl1".into()).unwrap().distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(16);
+        assert_that!(dist.probability(&Outcome::Num(18))).is_close_to(21.0 / 1296.0, 1e-12);
+        assert_that!(dist.probability(&Outcome::Num(3))).is_close_to(1.0 / 1296.0, 1e-12);
+
+        let dist = command("2d20kh1".into()).unwrap().distribution().unwrap();
+        assert_that!(dist.probability(&Outcome::Num(20))).is_close_to(39.0 / 400.0, 1e-12);
+        let dist = command("2d20kl1".into()).unwrap().distribution().unwrap();
+        assert_that!(dist.probability(&Outcome::Num(20))).is_close_to(1.0 / 400.0, 1e-12);
+
+        let total: f64 = command("5d10kh3 + 2".into()).unwrap().distribution().unwrap()
+            .outcomes().iter().map(|(_, p)| p).sum();
+        assert_that!(total).is_close_to(1.0, 1e-9);
+    }
+
+    fn rerolled(faces: Vec<i64>, rerolled: Vec<i64>) -> Die
+    {
+        Die{ faces, rerolled, dropped: false, symbols: Vec::new() }
+    }
+
+    #[test]
+    fn reroll_description()
+    {
+        let term = Roll::Modified(Box::new(Roll::Dice(2, 6)), Modifiers{
+            reroll: Some(Reroll{ condition: Condition{ compare: Compare::Equal, value: 1 }, once: false }),
+            ..Modifiers::default()
+        });
+        let rolled = RolledTerm{ term, dice: vec![rerolled(vec![4], vec![1]), Die::new(vec![3])], subtotal: 7 };
+        assert_that!(rolled.to_string()).is_equal_to("2d6r1(1r4+3)".to_string());
+
+        let term = Roll::Modified(Box::new(Roll::ExplodingDice(2, 6)), Modifiers{
+            reroll: Some(Reroll{ condition: Condition{ compare: Compare::Less, value: 3 }, once: true }),
+            minimum: Some(2),
+            ..Modifiers::default()
+        });
+        let rolled = RolledTerm{ term, dice: vec![rerolled(vec![6, 2], vec![1]), Die::new(vec![3])], subtotal: 11 };
+        assert_that!(rolled.to_string()).is_equal_to("2x6ro<3min2<(1r6!+2) + (3)>".to_string());
+
+        let term = Roll::Modified(Box::new(Roll::Fudge(2)), Modifiers{
+            reroll: Some(Reroll{ condition: Condition{ compare: Compare::LessEqual, value: 0 }, once: false }),
+            ..Modifiers::default()
+        });
+        let rolled = RolledTerm{ term, dice: vec![rerolled(vec![1], vec![-1, 0]), Die::new(vec![1])], subtotal: 2 };
+        assert_that!(rolled.to_string()).is_equal_to("2dFr<=0(-1r+0r+1+1)".to_string());
+    }
+
+    #[test]
+    fn reroll_values()
+    {
+        let cmd = command("10d6r<3".into()).unwrap();
+        for decision in cmd.iter_seeded(1).take(50)
+        {
+            assert_that!(Outcome::from(&decision).as_num().unwrap()).is_greater_than_or_equal_to(30);
+        }
+        let cmd = command("10d6min4".into()).unwrap();
+        for decision in cmd.iter_seeded(1).take(50)
+        {
+            assert_that!(Outcome::from(&decision).as_num().unwrap()).is_greater_than_or_equal_to(40);
+        }
+
+        let rolled = roll_min("2d6ro1");
+        assert_that!(rolled.to_string()).is_equal_to("2d6ro1(1r1+1r1)".to_string());
+        let rolled = roll_min("3d6min2");
+        assert_that!(rolled.total).is_equal_to(6);
+    }
+
+    #[test]
+    fn reroll_limit()
+    {
+        let rolled = roll_min("d6r1");
+        assert_that!(rolled.total).is_equal_to(1);
+        assert_that!(rolled.terms[0].dice[0].rerolled.len()).is_equal_to(MAX_REROLLS);
+    }
+
+    #[test]
+    fn reroll_bounds()
+    {
+        let bounds = |text: &str| match command(text.into()).unwrap()
+        {
+            Command::RollDice(expr) => expr.bounds(),
+            _ => panic!("Wrong command type"),
+        };
+        assert_that!(bounds("4d6r<3")).is_equal_to((12, 24));
+        assert_that!(bounds("4d6ro<3")).is_equal_to((4, 24));
+        assert_that!(bounds("4d6min2kh3")).is_equal_to((6, 18));
+        assert_that!(bounds("d6min8")).is_equal_to((8, 8));
+        assert_that!(bounds("x6r6")).is_equal_to((1, 5));
+        assert_that!(bounds("x6r1")).is_equal_to((2, i64::MAX));
+        assert_that!(bounds("dFr<0")).is_equal_to((0, 1));
+    }
+
+    #[test]
+    fn reroll_distribution()
+    {
+        let prob = |text: &str, value| command(text.into()).unwrap().distribution().unwrap()
+            .probability(&Outcome::Num(value));
+        assert_that!(prob("d6r1", 1)).is_equal_to(0.0);
+        assert_that!(prob("d6r1", 2)).is_close_to(1.0 / 5.0, 1e-12);
+        assert_that!(prob("d6ro1", 1)).is_close_to(1.0 / 36.0, 1e-12);
+        assert_that!(prob("d6ro1", 2)).is_close_to(7.0 / 36.0, 1e-12);
+        assert_that!(prob("d6min3", 3)).is_close_to(1.0 / 2.0, 1e-12);
+        assert_that!(prob("x6r1", 8)).is_close_to(1.0 / 5.0 * 1.0 / 6.0, 1e-12);
+        assert_that!(prob("2d6r1kh1", 6)).is_close_to(9.0 / 25.0, 1e-12);
+    }
+
+    #[test]
+    fn success_description()
+    {
+        let pool = |faces: &[i64]| faces.iter().map(|&f| Die::new(vec![f])).collect::<Vec<Die>>();
+        let term = Roll::Modified(Box::new(Roll::Dice(5, 10)), Modifiers{
+            successes: Some(Condition{ compare: Compare::GreaterEqual, value: 8 }),
+            failures: Some(Condition{ compare: Compare::Equal, value: 1 }),
+            ..Modifiers::default()
+        });
+        let botch = RolledTerm{ term: term.clone(), dice: pool(&[3, 1, 2, 5, 4]), subtotal: -1 };
+        assert_that!(botch.is_botch()).is_true();
+        assert_that!(botch.to_string()).is_equal_to("5d10>=8f1(3+1+2+5+4)[botch]".to_string());
+
+        let rolled = RolledTerm{ term, dice: pool(&[9, 1, 2, 5, 4]), subtotal: 0 };
+        assert_that!(rolled.is_botch()).is_false();
+        assert_that!(rolled.to_string()).is_equal_to("5d10>=8f1(9+1+2+5+4)".to_string());
+    }
+
+    #[test]
+    fn success_values()
+    {
+        let rolled = roll_min("4d10>=8f1 + 1");
+        assert_that!(rolled.total).is_equal_to(-3);
+        assert_that!(rolled.is_botch()).is_true();
+        assert_that!(rolled.to_string()).is_equal_to("4d10>=8f1(1+1+1+1)[botch] + 1".to_string());
+
+        let rolled = roll_min("3d6=1");
+        assert_that!(rolled.total).is_equal_to(3);
+        assert_that!(rolled.is_botch()).is_false();
+        assert_that!(rolled.to_string()).is_equal_to("3d6=1(1+1+1)".to_string());
+
+        let cmd = command("10d10>=8".into()).unwrap();
+        for decision in cmd.iter_seeded(3).take(50)
+        {
+            let successes = Outcome::from(&decision).as_num().unwrap();
+            assert_that!(successes).is_greater_than_or_equal_to(0);
+            assert_that!(successes).is_less_than_or_equal_to(10);
+        }
+    }
+
+    #[test]
+    fn success_bounds()
+    {
+        let bounds = |text: &str| match command(text.into()).unwrap()
+        {
+            Command::RollDice(expr) => expr.bounds(),
+            _ => panic!("Wrong command type"),
+        };
+        assert_that!(bounds("10d10>=8")).is_equal_to((0, 10));
+        assert_that!(bounds("10d10>=8f1")).is_equal_to((-10, 10));
+        assert_that!(bounds("5x10kh3>=8")).is_equal_to((0, 3));
+    }
+
+    #[test]
+    fn success_distribution()
+    {
+        let prob = |text: &str, value| command(text.into()).unwrap().distribution().unwrap()
+            .probability(&Outcome::Num(value));
+        assert_that!(prob("10d10>=8", 3)).is_close_to(120.0 * 0.3_f64.powi(3) * 0.7_f64.powi(7), 1e-12);
+        assert_that!(prob("d10>=8f1", 1)).is_close_to(0.3, 1e-12);
+        assert_that!(prob("d10>=8f1", -1)).is_close_to(0.1, 1e-12);
+        assert_that!(prob("d10>=8f1", 0)).is_close_to(0.6, 1e-12);
+        assert_that!(prob("3d6kh1>=6", 1)).is_close_to(1.0 - (5.0_f64 / 6.0).powi(3), 1e-12);
+        assert_that!(prob("3d6kh1<2", 1)).is_close_to(1.0 / 216.0, 1e-12);
+    }
+
+    #[test]
+    fn explode_description()
+    {
+        let dice = vec![Die::new(vec![1]), Die::new(vec![6, 5]), Die::new(vec![2])];
+        let term = |style| Roll::Modified(Box::new(Roll::Dice(3, 6)), Modifiers{
+            explode: Some(Explode{ condition: None, style }),
+            ..Modifiers::default()
+        });
+        let rolled = RolledTerm{ term: term(ExplodeStyle::Chained), dice: dice.clone(), subtotal: 14 };
+        assert_that!(rolled.to_string()).is_equal_to("3d6!(1+(6!+5)+2)".to_string());
+        let rolled = RolledTerm{ term: term(ExplodeStyle::Compounding), dice: dice.clone(), subtotal: 14 };
+        assert_that!(rolled.to_string()).is_equal_to("3d6!!(1+11!!+2)".to_string());
+        let rolled = RolledTerm{ term: term(ExplodeStyle::Penetrating), dice, subtotal: 14 };
+        assert_that!(rolled.to_string()).is_equal_to("3d6!p(1+(6!+5)+2)".to_string());
+
+        let term = Roll::Modified(Box::new(Roll::Dice(1, 10)), Modifiers{
+            explode: Some(Explode{ condition: Some(Condition{ compare: Compare::GreaterEqual, value: 8 }), style: ExplodeStyle::Chained }),
+            ..Modifiers::default()
+        });
+        let rolled = RolledTerm{ term, dice: vec![Die::new(vec![9, 8, 3])], subtotal: 20 };
+        assert_that!(rolled.to_string()).is_equal_to("1d10!>=8((9!+8!+3))".to_string());
+    }
+
+    #[test]
+    fn explode_values()
+    {
+        let dice = |text: &str, seed| match command(text.into()).unwrap()
+        {
+            Command::RollDice(expr) => expr.roll_with(&mut rng::seeded(seed)).terms.remove(0).dice,
+            _ => panic!("Wrong command type"),
+        };
+        for seed in 0..20
+        {
+            for die in dice("10d6!>=5", seed)
+            {
+                let (last, exploded) = die.faces.split_last().unwrap();
+                assert_that!(*last).is_less_than(5);
+                assert_that!(exploded.iter().all(|&face| face >= 5)).is_true();
+            }
+            for die in dice("10d6!p", seed)
+            {
+                assert_that!(die.faces[1..].iter().all(|&face| (0..=5).contains(&face))).is_true();
+            }
+        }
+    }
+
+    #[test]
+    fn explode_limit()
+    {
+        let rolled = roll_min("d6!<=1");
+        assert_that!(rolled.terms[0].dice[0].faces.len()).is_equal_to(MAX_EXPLOSIONS as usize + 1);
+        assert_that!(rolled.total).is_equal_to(i64::from(MAX_EXPLOSIONS) + 1);
+    }
+
+    #[test]
+    fn explode_distribution()
+    {
+        let dist = |text: &str| command(text.into()).unwrap().distribution().unwrap();
+        let threshold = dist("d6!>=5");
+        assert_that!(threshold.probability(&Outcome::Num(1))).is_close_to(1.0 / 6.0, 1e-12);
+        assert_that!(threshold.probability(&Outcome::Num(5))).is_equal_to(0.0);
+        assert_that!(threshold.probability(&Outcome::Num(6))).is_close_to(1.0 / 36.0, 1e-12);
+        assert_that!(threshold.probability(&Outcome::Num(7))).is_close_to(2.0 / 36.0, 1e-12);
+
+        let penetrating = dist("d6!p");
+        assert_that!(penetrating.probability(&Outcome::Num(5))).is_close_to(1.0 / 6.0, 1e-12);
+        assert_that!(penetrating.probability(&Outcome::Num(6))).is_close_to(1.0 / 36.0, 1e-12);
+        assert_that!(penetrating.probability(&Outcome::Num(11))).is_close_to(1.0 / 216.0, 1e-12);
+
+        assert_that!(dist("2d6!!")).is_equal_to(dist("2x6"));
+        assert_that!(dist("2d6!")).is_equal_to(dist("2x6"));
+    }
+
+    #[test]
+    fn explode_bounds()
+    {
+        let bounds = |text: &str| match command(text.into()).unwrap()
+        {
+            Command::RollDice(expr) => expr.bounds(),
+            _ => panic!("Wrong command type"),
+        };
+        assert_that!(bounds("4d6!>=5")).is_equal_to((4, i64::MAX));
+        assert_that!(bounds("4d6!pkh2")).is_equal_to((2, i64::MAX));
+        assert_that!(bounds("4d6!>=5>=5")).is_equal_to((0, 4));
+    }
+
+    /// Assert that the totals rolled for the command text match its
+    /// distribution.
+    pub(super) fn assert_fair(text: &str, trials: usize)
+    {
+        let cmd = command(text.into()).unwrap();
+        assert_that!(fairness::chi_square(&cmd, trials, 42).unwrap().is_consistent(0.001)).is_true();
+    }
+
+    #[test]
+    fn modifiers_are_fair()
+    {
+        let exprs = [
+            "4d6kh3",
+            "4d6r1kh3", "3d6ro<3min2", "2x6r1",
+            "10d10>=8f1", "5d10kh3>=7", "4x6>=5",
+            "2d6!p", "4d6!!kh2", "5d10!>=9>=8",
+        ];
+        for expr in exprs
+        {
+            assert_fair(expr, 5000);
+        }
+    }
+
+    #[test]
+    fn deep_explosions_are_fair()
+    {
+        // A low threshold explodes past the default depth often.
+        assert_fair("3d6!>=5", 5000);
+    }
+
+    #[test]
+    fn command_total_dice_limit()
+    {
+        let many = format!("1000#{}", ["10000d6"; 10].join("+"));
+        assert_that!(command(many)).is_err_containing(Error::DiceTooManyTotalDice(100_000_000, 100_000));
+        assert_that!(command("10#10000d6; d6".into())).is_err_containing(Error::DiceTooManyTotalDice(100_001, 100_000));
+        assert_that!(command("10#5000d6 + 5000x6 + 7".into())).is_ok();
+        assert_that!(command("(10000d6) * 10 vs 10000d6 * 9".into())).is_ok();
+
+        let limits = Limits{ max_total_dice: 20, ..Limits::default() };
+        assert_that!(command_with_limits("20d6".into(), &limits)).is_ok();
+        assert_that!(command_with_limits("2d20 + 19d4".into(), &limits)).is_err_containing(Error::DiceTooManyTotalDice(21, 20));
+        assert_that!(command_with_limits("d20 + 5 vs 20d6".into(), &limits)).is_err_containing(Error::DiceTooManyTotalDice(21, 20));
+        assert_that!(command_with_limits("hit: d20; dmg: 4#5d6 sd".into(), &limits))
+            .is_err_containing(Error::DiceTooManyTotalDice(21, 20));
+    }
+
+    #[test]
+    fn command_sides_limit()
+    {
+        assert_that!(command_with_limits("d20".into(), &Limits{ max_sides: 12, ..Limits::default() }))
+            .is_err_containing(Error::UnparseableTerm(ParseError{
+                expr: "d20".into(), span: 1..3, problem: ParseProblem::Invalid(Box::new(Error::DiceTooManySides(20, 12))),
+            }));
+    }
+
+    #[test]
+    fn command_limit_errors()
+    {
+        let limit = |text: &str| command(text.into()).unwrap_err().limit().cloned();
+        assert_that!(limit("d2000000")).is_equal_to(Some(Error::DiceTooManySides(2_000_000, DEFAULT_MAX_SIDES)));
+        assert_that!(limit("20000d6")).is_equal_to(Some(Error::DiceTooManyDice(20_000, DEFAULT_MAX_DICE)));
+        assert_that!(limit("20#10000d6")).is_equal_to(Some(Error::DiceTooManyTotalDice(200_000, DEFAULT_MAX_TOTAL_DICE)));
+        assert_that!(limit(&vec!["1"; 1001].join(" + "))).is_equal_to(Some(Error::DiceTooManyTerms(DEFAULT_MAX_TERMS)));
+        let big = "10000d1000000 * 10000d1000000 * 10000d1000000";
+        assert_that!(limit(big)).is_equal_to(Some(Error::DiceOverflow(big.into())));
+        assert_that!(limit("d20 / 0")).is_equal_to(Some(Error::DiceDivideByZero("0".into())));
+        assert_that!(limit("d20 / d")).is_none();
+        assert_that!(Error::DiceMissingExpr.limit()).is_none();
+
+        // A parsed term records the explosion limit, so only a term built
+        // directly can exceed it.
+        let mods = Modifiers{
+            explode: Some(Explode{ condition: None, style: ExplodeStyle::Chained }),
+            max_explosions: Some(MAX_EXPLOSIONS + 1),
+            ..Modifiers::default()
+        };
+        let error = Error::DiceTooManyExplosions(MAX_EXPLOSIONS + 1, MAX_EXPLOSIONS);
+        assert_that!(check_term(&Roll::Dice(1, 6), &mods, &Limits::default()))
+            .is_err_containing((TermPart::Modifiers, ParseProblem::Invalid(Box::new(error.clone()))));
+        assert_that!(Error::UnparseableTerm(ParseError{ expr: "d6!".into(), span: 0..3,
+            problem: ParseProblem::Invalid(Box::new(error.clone())) }).limit()).is_equal_to(Some(&error));
+    }
+
+    #[test]
+    fn command_explosion_limit()
+    {
+        let limits = Limits{ max_explosions: Some(3), ..Limits::default() };
+        let expr = match command_with_limits("d4!1 + 2d2!1".into(), &limits).unwrap()
+        {
+            Command::RollDice(expr) => expr,
+            _ => panic!("Wrong command type"),
+        };
+        let rolled = expr.roll_with(&mut StepRng::new(0, 0));
+        assert_that!(rolled.terms[0].dice[0].faces.clone()).is_equal_to(vec![1; 4]);
+        assert_that!(rolled.terms[1].dice[1].faces.clone()).is_equal_to(vec![1; 4]);
+        assert_that!(rolled.total).is_equal_to(12);
+
+        let dist = match command_with_limits("d2!".into(), &Limits{ max_explosions: Some(1), ..Limits::default() })
+        {
+            Ok(Command::RollDice(expr)) => expr.distribution_to_depth(10).unwrap(),
+            _ => panic!("Wrong command type"),
+        };
+        assert_that!(dist.len()).is_equal_to(3);
+        assert_that!(dist.probability(&Outcome::Num(4))).is_close_to(0.25, 1e-12);
+    }
+
+    fn boost() -> CustomDie
+    {
+        "Boost: -, -, success, success advantage, advantage advantage, 1 advantage".parse().unwrap()
+    }
+
+    #[test]
+    fn custom_die_definition()
+    {
+        let die = boost();
+        assert_that!(die.name()).is_equal_to("Boost");
+        assert_that!(die.faces().len()).is_equal_to(6);
+        assert_that!(die.faces()[0].clone()).is_equal_to(Face::default());
+        assert_that!(die.faces()[5].clone())
+            .is_equal_to(Face{ value: 1, symbols: vec!["advantage".into()] });
+        assert_that!(die.to_string())
+            .is_equal_to("Boost: -, -, success, success advantage, advantage advantage, 1 advantage".to_string());
+        assert_that!("Story: 2 moon, -1 sun, 3".parse::<CustomDie>().unwrap().faces()[1].clone())
+            .is_equal_to(Face{ value: -1, symbols: vec!["sun".into()] });
+
+        assert_that!(CustomDie::new("F", vec![Face::default()])).is_err_containing(Error::DiceBadCustom("F".into()));
+        assert_that!(CustomDie::new("2sides", vec![Face::default()])).is_err_containing(Error::DiceBadCustom("2sides".into()));
+        assert_that!(CustomDie::new("Empty", Vec::new())).is_err_containing(Error::DiceBadCustom("Empty".into()));
+        assert_that!("No faces".parse::<CustomDie>()).is_err_containing(Error::DiceBadCustom("No faces".into()));
+    }
+
+    #[test]
+    fn command_custom_dice()
+    {
+        let dice = [boost(), "Story: 2 moon, 3 sun".parse().unwrap()];
+        assert_that!(command_with_dice("3dboost + 2".into(), &Limits::default(), &dice)).is_ok_containing(
+            Command::RollDice(Expr::Add(Box::new(Expr::Roll(Roll::Custom(3, boost()))), Box::new(Expr::Roll(Roll::Incr(2)))))
+        );
+        assert_that!(command("3dBoost".into())).is_err_containing(Error::UnparseableTerm(ParseError{
+            expr: "3dBoost".into(), span: 2..7, problem: ParseProblem::Invalid(Box::new(Error::DiceBadType("Boost".into()))),
+        }));
+        assert_that!(command_with_dice("3dBoost!".into(), &Limits::default(), &dice))
+            .is_err_containing(Error::UnparseableTerm(ParseError{
+                expr: "3dBoost!".into(), span: 7..8, problem: ParseProblem::Expected("no modifiers on custom dice"),
+            }));
+        assert_that!(command_with_dice("3xBoost".into(), &Limits::default(), &dice)).is_err();
+
+        let expr = match command_with_dice("4dBoost + dStory".into(), &Limits::default(), &dice)
+        {
+            Ok(Command::RollDice(expr)) => expr,
+            _ => panic!("Wrong command type"),
+        };
+        let rolled = expr.roll_with(&mut StepRng::new(1 << 30, 1 << 30));
+        assert_that!(rolled.total).is_equal_to(3);
+        assert_that!(rolled.tally()).is_equal_to(
+            vec![("advantage".to_string(), 3), ("success".to_string(), 1), ("sun".to_string(), 1)].into_iter().collect::<BTreeMap<_, _>>()
+        );
+        assert_that!(rolled.to_string()).is_equal_to(
+            "4dBoost(-, success advantage, advantage advantage, -) + 1dStory(3 sun) [advantage: 3, success: 1, sun: 1]".to_string()
+        );
+        assert_that!(expr.min()).is_some().is_equal_to(2);
+        assert_that!(expr.max()).is_some().is_equal_to(7);
+        assert_that!(expr.distribution().unwrap().probability(&Outcome::Num(7))).is_close_to(1.0 / 2592.0, 1e-12);
+    }
+
+    #[test]
+    fn overflow_safe_division()
+    {
+        assert_that!(divide(i64::MIN, -1, Rounding::Down)).is_equal_to(i64::MAX);
+        assert_that!(divide(i64::MAX, 2, Rounding::Nearest)).is_equal_to(1 << 62);
+        assert_that!(divide(i64::MIN, 2, Rounding::Up)).is_equal_to(i64::MIN / 2);
+        assert_that!(checked_divide(i64::MIN, -1, Rounding::Up)).is_none();
     }
 }
@@ -0,0 +1,858 @@
+use super::{checked_divide, dice_count, divide, explosion, face_bounds, Condition, Expr, ExplodeStyle, Modifiers,
+            Reroll, Roll, DEFAULT_EXPLODE_DEPTH};
+use crate::distribution::{self, Accuracy, Distribution};
+
+use std::collections::{BTreeMap, HashMap};
+
+/// The most steps taken computing the distribution of a term, beyond which no
+/// distribution is returned.
+pub(super) const MAX_WORK: u64 = (distribution::MAX_OUTCOMES as u64) * 64;
+
+type NumDist = BTreeMap<i64, f64>;
+
+impl Expr
+{
+    /// Return the lowest and highest possible totals of the expression. The
+    /// highest total of exploding dice is unbounded, and is given as the
+    /// maximum i64.
+    pub(crate) fn bounds(&self) -> (i64, i64)
+    {
+        let corners = |(alow, ahigh): (i64, i64), (blow, bhigh): (i64, i64), op: &dyn Fn(i64, i64) -> i64| {
+            let values = [op(alow, blow), op(alow, bhigh), op(ahigh, blow), op(ahigh, bhigh)];
+            (*values.iter().min().unwrap(), *values.iter().max().unwrap())
+        };
+        match self
+        {
+            Expr::Roll(roll) => roll_bounds(roll),
+            Expr::Neg(expr)  => {
+                let (low, high) = expr.bounds();
+                (high.saturating_neg(), low.saturating_neg())
+            },
+            Expr::Add(lhs, rhs) => corners(lhs.bounds(), rhs.bounds(), &|a, b| a.saturating_add(b)),
+            Expr::Sub(lhs, rhs) => corners(lhs.bounds(), rhs.bounds(), &|a, b| a.saturating_sub(b)),
+            Expr::Mul(lhs, rhs) => corners(lhs.bounds(), rhs.bounds(), &|a, b| a.saturating_mul(b)),
+            Expr::Div(lhs, rhs, rounding) => match rhs.bounds()
+            {
+                (low, high) if low <= 0 && high >= 0 => (i64::MIN, i64::MAX),
+                divisor => corners(lhs.bounds(), divisor, &|a, b| divide(a, b, *rounding)),
+            },
+        }
+    }
+
+    /// Return the lowest and highest totals that rolling the expression can
+    /// produce, with each exploding die exploding at most its limit, or None
+    /// if a total could overflow.
+    pub(crate) fn reachable_bounds(&self) -> Option<(i64, i64)>
+    {
+        let corners = |(alow, ahigh): (i64, i64), (blow, bhigh): (i64, i64), op: &dyn Fn(i64, i64) -> Option<i64>| {
+            let values = [op(alow, blow)?, op(alow, bhigh)?, op(ahigh, blow)?, op(ahigh, bhigh)?];
+            Some((*values.iter().min().unwrap(), *values.iter().max().unwrap()))
+        };
+        match self
+        {
+            Expr::Roll(roll) => reachable_roll_bounds(roll),
+            Expr::Neg(expr)  => {
+                let (low, high) = expr.reachable_bounds()?;
+                Some((high.checked_neg()?, low.checked_neg()?))
+            },
+            Expr::Add(lhs, rhs) => corners(lhs.reachable_bounds()?, rhs.reachable_bounds()?, &|a, b| a.checked_add(b)),
+            Expr::Sub(lhs, rhs) => corners(lhs.reachable_bounds()?, rhs.reachable_bounds()?, &|a, b| a.checked_sub(b)),
+            Expr::Mul(lhs, rhs) => corners(lhs.reachable_bounds()?, rhs.reachable_bounds()?, &|a, b| a.checked_mul(b)),
+            Expr::Div(lhs, rhs, rounding) => corners(lhs.reachable_bounds()?, rhs.reachable_bounds()?,
+                &|a, b| checked_divide(a, b, *rounding)),
+        }
+    }
+
+    /// Return the distribution of the totals of the expression, with each
+    /// exploding die exploding at most depth times. A die that would explode
+    /// again within its explosion limit is given the lowest total it could
+    /// still reach, so the distribution is exact except for the totals that
+    /// such dice could roll, as given by its accuracy. Returns None if there
+    /// would be more than MAX_OUTCOMES totals.
+    pub fn distribution_to_depth(&self, depth: u32) -> Option<Distribution>
+    {
+        self.num_dist(depth).map(|(totals, accuracy)| Distribution::from_nums(&totals).with_accuracy(accuracy))
+    }
+
+    /// Return the lowest possible total of the expression, with each exploding
+    /// die exploding at most its limit, or None if a total could overflow.
+    pub fn min(&self) -> Option<i64>
+    {
+        self.reachable_bounds().map(|(low, _)| low)
+    }
+
+    /// Return the highest possible total of the expression, with each exploding
+    /// die exploding at most its limit, or None if a total could overflow.
+    pub fn max(&self) -> Option<i64>
+    {
+        self.reachable_bounds().map(|(_, high)| high)
+    }
+
+    /// Return the expected total of the expression, or None if it would take
+    /// too long to compute. Each exploding die is treated as exploding at most
+    /// DEFAULT_EXPLODE_DEPTH times, as in `distribution()`.
+    pub fn mean(&self) -> Option<f64>
+    {
+        self.moments().map(|(mean, _)| mean)
+    }
+
+    /// Return the variance of the total of the expression, or None if it would
+    /// take too long to compute. Exploding dice are truncated as for `mean()`.
+    pub fn variance(&self) -> Option<f64>
+    {
+        self.moments().map(|(_, variance)| variance)
+    }
+
+    /// Return the probability of rolling a total of at least target, or None
+    /// if the distribution of the expression would be too large. Exploding
+    /// dice are truncated as for `distribution()`.
+    pub fn probability_at_least(&self, target: i64) -> Option<f64>
+    {
+        self.probability_where(|total| total >= target)
+    }
+
+    /// Return the probability of rolling a total of at most target, or None
+    /// if the distribution of the expression would be too large.
+    pub fn probability_at_most(&self, target: i64) -> Option<f64>
+    {
+        self.probability_where(|total| total <= target)
+    }
+
+    /// Return the probability of rolling a total of exactly target, or None
+    /// if the distribution of the expression would be too large.
+    pub fn probability_exactly(&self, target: i64) -> Option<f64>
+    {
+        self.probability_where(|total| total == target)
+    }
+
+    fn probability_where<F>(&self, pred: F) -> Option<f64>
+        where F: Fn(i64) -> bool
+    {
+        let (totals, _) = self.num_dist(DEFAULT_EXPLODE_DEPTH)?;
+        Some(totals.iter().filter(|(&total, _)| pred(total)).map(|(_, p)| p).sum())
+    }
+
+    /// Return the mean and variance of the total of the expression, or None
+    /// if they would take too long to compute. Terms are independent, so
+    /// these combine directly except for division.
+    fn moments(&self) -> Option<(f64, f64)>
+    {
+        match self
+        {
+            Expr::Roll(roll) => term_moments(roll),
+            Expr::Neg(expr)  => expr.moments().map(|(mean, var)| (-mean, var)),
+            Expr::Add(lhs, rhs) => {
+                let ((lmean, lvar), (rmean, rvar)) = (lhs.moments()?, rhs.moments()?);
+                Some((lmean + rmean, lvar + rvar))
+            },
+            Expr::Sub(lhs, rhs) => {
+                let ((lmean, lvar), (rmean, rvar)) = (lhs.moments()?, rhs.moments()?);
+                Some((lmean - rmean, lvar + rvar))
+            },
+            Expr::Mul(lhs, rhs) => {
+                let ((lmean, lvar), (rmean, rvar)) = (lhs.moments()?, rhs.moments()?);
+                let mean = lmean * rmean;
+                Some((mean, (lvar + lmean * lmean) * (rvar + rmean * rmean) - mean * mean))
+            },
+            Expr::Div(..) => Some(dist_moments(&self.num_dist(DEFAULT_EXPLODE_DEPTH)?.0)),
+        }
+    }
+
+    /// Return the distribution of the totals of the expression, and how exact
+    /// it is, or None if it would take too long to compute.
+    pub(super) fn num_dist(&self, depth: u32) -> Option<(NumDist, Accuracy)>
+    {
+        let add = |x: i64, y: i64| x.saturating_add(y);
+        match self
+        {
+            Expr::Roll(roll) => {
+                if term_span(roll, depth) >= distribution::MAX_OUTCOMES as u64 { return None; }
+                term_dist(roll, depth)
+            },
+            Expr::Neg(expr) => {
+                let (dist, accuracy) = expr.num_dist(depth)?;
+                Some((negate(&dist), both_exact(accuracy, Accuracy::Exact)))
+            },
+            Expr::Add(lhs, rhs) => {
+                let ((a, aacc), (b, bacc)) = (lhs.num_dist(depth)?, rhs.num_dist(depth)?);
+                Some((add_dists(&a, &b)?, increasing(aacc, bacc, lowest(&a), lowest(&b), add)))
+            },
+            Expr::Sub(lhs, rhs) => {
+                let ((a, aacc), (b, bacc)) = (lhs.num_dist(depth)?, rhs.num_dist(depth)?);
+                let b = negate(&b);
+                let bacc = both_exact(bacc, Accuracy::Exact);
+                Some((add_dists(&a, &b)?, increasing(aacc, bacc, lowest(&a), lowest(&b), add)))
+            },
+            Expr::Mul(lhs, rhs) => {
+                let ((a, aacc), (b, bacc)) = (lhs.num_dist(depth)?, rhs.num_dist(depth)?);
+                let accuracy = match lowest(&a) >= 0 && lowest(&b) >= 0
+                {
+                    true  => increasing(aacc, bacc, lowest(&a), lowest(&b), |x, y| x.saturating_mul(y)),
+                    false => both_exact(aacc, bacc),
+                };
+                Some((combine(&a, &b, |x, y| x * y)?, accuracy))
+            },
+            Expr::Div(lhs, rhs, rounding) => {
+                let ((a, aacc), (b, bacc)) = (lhs.num_dist(depth)?, rhs.num_dist(depth)?);
+                // Dividing by a positive divisor keeps the order of the
+                // dividends, and the lowest quotient of a dividend is found by
+                // dividing by the smallest or largest divisor.
+                let accuracy = match (aacc, bacc)
+                {
+                    (Accuracy::ExactBelow(cut), Accuracy::Exact) if lowest(&b) > 0 => Accuracy::ExactBelow(
+                        divide(cut, lowest(&b), *rounding).min(divide(cut, highest(&b), *rounding))),
+                    _ => both_exact(aacc, bacc),
+                };
+                Some((combine(&a, &b, |x, y| divide(x, y, *rounding))?, accuracy))
+            },
+        }
+    }
+}
+
+/// Return the lowest and highest possible totals of the term.
+fn roll_bounds(term: &Roll) -> (i64, i64)
+{
+    match term
+    {
+        Roll::Incr(num) => (i64::from(*num), i64::from(*num)),
+        _ => {
+            let (low, high) = die_bounds(term);
+            let kept = i64::from(kept_dice(term));
+            (low.saturating_mul(kept), high.saturating_mul(kept))
+        },
+    }
+}
+
+/// Return the lowest and highest totals that rolling the term can produce, or
+/// None if they would overflow.
+fn reachable_roll_bounds(term: &Roll) -> Option<(i64, i64)>
+{
+    let unmodified = Modifiers::default();
+    let (base, mods) = match term
+    {
+        Roll::Incr(num) => return Some((i64::from(*num), i64::from(*num))),
+        Roll::Modified(base, mods) => (&**base, mods),
+        term                       => (term, &unmodified),
+    };
+    let (low, high) = match explosion(base, mods)
+    {
+        Some(exploding) if mods.successes.is_none() => exploding_die_bounds(base, mods, exploding)?,
+        _ => die_bounds(term),
+    };
+    let kept = i64::from(kept_dice(term));
+    Some((low.checked_mul(kept)?, high.checked_mul(kept)?))
+}
+
+/// Return the lowest and highest totals of a single die of a term that
+/// explodes, with the die exploding at most its limit, or None if they would
+/// overflow.
+fn exploding_die_bounds(base: &Roll, mods: &Modifiers, (condition, style, sides): (Condition, ExplodeStyle, u32))
+    -> Option<(i64, i64)>
+{
+    let penalty = if style == ExplodeStyle::Penetrating { 1 } else { 0 };
+    let (low, high) = face_bounds(base);
+    let sides = i64::from(sides);
+    let limit = i64::from(mods.explosion_limit());
+    let rerolled = |face: i64| match mods.reroll
+    {
+        Some(Reroll{ condition, once: false }) => condition.matches(face),
+        _ => false,
+    };
+
+    // Which conditions a roll meets only changes around the values of the
+    // conditions and the minimum, so the extreme rolls meeting some of them
+    // are among those values and the lowest and highest faces.
+    let mut values = vec![condition.value];
+    values.extend(mods.reroll.map(|reroll| reroll.condition.value));
+    values.extend(mods.minimum);
+    let candidates = |low: i64, high: i64| {
+        let mut faces = vec![low, high];
+        for value in &values
+        {
+            faces.extend([value.saturating_sub(1), *value, value.saturating_add(1)]);
+        }
+        faces.retain(|face| (low..=high).contains(face));
+        faces
+    };
+    let extremes = |faces: &[i64], explodes: bool| {
+        let matching = faces.iter().copied().filter(|&face| condition.matches(face) == explodes);
+        (matching.clone().min(), matching.max())
+    };
+    let firsts: Vec<i64> = candidates(low, high).into_iter()
+        .filter(|&face| !rerolled(face))
+        .map(|face| face.max(mods.minimum.unwrap_or(face)))
+        .collect();
+    let (first_ends, first_explodes) = (extremes(&firsts, false), extremes(&firsts, true));
+    let rerolls = candidates(1, sides);
+    let (ends, explodes) = (extremes(&rerolls, false), extremes(&rerolls, true));
+
+    // The re-rolls of an exploding die stop at the first that doesn't
+    // explode, or at the last whatever it rolls. Every exploding re-roll
+    // adds at least zero, so the lowest total stops as soon as it can and the
+    // highest explodes as long as it can.
+    let (lowest, highest) = match limit
+    {
+        0 => (0, 0),
+        _ => {
+            let lowest = explodes.0
+                .map(|face| (face - penalty).saturating_mul(limit - 1).saturating_add(1 - penalty))
+                .into_iter()
+                .chain(ends.0.map(|face| face - penalty))
+                .min()
+                .unwrap_or(0);
+            let highest = match explodes.1
+            {
+                Some(face) => (face - penalty).checked_mul(limit - 1)?.checked_add(sides - penalty)?,
+                None       => ends.1.map_or(0, |face| face - penalty),
+            };
+            (lowest, highest)
+        },
+    };
+    let low = first_ends.0.into_iter()
+        .chain(first_explodes.0.map(|face| face + lowest))
+        .min()
+        .unwrap_or(low);
+    let high = match first_explodes.1
+    {
+        Some(face) => face.checked_add(highest)?.max(first_ends.1.unwrap_or(i64::MIN)),
+        None       => first_ends.1.unwrap_or(high),
+    };
+    Some((low, high))
+}
+
+/// Return the lowest and highest total of a single die of the term.
+fn die_bounds(term: &Roll) -> (i64, i64)
+{
+    match term
+    {
+        Roll::ExplodingDice(_, _) => (1, i64::MAX),
+        Roll::Modified(_, Modifiers{ successes: Some(_), failures, .. }) =>
+            (if failures.is_some() { -1 } else { 0 }, 1),
+        Roll::Modified(base, mods) => {
+            let (mut low, mut high) = die_bounds(base);
+            let (face_low, face_high) = face_bounds(base);
+            if let Some(Reroll{ condition, once: false }) = mods.reroll
+            {
+                if let Some((first_low, first_high)) = condition.unmatched(face_low, face_high)
+                {
+                    low = first_low;
+                    // An exploding die can't explode if its maximum is always re-rolled.
+                    if high != i64::MAX || first_high < face_high
+                    {
+                        high = first_high;
+                    }
+                }
+            }
+            if let Some(minimum) = mods.minimum
+            {
+                low = low.max(minimum);
+                high = high.max(minimum);
+            }
+            if mods.explode.is_some()
+            {
+                high = i64::MAX;
+            }
+            (low, high)
+        },
+        _ => face_bounds(term),
+    }
+}
+
+/// Return the number of dice that count towards the total of the term.
+fn kept_dice(term: &Roll) -> u32
+{
+    let num = dice_count(term);
+    match term
+    {
+        Roll::Modified(_, Modifiers{ keep: Some(keep), .. }) => num - keep.dropped(num).0,
+        _ => num,
+    }
+}
+
+/// Return the difference between the largest and smallest total of the term.
+fn term_span(term: &Roll, depth: u32) -> u64
+{
+    let die_span = match *term
+    {
+        Roll::Dice(_, sides) => u64::from(sides - 1),
+        Roll::ExplodingDice(_, sides) => u64::from(sides) * (u64::from(depth) + 1) - 1,
+        Roll::Fudge(_) => 2,
+        Roll::Incr(_) => 0,
+        Roll::Custom(..) => {
+            let (low, high) = face_bounds(term);
+            high.saturating_sub(low) as u64
+        },
+        Roll::Modified(_, Modifiers{ successes: Some(_), .. }) => return 2 * u64::from(kept_dice(term)),
+        Roll::Modified(ref base, ref mods) => {
+            let die_span = match explosion(base, mods)
+            {
+                Some((_, _, sides)) => u64::from(sides) * (u64::from(depth.min(mods.explosion_limit())) + 1) - 1,
+                None => term_span(base, depth) / u64::from(dice_count(base)).max(1),
+            };
+            return die_span * u64::from(kept_dice(term));
+        },
+    };
+    u64::from(dice_count(term)) * die_span
+}
+
+/// Return the mean and variance of the total of the term, or None if they
+/// would take too long to compute.
+fn term_moments(term: &Roll) -> Option<(f64, f64)>
+{
+    let count = f64::from(dice_count(term));
+    let die = match *term
+    {
+        Roll::Incr(num) => return Some((f64::from(num), 0.0)),
+        Roll::Dice(_, sides) => {
+            let sides = f64::from(sides);
+            ((sides + 1.0) / 2.0, (sides * sides - 1.0) / 12.0)
+        },
+        Roll::Fudge(_) => (0.0, 2.0 / 3.0),
+        Roll::Modified(_, Modifiers{ keep: Some(_), .. }) => {
+            if term_span(term, DEFAULT_EXPLODE_DEPTH) >= distribution::MAX_OUTCOMES as u64 { return None; }
+            return term_dist(term, DEFAULT_EXPLODE_DEPTH).map(|(dist, _)| dist_moments(&dist));
+        },
+        _ => {
+            let die_span = term_span(term, DEFAULT_EXPLODE_DEPTH) / u64::from(dice_count(term)).max(1);
+            if die_span >= distribution::MAX_OUTCOMES as u64 { return None; }
+            let unmodified = Modifiers::default();
+            let mods = match term
+            {
+                Roll::Modified(_, mods) => mods,
+                _                       => &unmodified,
+            };
+            let die = single_die_dist(term, DEFAULT_EXPLODE_DEPTH).0.into_iter()
+                .map(|(value, p)| (mods.score(value), p))
+                .fold(NumDist::new(), |mut dist, (value, p)| {
+                    *dist.entry(value).or_insert(0.0) += p;
+                    dist
+                });
+            dist_moments(&die)
+        },
+    };
+    Some((die.0 * count, die.1 * count))
+}
+
+/// Return the mean and variance of a distribution.
+fn dist_moments(dist: &NumDist) -> (f64, f64)
+{
+    let mean: f64 = dist.iter().map(|(&value, &p)| value as f64 * p).sum();
+    let variance = dist.iter().map(|(&value, &p)| (value as f64 - mean).powi(2) * p).sum();
+    (mean, variance)
+}
+
+fn die_dist(sides: u32) -> NumDist
+{
+    let prob = 1.0 / f64::from(sides);
+    (1..=i64::from(sides)).map(|face| (face, prob)).collect()
+}
+
+/// Distribution of a die that explodes at most depth times, from the
+/// distribution of its original roll. Each re-roll is reduced by the penalty,
+/// and the first roll by first_penalty. The die may explode limit times, so one
+/// that would explode more than depth times is given the lowest total it could
+/// still reach, which is returned along with the distribution.
+fn explode_dist(first: &NumDist, first_penalty: i64, sides: u32, condition: Condition, penalty: i64, depth: u32,
+    limit: u32) -> (NumDist, Option<i64>)
+{
+    let tail = match depth
+    {
+        0 => None,
+        _ => Some(explode_dist(&die_dist(sides), penalty, sides, condition, penalty, depth - 1, limit - 1)),
+    };
+    let rest = match limit
+    {
+        0 => None,
+        _ => Some(lowest_rerolls(sides, condition, penalty, limit)),
+    };
+    let mut dist = NumDist::new();
+    let mut cut: Option<i64> = None;
+    for (&face, &prob) in first
+    {
+        let value = face - first_penalty;
+        match (&tail, rest)
+        {
+            _ if !condition.matches(face) => *dist.entry(value).or_insert(0.0) += prob,
+            (Some((tail, tail_cut)), _) => {
+                for (rest, prest) in tail
+                {
+                    *dist.entry(value + rest).or_insert(0.0) += prob * prest;
+                }
+                cut = cut.into_iter().chain(tail_cut.map(|tail_cut| value + tail_cut)).min();
+            },
+            (None, Some(rest)) => {
+                *dist.entry(value + rest).or_insert(0.0) += prob;
+                cut = cut.into_iter().chain(Some(value + rest)).min();
+            },
+            (None, None) => *dist.entry(value).or_insert(0.0) += prob,
+        }
+    }
+    (dist, cut)
+}
+
+/// Return the lowest total of the re-rolls of a die that has just exploded,
+/// and may explode limit more times.
+fn lowest_rerolls(sides: u32, condition: Condition, penalty: i64, limit: u32) -> i64
+{
+    let lowest = |matched: bool| (1..=i64::from(sides)).find(|&face| condition.matches(face) == matched);
+    let (unmatched, matched) = (lowest(false), lowest(true));
+    (0..limit).fold(0, |rest, _| {
+        let ends = unmatched.map(|face| face - penalty);
+        let explodes = matched.map(|face| face - penalty + rest);
+        ends.into_iter().chain(explodes).min().unwrap_or(0)
+    })
+}
+
+/// Return the distribution of a single die of the term, along with the lowest
+/// total given to a die that would explode more than depth times, if any.
+fn single_die_dist(term: &Roll, depth: u32) -> (NumDist, Option<i64>)
+{
+    let unmodified = Modifiers::default();
+    let (base, mods) = match term
+    {
+        Roll::Modified(base, mods) => (&**base, mods),
+        term                       => (term, &unmodified),
+    };
+    let faces = modified_face_dist(face_dist(base), mods);
+    match explosion(base, mods)
+    {
+        Some((condition, style, sides)) => {
+            let penalty = if style == ExplodeStyle::Penetrating { 1 } else { 0 };
+            let limit = mods.explosion_limit();
+            explode_dist(&faces, 0, sides, condition, penalty, depth.min(limit), limit)
+        },
+        None => (faces, None),
+    }
+}
+
+/// Return the distribution of a single roll of a die of the term.
+fn face_dist(term: &Roll) -> NumDist
+{
+    match term
+    {
+        Roll::Dice(_, sides) | Roll::ExplodingDice(_, sides) => die_dist(*sides),
+        Roll::Fudge(_) => (-1..=1).map(|face| (face, 1.0 / 3.0)).collect(),
+        Roll::Incr(num) => NumDist::from([(i64::from(*num), 1.0)]),
+        Roll::Modified(base, _) => face_dist(base),
+        Roll::Custom(_, die) => {
+            let prob = 1.0 / die.faces.len() as f64;
+            die.faces.iter().fold(NumDist::new(), |mut dist, face| {
+                *dist.entry(face.value).or_insert(0.0) += prob;
+                dist
+            })
+        },
+    }
+}
+
+/// Apply any reroll and minimum modifiers to the distribution of a single
+/// roll of a die. Re-rolling until a die doesn't match is treated as having no
+/// limit on the number of re-rolls.
+fn modified_face_dist(faces: NumDist, mods: &Modifiers) -> NumDist
+{
+    let mut faces = faces;
+    if let Some(reroll) = mods.reroll
+    {
+        let matched: f64 = faces.iter()
+            .filter(|(&face, _)| reroll.condition.matches(face))
+            .map(|(_, p)| p)
+            .sum();
+        faces = faces.iter()
+            .map(|(&face, &p)| {
+                let kept = if reroll.condition.matches(face) { 0.0 } else { p };
+                (face, if reroll.once { kept + matched * p } else { kept / (1.0 - matched) })
+            })
+            .filter(|&(_, p)| p > 0.0)
+            .collect();
+    }
+    if let Some(minimum) = mods.minimum
+    {
+        faces = faces.into_iter().fold(NumDist::new(), |mut dist, (face, p)| {
+            *dist.entry(face.max(minimum)).or_insert(0.0) += p;
+            dist
+        });
+    }
+    faces
+}
+
+/// Return the distribution of the totals of the term, and how exact it is, or
+/// None if it would take too long to compute.
+fn term_dist(term: &Roll, depth: u32) -> Option<(NumDist, Accuracy)>
+{
+    if let Roll::Incr(num) = *term
+    {
+        return Some((NumDist::from([(i64::from(num), 1.0)]), Accuracy::Exact));
+    }
+    let num = dice_count(term);
+    let (die, cut) = single_die_dist(term, depth);
+    let unmodified = Modifiers::default();
+    let mods = match term
+    {
+        Roll::Modified(_, mods) => mods,
+        _                       => &unmodified,
+    };
+    // Only a total including a die given the lowest total it could reach
+    // differs from the total rolled, and both are at least that die plus the
+    // lowest of the other kept dice. A count of successes is exact if every
+    // total that high scores the same.
+    let kept = kept_dice(term);
+    let accuracy = match cut
+    {
+        None => Accuracy::Exact,
+        Some(_) if kept == 0 => Accuracy::Exact,
+        Some(cut) if mods.successes.is_some() => match mods.scores_all_from(cut)
+        {
+            true  => Accuracy::Exact,
+            false => Accuracy::Approximate,
+        },
+        Some(cut) => Accuracy::ExactBelow(lowest(&die).saturating_mul(i64::from(kept - 1)).saturating_add(cut)),
+    };
+    if let Some(keep) = mods.keep
+    {
+        let (drop, highest) = keep.dropped(num);
+        return keep_dist(&die, num, num - drop, !highest, |value| mods.score(value)).map(|dist| (dist, accuracy));
+    }
+    let die = die.into_iter().fold(NumDist::new(), |mut dist, (value, p)| {
+        *dist.entry(mods.score(value)).or_insert(0.0) += p;
+        dist
+    });
+    // Each die is convolved with the total of the dice before it, which grows
+    // by the span of the die each time.
+    (u64::from(num).pow(2) / 2).checked_mul(dist_span(&die))
+        .and_then(|work| work.checked_mul(die.len() as u64))
+        .filter(|&work| work <= MAX_WORK)?;
+
+    Some(((0..num).fold(NumDist::from([(0, 1.0)]), |acc, _| distribution::convolve(&acc, &die)), accuracy))
+}
+
+/// Return the distribution of the total score of the keep highest (or lowest)
+/// of num dice, or None if it would take too long to compute.
+///
+/// The faces are considered from the first kept to the last, tracking the
+/// number of dice assigned a face so far and the total of the kept dice.
+fn keep_dist<F>(die: &NumDist, num: u32, keep: u32, highest: bool, score: F) -> Option<NumDist>
+    where F: Fn(i64) -> i64
+{
+    let faces: Vec<(i64, f64)> = if highest
+    {
+        die.iter().rev().map(|(&v, &p)| (v, p)).collect()
+    }
+    else
+    {
+        die.iter().map(|(&v, &p)| (v, p)).collect()
+    };
+    (u64::from(num) + 1).checked_pow(2)
+        .and_then(|work| work.checked_mul(faces.len() as u64))
+        .and_then(|work| work.checked_mul(u64::from(keep) + 1))
+        .filter(|&work| work <= MAX_WORK)?;
+
+    let mut states: HashMap<(u32, i64), f64> = HashMap::new();
+    states.insert((0, 0), 1.0);
+    for (value, prob) in faces
+    {
+        let mut next: HashMap<(u32, i64), f64> = HashMap::new();
+        for ((assigned, total), weight) in states
+        {
+            let remaining = num - assigned;
+            let mut ways = 1.0;
+            for count in 0..=remaining
+            {
+                let kept = count.min(keep.saturating_sub(assigned));
+                let key = (assigned + count, total + score(value) * i64::from(kept));
+                *next.entry(key).or_insert(0.0) += weight * ways * prob.powi(count as i32);
+                ways = ways * f64::from(remaining - count) / f64::from(count + 1);
+            }
+        }
+        states = next;
+    }
+    Some(states.into_iter()
+        .filter(|&((assigned, _), p)| assigned == num && p > 0.0)
+        .fold(NumDist::new(), |mut dist, ((_, total), p)| {
+            *dist.entry(total).or_insert(0.0) += p;
+            dist
+        }))
+}
+
+/// Return the lowest value of the distribution.
+fn lowest(dist: &NumDist) -> i64
+{
+    dist.keys().next().copied().unwrap_or(0)
+}
+
+/// Return the highest value of the distribution.
+fn highest(dist: &NumDist) -> i64
+{
+    dist.keys().next_back().copied().unwrap_or(0)
+}
+
+/// Return the accuracy of combining two distributions with an operation that
+/// never decreases as either value increases. A combined total differs from
+/// the one rolled only if one of the values does, so it is at least the value
+/// from which that one is inexact combined with the lowest of the other.
+fn increasing<F>(a: Accuracy, b: Accuracy, alow: i64, blow: i64, op: F) -> Accuracy
+    where F: Fn(i64, i64) -> i64
+{
+    let cut = |accuracy, other| match accuracy
+    {
+        Accuracy::ExactBelow(cut) => Some(op(cut, other)),
+        _                         => None,
+    };
+    match (a, b)
+    {
+        (Accuracy::Approximate, _) | (_, Accuracy::Approximate) => Accuracy::Approximate,
+        _ => cut(a, blow).into_iter().chain(cut(b, alow)).min().map_or(Accuracy::Exact, Accuracy::ExactBelow),
+    }
+}
+
+/// Return the accuracy of combining two distributions with an operation that
+/// may reorder their values, which is only exact if both are.
+pub(super) fn both_exact(a: Accuracy, b: Accuracy) -> Accuracy
+{
+    match (a, b)
+    {
+        (Accuracy::Exact, Accuracy::Exact) => Accuracy::Exact,
+        _                                  => Accuracy::Approximate,
+    }
+}
+
+fn negate(dist: &NumDist) -> NumDist
+{
+    dist.iter().map(|(&n, &p)| (-n, p)).collect()
+}
+
+/// Return the difference between the largest and smallest values of dist.
+fn dist_span(dist: &NumDist) -> u64
+{
+    match (dist.keys().next(), dist.keys().next_back())
+    {
+        (Some(low), Some(high)) => (high - low) as u64,
+        _ => 0,
+    }
+}
+
+/// Return the distribution of the sum, or None if it is too large or would
+/// take too long to compute.
+fn add_dists(a: &NumDist, b: &NumDist) -> Option<NumDist>
+{
+    if dist_span(a) + dist_span(b) >= distribution::MAX_OUTCOMES as u64 { return None; }
+    if (a.len() as u64) * (b.len() as u64) > MAX_WORK { return None; }
+    Some(distribution::convolve(a, b))
+}
+
+/// Return the distribution of combining each pair of values with op, or None
+/// if there are too many pairs.
+fn combine<F>(a: &NumDist, b: &NumDist, op: F) -> Option<NumDist>
+    where F: Fn(i64, i64) -> i64
+{
+    if a.len().saturating_mul(b.len()) > distribution::MAX_OUTCOMES { return None; }
+
+    let mut dist = NumDist::new();
+    for (&x, &px) in a
+    {
+        for (&y, &py) in b
+        {
+            *dist.entry(op(x, y)).or_insert(0.0) += px * py;
+        }
+    }
+    Some(dist)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use spectral::prelude::*;
+
+    use crate::Decider;
+    use crate::Command;
+    use crate::dice::*;
+    use crate::distribution::{Accuracy, Outcome};
+
+    #[test]
+    fn dice_distribution()
+    {
+        let dist = Expr::sum(vec![Roll::Dice(2, 6), Roll::Incr(1)]).distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(11);
+        assert_that!(dist.outcomes()[0].0.clone()).is_equal_to(Outcome::Num(3));
+        assert_that!(dist.probability(&Outcome::Num(8))).is_close_to(6.0 / 36.0, 1e-12);
+        assert_that!(dist.probability_where(|_| true)).is_close_to(1.0, 1e-12);
+    }
+
+    #[test]
+    fn dice_distribution_at_least()
+    {
+        let dist = command("4d6".into()).unwrap().distribution().unwrap();
+        assert_that!(dist.probability_where(|o| matches!(o, Outcome::Num(n) if *n >= 18)))
+            .is_close_to(206.0 / 1296.0, 1e-12);
+    }
+
+    #[test]
+    fn exploding_distribution()
+    {
+        let dist = Expr::sum(vec![Roll::ExplodingDice(1, 6)]).distribution_to_depth(1).unwrap();
+        assert_that!(dist.len()).is_equal_to(11);
+        assert_that!(dist.probability(&Outcome::Num(5))).is_close_to(1.0 / 6.0, 1e-12);
+        assert_that!(dist.probability(&Outcome::Num(6))).is_equal_to(0.0);
+        // Two sixes would explode again, adding at least 1.
+        assert_that!(dist.probability(&Outcome::Num(12))).is_equal_to(0.0);
+        assert_that!(dist.probability(&Outcome::Num(13))).is_close_to(1.0 / 36.0, 1e-12);
+        assert_that!(dist.probability_where(|_| true)).is_close_to(1.0, 1e-12);
+        assert_that!(dist.accuracy()).is_equal_to(Accuracy::ExactBelow(13));
+    }
+
+    #[test]
+    fn exploding_distribution_limit()
+    {
+        // Every roll of a d1 explodes, up to the explosion limit.
+        let dist = Expr::sum(vec![Roll::ExplodingDice(1, 1)]).distribution().unwrap();
+        assert_that!(dist.outcomes().to_vec()).is_equal_to(vec![(Outcome::Num(101), 1.0)]);
+
+        let limited = |max| Expr::Roll(Roll::Modified(Box::new(Roll::ExplodingDice(2, 6)),
+            Modifiers{ max_explosions: Some(max), ..Modifiers::default() }));
+        assert_that!(limited(2).distribution().unwrap().accuracy()).is_equal_to(Accuracy::Exact);
+        assert_that!(limited(2).distribution().unwrap().probability(&Outcome::Num(36))).is_close_to(6.0_f64.powi(-6), 1e-15);
+        assert_that!(limited(6).distribution().unwrap().accuracy()).is_equal_to(Accuracy::ExactBelow(38));
+    }
+
+    #[test]
+    fn exploding_distribution_accuracy()
+    {
+        let accuracy = |text: &str| command(text.into()).unwrap().distribution().unwrap().accuracy();
+        assert_that!(accuracy("2d6 + 3")).is_equal_to(Accuracy::Exact);
+        assert_that!(accuracy("d6! + 3")).is_equal_to(Accuracy::ExactBelow(40));
+        assert_that!(accuracy("3d6!>=5")).is_equal_to(Accuracy::ExactBelow(33));
+        assert_that!(accuracy("4d6!!kh2")).is_equal_to(Accuracy::ExactBelow(38));
+        assert_that!(accuracy("(x6 + 1) * 2")).is_equal_to(Accuracy::ExactBelow(76));
+        assert_that!(accuracy("x6 / 2")).is_equal_to(Accuracy::ExactBelow(18));
+        assert_that!(accuracy("5d10!>=9>=8")).is_equal_to(Accuracy::Exact);
+        assert_that!(accuracy("5d10!<3")).is_equal_to(Accuracy::ExactBelow(21));
+        assert_that!(accuracy("d6!>=5=40")).is_equal_to(Accuracy::Approximate);
+        assert_that!(accuracy("10 - x6")).is_equal_to(Accuracy::Approximate);
+        assert_that!(accuracy("x6 vs 3")).is_equal_to(Accuracy::Approximate);
+    }
+
+    #[test]
+    fn large_distribution()
+    {
+        assert_that!(Expr::sum(vec![Roll::Dice(100, 100)]).distribution()).is_some();
+        assert_that!(Expr::sum(vec![Roll::Dice(20000, 100)]).distribution()).is_none();
+
+        // Too many steps to convolve, although the totals would fit.
+        let start = std::time::Instant::now();
+        let expr: Expr = "10000d100".parse().unwrap();
+        assert_that!(expr.distribution()).is_none();
+        assert_that!(expr.probability_at_least(500_000)).is_none();
+        assert_that!(expr.mean()).is_some();
+        assert_that!("d100000 + d100000".parse::<Expr>().unwrap().distribution()).is_none();
+
+        // The size of a term is only limited by the limits supplied.
+        let limits = Limits{ max_dice: u32::MAX, max_sides: u32::MAX, max_total_dice: u32::MAX, ..Limits::default() };
+        for text in ["4294967295d6kh1", "4294967295d6kh4294967295", "2000000000d2"]
+        {
+            match command_with_limits(text.into(), &limits).unwrap()
+            {
+                Command::RollDice(expr) => assert_that!(expr.distribution()).is_none(),
+                _ => panic!("Wrong command type"),
+            }
+        }
+        assert_that!(start.elapsed()).is_less_than(std::time::Duration::from_secs(1));
+    }
+}
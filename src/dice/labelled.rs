@@ -0,0 +1,594 @@
+use super::{Compare, Condition, Expr, Rolled, DEFAULT_EXPLODE_DEPTH};
+use super::dist::{both_exact, MAX_WORK};
+use crate::{Decision, Decider};
+use crate::decision::{LabelledNum, Versus};
+use crate::distribution::{self, Accuracy, Distribution, Outcome};
+
+use rand::RngCore;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+#[cfg(feature = "serde")]
+use super::serde::{UncheckedContest, UncheckedRepeat, UncheckedRollSet};
+
+type TotalsDist = Vec<(Vec<i64>, f64)>;
+
+/// A dice expression with a label, rolled as part of a RollSet. A repeated
+/// expression is rolled several times, giving a total for each.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Labelled
+{
+    pub label: String,
+    pub expr: Expr,
+    pub repeat: Option<Repeat>,
+}
+
+/// The number of times to roll a repeated expression, and how to sort the
+/// totals.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "UncheckedRepeat"))]
+pub struct Repeat
+{
+    pub count: u32,
+    pub sort: Option<Sort>,
+}
+
+/// The order of the totals of a repeated expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Sort
+{
+    Ascending,
+    Descending,
+}
+
+/// Several labelled dice expressions rolled together, such as the attack and
+/// damage rolls of an attack.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "UncheckedRollSet"))]
+pub struct RollSet
+{
+    pub rolls: Vec<Labelled>,
+}
+
+/// Two dice expressions rolled together and compared. Without a comparison,
+/// the left expression wins, loses, or ties against the right (`d20+3 vs
+/// d20+5`), otherwise the decision is whether the comparison holds
+/// (`2d6 > 7`).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "UncheckedContest"))]
+pub struct Contest
+{
+    pub left: Expr,
+    pub right: Expr,
+    pub compare: Option<Compare>,
+}
+
+impl RollSet
+{
+    /// Return the number of dice rolled by all of the expressions, counting
+    /// each repeat.
+    pub(super) fn total_dice(&self) -> u64
+    {
+        self.rolls.iter()
+            .map(|roll| roll.expr.total_dice().saturating_mul(u64::from(roll.repeat.map_or(1, |repeat| repeat.count))))
+            .fold(0, u64::saturating_add)
+    }
+}
+
+impl Contest
+{
+    /// Return the number of dice rolled by both expressions.
+    pub(super) fn total_dice(&self) -> u64
+    {
+        self.left.total_dice().saturating_add(self.right.total_dice())
+    }
+}
+
+impl Labelled
+{
+    /// Return the label of the total at index among the totals of this
+    /// expression.
+    fn result_label(&self, index: usize) -> String
+    {
+        match self.repeat
+        {
+            None                            => self.label.clone(),
+            Some(_) if self.label.is_empty() => format!("#{}", index + 1),
+            Some(_)                         => format!("{} #{}", self.label, index + 1),
+        }
+    }
+
+    /// Roll the expression as many times as it is repeated, in the order
+    /// requested.
+    fn roll_with(&self, rng: &mut dyn RngCore) -> Vec<Rolled>
+    {
+        let count = self.repeat.map_or(1, |repeat| repeat.count);
+        let mut rolls: Vec<Rolled> = (0..count).map(|_| self.expr.roll_with(rng)).collect();
+        match self.repeat.and_then(|repeat| repeat.sort)
+        {
+            Some(Sort::Ascending)  => rolls.sort_by_key(|rolled| rolled.total),
+            Some(Sort::Descending) => rolls.sort_by_key(|rolled| std::cmp::Reverse(rolled.total)),
+            None                   => (),
+        }
+        rolls
+    }
+
+    /// Return the distribution of the list of totals, and the accuracy of the
+    /// distribution of the expression, or None if it is too large to compute.
+    /// Sorted totals are combined as they are found, so many more repeats can
+    /// be handled.
+    fn totals_dist(&self) -> Option<(TotalsDist, Accuracy)>
+    {
+        let dist = self.expr.distribution()?;
+        let count = self.repeat.map_or(1, |repeat| repeat.count);
+        let sort = self.repeat.and_then(|repeat| repeat.sort);
+        if sort.is_some() && !sorted_fits(dist.len() as u64, count) { return None; }
+
+        let mut totals: TotalsDist = vec![(Vec::new(), 1.0)];
+        for _ in 0..count
+        {
+            if totals.len() * dist.len() > distribution::MAX_OUTCOMES { return None; }
+
+            let next = totals.iter().flat_map(|(list, p)| dist.outcomes().iter().map(move |(total, ptotal)| {
+                let mut list = list.clone();
+                list.push(total.as_num().unwrap_or_default());
+                (list, p * ptotal)
+            }));
+            totals = match sort
+            {
+                None => next.collect(),
+                Some(_) => next
+                    .fold(BTreeMap::new(), |mut merged: BTreeMap<Vec<i64>, f64>, (mut list, p)| {
+                        list.sort_unstable();
+                        *merged.entry(list).or_insert(0.0) += p;
+                        merged
+                    })
+                    .into_iter()
+                    .collect(),
+            };
+        }
+        if sort == Some(Sort::Descending)
+        {
+            totals.iter_mut().for_each(|(list, _)| list.reverse());
+        }
+        Some((totals, dist.accuracy()))
+    }
+}
+
+/// Return true if the sorted lists of count totals, each one of outcomes
+/// totals, number no more than MAX_OUTCOMES and take no more than MAX_WORK
+/// steps to build. There are C(outcomes + k - 1, k) sorted lists of k totals,
+/// and each is built by adding a total to a list of k - 1.
+fn sorted_fits(outcomes: u64, count: u32) -> bool
+{
+    let (mut lists, mut work) = (1u64, 0u64);
+    for k in 1..=u64::from(count)
+    {
+        work = work.saturating_add(lists.saturating_mul(outcomes).saturating_mul(k));
+        lists = match lists.checked_mul(outcomes + k - 1)
+        {
+            Some(product) => product / k,
+            None          => return false,
+        };
+        if lists > distribution::MAX_OUTCOMES as u64 || work > MAX_WORK { return false; }
+    }
+    true
+}
+
+impl fmt::Display for Contest
+{
+    /// Show both expressions separated by the comparison, or by vs, with
+    /// spaces around it so a comparison after dice doesn't count successes.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.compare
+        {
+            Some(compare) => write!(f, "{} {} {}", self.left, compare, self.right),
+            None          => write!(f, "{} vs {}", self.left, self.right),
+        }
+    }
+}
+
+impl Decider for RollSet {
+    /// Roll each of the expressions in order, returning the label, total, and
+    /// description of each.
+    fn decide_with(&self, rng: &mut dyn RngCore) -> Decision
+    {
+        let mut nums = Vec::new();
+        for roll in &self.rolls
+        {
+            nums.extend(roll.roll_with(rng).into_iter().enumerate().map(|(i, rolled)| LabelledNum{
+                label: roll.result_label(i),
+                value: rolled.total,
+                extra: rolled.to_string(),
+            }));
+        }
+        Decision::LabelledNums(nums)
+    }
+
+    /// Return the distribution of the combined totals, each outcome listing
+    /// the label and total of each expression, as in `Outcome::from()`. The
+    /// distribution is approximate if that of any expression is not exact.
+    fn distribution(&self) -> Option<Distribution>
+    {
+        let mut outcomes: Vec<(Vec<String>, f64)> = vec![(Vec::new(), 1.0)];
+        let mut accuracy = Accuracy::Exact;
+        for roll in &self.rolls
+        {
+            let (dist, roll_accuracy) = roll.totals_dist()?;
+            accuracy = both_exact(accuracy, roll_accuracy);
+            if outcomes.len() * dist.len() > distribution::MAX_OUTCOMES { return None; }
+
+            outcomes = outcomes.iter()
+                .flat_map(|(labels, p)| dist.iter().map(move |(totals, ptotals)| {
+                    let mut labels = labels.clone();
+                    labels.extend(totals.iter().enumerate()
+                        .map(|(i, total)| format!("{}: {}", roll.result_label(i), total)));
+                    (labels, p * ptotals)
+                }))
+                .collect();
+        }
+        Some(Distribution::from_outcomes(outcomes.into_iter()
+            .map(|(labels, p)| (Outcome::List(labels), p))
+            .collect()).with_accuracy(accuracy))
+    }
+}
+
+/// Return the side of a contest, labelled with the expression rolled.
+fn contest_side(expr: &Expr, rolled: &Rolled) -> LabelledNum
+{
+    LabelledNum{ label: expr.to_string(), value: rolled.total, extra: rolled.to_string() }
+}
+
+impl Decider for Contest {
+    /// Roll the left expression and then the right, returning whether the
+    /// left wins or whether the comparison holds, along with the total and
+    /// description of each.
+    fn decide_with(&self, rng: &mut dyn RngCore) -> Decision
+    {
+        let left = self.left.roll_with(rng);
+        let right = self.right.roll_with(rng);
+        let (lhs, rhs) = (contest_side(&self.left, &left), contest_side(&self.right, &right));
+        match self.compare
+        {
+            None => Decision::Opposed{ result: Versus::from(left.total.cmp(&right.total)), left: lhs, right: rhs },
+            Some(compare) => Decision::Compared{
+                value: Condition{ compare, value: right.total }.matches(left.total),
+                left: lhs,
+                right: rhs,
+            },
+        }
+    }
+
+    /// Return the distribution of wins, losses, and ties, or of the comparison
+    /// being true or false, limiting exploding dice to DEFAULT_EXPLODE_DEPTH
+    /// explosions. The distribution is approximate if either expression's is
+    /// not exact.
+    fn distribution(&self) -> Option<Distribution>
+    {
+        let (left, lacc) = self.left.num_dist(DEFAULT_EXPLODE_DEPTH)?;
+        let (right, racc) = self.right.num_dist(DEFAULT_EXPLODE_DEPTH)?;
+        if left.len().saturating_mul(right.len()) > distribution::MAX_OUTCOMES { return None; }
+
+        let outcome = |x: i64, y: i64| match self.compare
+        {
+            None          => Outcome::Text(Versus::from(x.cmp(&y)).to_string()),
+            Some(compare) => Outcome::Bool(Condition{ compare, value: y }.matches(x)),
+        };
+        let mut outcomes: Vec<(Outcome, f64)> = Vec::new();
+        for (&x, &px) in &left
+        {
+            for (&y, &py) in &right
+            {
+                let outcome = outcome(x, y);
+                match outcomes.iter_mut().find(|(o, _)| *o == outcome)
+                {
+                    Some((_, p)) => *p += px * py,
+                    None         => outcomes.push((outcome, px * py)),
+                }
+            }
+        }
+        Some(Distribution::from_outcomes(outcomes).with_accuracy(both_exact(lacc, racc)))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use spectral::prelude::*;
+    use rand::rngs::mock::StepRng;
+
+    use crate::Decision;
+    use crate::DecisionAssertions;
+    use crate::Decider;
+    use crate::Command;
+    use crate::decision::{LabelledNum, Versus};
+    use crate::dice::*;
+    use crate::dice::tests::assert_fair;
+    use crate::distribution::Outcome;
+    use crate::rng;
+
+    fn labelled(label: &str, roll: Roll) -> Labelled
+    {
+        Labelled{ label: label.into(), expr: Expr::sum(vec![roll]), repeat: None }
+    }
+
+    #[test]
+    fn command_labelled()
+    {
+        assert_that!(command("hit: d20+5; dmg: 2d6+3".into())).is_ok_containing(Command::RollDiceSet(RollSet{
+            rolls: vec![
+                Labelled{ label: "hit".into(), expr: Expr::sum(vec![Roll::Dice(1, 20), Roll::Incr(5)]), repeat: None },
+                Labelled{ label: "dmg".into(), expr: Expr::sum(vec![Roll::Dice(2, 6), Roll::Incr(3)]), repeat: None },
+            ]
+        }));
+        assert_that!(command("d20, 2d6".into())).is_ok_containing(Command::RollDiceSet(RollSet{
+            rolls: vec![labelled("d20", Roll::Dice(1, 20)), labelled("2d6", Roll::Dice(2, 6))]
+        }));
+        assert_that!(command(" to hit : d20".into())).is_ok_containing(Command::RollDiceSet(RollSet{
+            rolls: vec![labelled("to hit", Roll::Dice(1, 20))]
+        }));
+    }
+
+    #[test]
+    fn command_labelled_errors()
+    {
+        assert_that!(command("hit: d20;".into())).is_err_containing(Error::DiceMissingExpr);
+        assert_that!(command("hit:".into())).is_err_containing(Error::DiceMissingExpr);
+        assert_that!(command(": d20".into())).is_err_containing(Error::UnparseableTerm(ParseError{
+            expr: ": d20".into(), span: 0..1, problem: ParseProblem::Expected("a label before ':'"),
+        }));
+        assert_that!(command("hit: d20; dmg: 2d".into())).is_err_containing(Error::UnparseableTerm(ParseError{
+            expr: "hit: d20; dmg: 2d".into(), span: 17..17, problem: ParseProblem::Expected("the number of sides"),
+        }));
+    }
+
+    #[test]
+    fn labelled_decision()
+    {
+        let cmd = command("hit: d20+5; dmg: 2d6+3".into()).unwrap();
+        let decision = cmd.decide_with(&mut StepRng::new(0, 0));
+        assert_that!(decision).is_labelled_nums_decision();
+        assert_that!(decision).is_equal_to(Decision::LabelledNums(vec![
+            LabelledNum{ label: "hit".into(), value: 6, extra: "1d20(1) + 5".into() },
+            LabelledNum{ label: "dmg".into(), value: 5, extra: "2d6(1+1) + 3".into() },
+        ]));
+        assert_that!(decision.to_string())
+            .is_equal_to("hit: 6: '1d20(1) + 5', dmg: 5: '2d6(1+1) + 3'".to_string());
+        assert_that!(Outcome::from(&decision))
+            .is_equal_to(Outcome::List(vec!["hit: 6".into(), "dmg: 5".into()]));
+    }
+
+    #[test]
+    fn labelled_distribution()
+    {
+        let dist = command("a: d2; b: d3".into()).unwrap().distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(6);
+        assert_that!(dist.probability(&Outcome::List(vec!["a: 2".into(), "b: 3".into()])))
+            .is_close_to(1.0 / 6.0, 1e-12);
+
+        assert_that!(command("a: d1000; b: d1000; c: d1000".into()).unwrap().distribution()).is_none();
+
+        assert_fair("hit: d20+5; dmg: 2d6+3", 20000);
+    }
+
+    #[test]
+    fn command_repeat()
+    {
+        let keep = Roll::Modified(Box::new(Roll::Dice(4, 6)), Modifiers{ keep: Some(Keep::Highest(3)), ..Modifiers::default() });
+        assert_that!(command("6#4d6kh3".into())).is_ok_containing(Command::RollDiceSet(RollSet{
+            rolls: vec![Labelled{ label: String::new(), expr: Expr::sum(vec![keep]), repeat: Some(Repeat{ count: 6, sort: None }) }]
+        }));
+        assert_that!(command("stats: 3 # 3d6 SD; hp: d8".into())).is_ok_containing(Command::RollDiceSet(RollSet{
+            rolls: vec![
+                Labelled{ label: "stats".into(), expr: Expr::sum(vec![Roll::Dice(3, 6)]),
+                    repeat: Some(Repeat{ count: 3, sort: Some(Sort::Descending) }) },
+                labelled("hp", Roll::Dice(1, 8)),
+            ]
+        }));
+        assert_that!(command("2#d20\tsa".into())).is_ok_containing(Command::RollDiceSet(RollSet{
+            rolls: vec![Labelled{ label: String::new(), expr: Expr::sum(vec![Roll::Dice(1, 20)]),
+                repeat: Some(Repeat{ count: 2, sort: Some(Sort::Ascending) }) }]
+        }));
+
+        // A die name ending in sd is not a sort order.
+        let crisd: CustomDie = "Crisd: 1, 2".parse().unwrap();
+        let repeat = |sort| Command::RollDiceSet(RollSet{
+            rolls: vec![Labelled{ label: String::new(), expr: Expr::Roll(Roll::Custom(3, crisd.clone())),
+                repeat: Some(Repeat{ count: 2, sort }) }]
+        });
+        let dice = [crisd.clone()];
+        assert_that!(command_with_dice("2#3dCrisd".into(), &Limits::default(), &dice)).is_ok_containing(repeat(None));
+        assert_that!(command_with_dice("2#3dCrisd sd".into(), &Limits::default(), &dice))
+            .is_ok_containing(repeat(Some(Sort::Descending)));
+    }
+
+    #[test]
+    fn command_repeat_errors()
+    {
+        let bad_repeat = |expr: &str, span, expected| Error::UnparseableTerm(ParseError{
+            expr: expr.into(), span, problem: ParseProblem::Expected(expected),
+        });
+        assert_that!(command("0#d6".into())).is_err_containing(bad_repeat("0#d6", 0..1, "at least one repeat"));
+        assert_that!(command("1001#d6".into())).is_err_containing(bad_repeat("1001#d6", 0..4, "a smaller repeat count"));
+        assert_that!(command("x#d6".into())).is_err_containing(bad_repeat("x#d6", 0..1, "a repeat count"));
+        assert_that!(command("#d6".into())).is_err_containing(bad_repeat("#d6", 0..0, "a repeat count"));
+        assert_that!(command("hit: 0 #d6".into())).is_err_containing(bad_repeat("hit: 0 #d6", 5..6, "at least one repeat"));
+        assert_that!(command("3#".into())).is_err_containing(Error::DiceMissingExpr);
+        assert_that!(command("3# sd".into())).is_err_containing(Error::DiceMissingExpr);
+        assert_that!(command("2#d20sa".into())).is_err_containing(Error::UnparseableTerm(ParseError{
+            expr: "2#d20sa".into(), span: 5..7, problem: ParseProblem::Expected("an operator or the end of the expression"),
+        }));
+        assert_that!(command("3#d6#d6".into())).is_err_containing(Error::UnparseableTerm(ParseError{
+            expr: "3#d6#d6".into(), span: 4..5, problem: ParseProblem::Expected("an operator or the end of the expression"),
+        }));
+    }
+
+    #[test]
+    fn repeat_decision()
+    {
+        let cmd = command("3#d6+1; dmg: d4".into()).unwrap();
+        let label = |label: &str, value, extra: &str| LabelledNum{ label: label.into(), value, extra: extra.into() };
+        assert_that!(cmd.decide_with(&mut StepRng::new(0, 0))).is_equal_to(Decision::LabelledNums(vec![
+            label("#1", 2, "1d6(1) + 1"),
+            label("#2", 2, "1d6(1) + 1"),
+            label("#3", 2, "1d6(1) + 1"),
+            label("dmg", 1, "1d4(1)"),
+        ]));
+
+        for seed in 0..10
+        {
+            match command("stats: 6#4d6kh3 sd".into()).unwrap().decide_with(&mut rng::seeded(seed))
+            {
+                Decision::LabelledNums(nums) => {
+                    assert_that!(nums.len()).is_equal_to(6);
+                    assert_that!(nums[0].label.clone()).is_equal_to("stats #1".to_string());
+                    assert_that!(nums.windows(2).all(|pair| pair[0].value >= pair[1].value)).is_true();
+                },
+                _ => panic!("Wrong decision type"),
+            }
+        }
+    }
+
+    #[test]
+    fn repeat_distribution()
+    {
+        let list = |items: &[&str]| Outcome::List(items.iter().map(|&item| item.into()).collect());
+        let dist = command("2#d2".into()).unwrap().distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(4);
+        assert_that!(dist.probability(&list(&["#1: 2", "#2: 1"]))).is_close_to(0.25, 1e-12);
+
+        let dist = command("2#d2 sd".into()).unwrap().distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(3);
+        assert_that!(dist.probability(&list(&["#1: 2", "#2: 1"]))).is_close_to(0.5, 1e-12);
+        assert_that!(dist.probability(&list(&["#1: 1", "#2: 2"]))).is_equal_to(0.0);
+
+        let dist = command("6#4d6kh3 sa".into()).unwrap().distribution().unwrap();
+        let total: f64 = dist.outcomes().iter().map(|(_, p)| p).sum();
+        assert_that!(total).is_close_to(1.0, 1e-9);
+        assert_that!(command("6#4d6kh3".into()).unwrap().distribution()).is_none();
+
+        assert_fair("3#2d6 sd", 20000);
+    }
+
+    #[test]
+    fn large_sorted_repeat_distribution()
+    {
+        let start = std::time::Instant::now();
+        assert_that!(command("1000#d6 sa".into()).unwrap().distribution()).is_none();
+        assert_that!(command("100#d2 sd".into()).unwrap().distribution()).is_some();
+        assert_that!(command("20#3d6 sa".into()).unwrap().distribution()).is_none();
+        assert_that!(start.elapsed()).is_less_than(std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn command_contest()
+    {
+        let d20_plus = |n| Expr::sum(vec![Roll::Dice(1, 20), Roll::Incr(n)]);
+        assert_that!(command("d20+3 vs d20+5".into())).is_ok_containing(Command::RollContest(Contest{
+            left: d20_plus(3), right: d20_plus(5), compare: None
+        }));
+        for text in ["2d6 > 7", "(2d6) > 7", "(2d6)>7", " 2d6\t>  7 "]
+        {
+            assert_that!(command(text.into())).is_ok_containing(Command::RollContest(Contest{
+                left: Expr::Roll(Roll::Dice(2, 6)), right: Expr::Roll(Roll::Incr(7)), compare: Some(Compare::Greater)
+            }));
+        }
+        assert_that!(command("2d6+1 >7".into())).is_ok_containing(Command::RollContest(Contest{
+            left: Expr::sum(vec![Roll::Dice(2, 6), Roll::Incr(1)]), right: Expr::Roll(Roll::Incr(7)),
+            compare: Some(Compare::Greater)
+        }));
+        assert_that!(command("(d6)<=2".into())).is_ok_containing(Command::RollContest(Contest{
+            left: Expr::Roll(Roll::Dice(1, 6)), right: Expr::Roll(Roll::Incr(2)), compare: Some(Compare::LessEqual)
+        }));
+        assert_that!(command("2d6>7".into())).is_ok_containing(Command::RollDice(Expr::Roll(Roll::Modified(
+            Box::new(Roll::Dice(2, 6)),
+            Modifiers{ successes: Some(Condition{ compare: Compare::Greater, value: 7 }), ..Modifiers::default() }
+        ))));
+        assert_that!(command("(2d6) >".into())).is_err_containing(Error::UnparseableTerm(ParseError{
+            expr: "(2d6) >".into(), span: 7..7, problem: ParseProblem::Expected("a number, dice, or '('"),
+        }));
+        let ambiguous = "no space before a count of successes (2d6>7), or spaces around a comparison after dice (2d6 > 7)";
+        assert_that!(command("2d6 >=7".into())).is_err_containing(Error::UnparseableTerm(ParseError{
+            expr: "2d6 >=7".into(), span: 4..6, problem: ParseProblem::Expected(ambiguous),
+        }));
+        assert_that!(command("1 + 4d6kh3 < 2d6".into())).is_ok();
+        assert_that!(command("2d6>7 > 1".into())).is_ok();
+        assert_that!(command("d20 vs d20 vs d20".into())).is_err_containing(Error::UnparseableTerm(ParseError{
+            expr: "d20 vs d20 vs d20".into(), span: 11..13, problem: ParseProblem::Expected("an operator or the end of the expression"),
+        }));
+        assert_that!(command("d20 vs 100000000000000d6".into())).is_err();
+        let overflow = "d4 vs 10000d1000000 * 10000d1000000 * 1000d1000000";
+        assert_that!(command(overflow.into())).is_err_containing(Error::UnparseableTerm(ParseError{
+            expr: overflow.into(), span: 6..overflow.len(),
+            problem: ParseProblem::Invalid(Box::new(Error::DiceOverflow(overflow[6..].into()))),
+        }));
+        assert_that!(command("d6: d20 vs d20".into())).is_err();
+    }
+
+    #[test]
+    fn contest_display()
+    {
+        let contest = |text: &str| match command(text.into()).unwrap()
+        {
+            Command::RollContest(contest) => contest.to_string(),
+            _ => panic!("Wrong command type"),
+        };
+        assert_that!(contest("D20+3 VS d20 + 5")).is_equal_to("1d20 + 3 vs 1d20 + 5".to_string());
+        assert_that!(contest("(2d6) >= 7")).is_equal_to("2d6 >= 7".to_string());
+        assert_that!(contest("(d6)=d6")).is_equal_to("1d6 = 1d6".to_string());
+        assert_that!(contest("-(d6 + 1) < 2d6")).is_equal_to("-(1d6 + 1) < 2d6".to_string());
+        assert_that!(contest("(2d6>7) > 1")).is_equal_to("2d6>7 > 1".to_string());
+    }
+
+    #[test]
+    fn decide_contest()
+    {
+        let decide = |text: &str| command(text.into()).unwrap().decide_with(&mut StepRng::new(0, 0));
+        let side = |label: &str, value, extra: &str| LabelledNum{ label: label.into(), value, extra: extra.into() };
+        assert_that!(decide("d20+3 vs d20+5")).is_equal_to(Decision::Opposed{
+            result: Versus::Lose,
+            left: side("1d20 + 3", 4, "1d20(1) + 3"),
+            right: side("1d20 + 5", 6, "1d20(1) + 5"),
+        });
+        assert_that!(decide("d20+5 vs d20+3").to_string())
+            .is_equal_to("Win: 1d20 + 5: 6: '1d20(1) + 5' vs 1d20 + 3: 4: '1d20(1) + 3'".to_string());
+        assert_that!(decide("2d6 vs 2")).is_equal_to(Decision::Opposed{
+            result: Versus::Tie,
+            left: side("2d6", 2, "2d6(1+1)"),
+            right: side("2", 2, "2"),
+        });
+        assert_that!(decide("2d6 > 7")).is_equal_to(Decision::Compared{
+            value: false,
+            left: side("2d6", 2, "2d6(1+1)"),
+            right: side("7", 7, "7"),
+        });
+        assert_that!(decide("(3d6) <= 2d6 + 1")).is_equal_to(Decision::Compared{
+            value: true,
+            left: side("3d6", 3, "3d6(1+1+1)"),
+            right: side("2d6 + 1", 3, "2d6(1+1) + 1"),
+        });
+    }
+
+    #[test]
+    fn contest_distribution()
+    {
+        let dist = command("d6 vs d6".into()).unwrap().distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(3);
+        assert_that!(dist.probability(&Outcome::Text("Win".into()))).is_close_to(5.0 / 12.0, 1e-12);
+        assert_that!(dist.probability(&Outcome::Text("Lose".into()))).is_close_to(5.0 / 12.0, 1e-12);
+        assert_that!(dist.probability(&Outcome::Text("Tie".into()))).is_close_to(1.0 / 6.0, 1e-12);
+
+        let dist = command("2d6 > 7".into()).unwrap().distribution().unwrap();
+        assert_that!(dist.probability(&Outcome::Bool(true))).is_close_to(15.0 / 36.0, 1e-12);
+        assert_that!(dist.probability(&Outcome::Bool(false))).is_close_to(21.0 / 36.0, 1e-12);
+
+        let dist = command("d20 vs 1".into()).unwrap().distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(2);
+        assert_that!(command("10000d1000 vs d6".into()).unwrap().distribution()).is_none();
+    }
+}
@@ -0,0 +1,864 @@
+use super::{Compare, Condition, Contest, CustomDie, Explode, ExplodeStyle, Expr, Keep, Limits, Modifiers, ParseError,
+            ParseProblem, Reroll, Roll, Rounding, TermPart};
+use crate::{Command, Error};
+
+use std::ops::Range;
+
+/// The most parens and minus signs that may enclose a part of an expression.
+const MAX_NESTING: usize = 100;
+
+/// Parse the text of a dice expression into an Expr.
+///
+/// ```text
+/// sum     := product (('+' | '-') product)*
+/// product := unary (('*' | '/' | '/^' | '/~') unary)*
+/// unary   := '-' unary | atom
+/// atom    := '(' sum ')' | [count] ('d' | 'x') sides modifier* | number
+/// sides   := number | '%' | 'F' | custom die name
+/// modifier := ('r' | 'ro') condition | 'min' number
+///           | ('!' | '!!' | '!p') [condition]
+///           | ('k' | 'kh' | 'kl' | 'dh' | 'dl') [number]
+///           | compare number | 'f' condition
+/// condition := [compare] number
+/// compare := '=' | '<' | '<=' | '>' | '>='
+/// contest := sum [('vs' | compare) sum]
+/// ```
+///
+/// A compare written directly after dice counts successes (`2d6>7`), so a
+/// contest comparing dice needs whitespace on both sides of the compare
+/// (`2d6 > 7`), or parens around the dice (`(2d6)>7`).
+pub(super) fn parse(text: &str, limits: &Limits) -> crate::Result<Expr>
+{
+    parse_with_dice(text, limits, &[])
+}
+
+/// Parse the text of a dice expression that may roll the custom dice.
+pub(super) fn parse_with_dice(text: &str, limits: &Limits, dice: &[CustomDie]) -> crate::Result<Expr>
+{
+    let mut parser = Parser{ text, pos: 0, limits, dice, counting_end: None, depth: 0, terms: 0 };
+    let expr = parser.sum()?;
+    if parser.peek().is_some()
+    {
+        return Err(parser.expected("an operator or the end of the expression"));
+    }
+    check_overflow(expr, text, 0..text.len())
+}
+
+/// Parse the text of a dice expression into a RollDice command, or of two
+/// expressions rolled against each other or compared into a RollContest.
+pub(super) fn parse_command(text: &str, limits: &Limits, dice: &[CustomDie]) -> crate::Result<Command>
+{
+    let mut parser = Parser{ text, pos: 0, limits, dice, counting_end: None, depth: 0, terms: 0 };
+    let left = parser.sum()?;
+    let middle = parser.pos;
+    let compare = match parser.peek()
+    {
+        None => return Ok(Command::RollDice(check_overflow(left, text, 0..text.len())?)),
+        Some(_) if parser.eat_word("vs") => None,
+        Some(_) => {
+            let after_dice = parser.counting_end.filter(|&end| text[end..middle].trim().is_empty());
+            let compare = parser.compare().ok_or_else(|| parser.expected("an operator or the end of the expression"))?;
+            let spaced = |pos: usize| text[pos..].starts_with(char::is_whitespace);
+            if after_dice.is_some_and(|end| !spaced(end) || !spaced(parser.pos))
+            {
+                return Err(located(text, middle..parser.pos, ParseProblem::Expected(
+                    "no space before a count of successes (2d6>7), or spaces around a comparison after dice (2d6 > 7)")));
+            }
+            Some(compare)
+        },
+    };
+    let start = parser.pos;
+    let right = parser.sum()?;
+    if parser.peek().is_some()
+    {
+        return Err(parser.expected("an operator or the end of the expression"));
+    }
+    Ok(Command::RollContest(Contest{
+        left: check_overflow(left, text, 0..middle)?,
+        right: check_overflow(right, text, start..text.len())?,
+        compare,
+    }))
+}
+
+/// Return the expression parsed from the span of text, unless its total could
+/// overflow.
+fn check_overflow(expr: Expr, text: &str, span: Range<usize>) -> crate::Result<Expr>
+{
+    if expr.reachable_bounds().is_none()
+    {
+        let span = trimmed(text, span);
+        let error = Error::DiceOverflow(text[span.clone()].to_owned());
+        return Err(located(text, span, ParseProblem::Invalid(Box::new(error))));
+    }
+    Ok(expr)
+}
+
+/// Return the span of text without any leading or trailing whitespace.
+pub(super) fn trimmed(text: &str, span: Range<usize>) -> Range<usize>
+{
+    let part = &text[span.clone()];
+    let start = span.start + (part.len() - part.trim_start().len());
+    start..start + part.trim().len()
+}
+
+/// Return the error found parsing the part of text starting at offset, located
+/// in the whole of text.
+pub(super) fn relocate(error: Error, text: &str, offset: usize) -> Error
+{
+    match error
+    {
+        Error::UnparseableTerm(ParseError{ span, problem, .. }) =>
+            located(text, span.start + offset..span.end + offset, problem),
+        error => error,
+    }
+}
+
+/// Return an error for the span of text.
+pub(super) fn located(text: &str, span: Range<usize>, problem: ParseProblem) -> Error
+{
+    Error::UnparseableTerm(ParseError{ expr: text.to_owned(), span, problem })
+}
+
+struct Parser<'a>
+{
+    text: &'a str,
+    pos: usize,
+    limits: &'a Limits,
+    dice: &'a [CustomDie],
+    /// Where the last term parsed ended, if it was dice that could have
+    /// counted successes.
+    counting_end: Option<usize>,
+    /// How many parens and minus signs enclose the position.
+    depth: usize,
+    /// How many terms have been parsed.
+    terms: u32,
+}
+
+impl<'a> Parser<'a>
+{
+    /// Return the next character without skipping whitespace.
+    fn peek_raw(&self) -> Option<char>
+    {
+        self.text[self.pos..].chars().next()
+    }
+
+    /// Skip any whitespace and return the next character.
+    fn peek(&mut self) -> Option<char>
+    {
+        while let Some(c) = self.peek_raw().filter(|c| c.is_whitespace())
+        {
+            self.pos += c.len_utf8();
+        }
+        self.peek_raw()
+    }
+
+    fn bump(&mut self)
+    {
+        if let Some(c) = self.peek_raw()
+        {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    /// Consume the next character if it is the expected one.
+    fn eat(&mut self, expected: char) -> bool
+    {
+        if self.peek() == Some(expected)
+        {
+            self.bump();
+            return true;
+        }
+        false
+    }
+
+    /// Consume the word if the text continues with it, ignoring case.
+    fn eat_word(&mut self, word: &str) -> bool
+    {
+        let rest = &self.text[self.pos..];
+        if rest.len() >= word.len() && rest.is_char_boundary(word.len())
+            && rest[..word.len()].eq_ignore_ascii_case(word)
+        {
+            self.pos += word.len();
+            return true;
+        }
+        false
+    }
+
+    /// Return a syntax error at the next token, saying what was expected
+    /// there. A token is a run of letters and digits, or a single character.
+    fn expected(&mut self, expected: &'static str) -> Error
+    {
+        let start = match self.peek()
+        {
+            Some(_) => self.pos,
+            None    => self.text.len(),
+        };
+        let rest = &self.text[start..];
+        let len = match rest.find(|c: char| !c.is_ascii_alphanumeric())
+        {
+            Some(0) => rest.chars().next().map_or(0, char::len_utf8),
+            Some(n) => n,
+            None    => rest.len(),
+        };
+        located(self.text, start..start + len, ParseProblem::Expected(expected))
+    }
+
+    /// Return an error for the span, which is well formed but describes dice
+    /// that can't be rolled.
+    fn invalid(&self, span: Range<usize>, error: Error) -> Error
+    {
+        located(self.text, span, ParseProblem::Invalid(Box::new(error)))
+    }
+
+    fn sum(&mut self) -> crate::Result<Expr>
+    {
+        let mut expr = self.product()?;
+        loop
+        {
+            expr = match self.peek()
+            {
+                Some('+') => { self.bump(); Expr::Add(Box::new(expr), Box::new(self.product()?)) },
+                Some('-') => { self.bump(); Expr::Sub(Box::new(expr), Box::new(self.product()?)) },
+                _ => return Ok(expr),
+            };
+        }
+    }
+
+    fn product(&mut self) -> crate::Result<Expr>
+    {
+        let mut expr = self.unary()?;
+        loop
+        {
+            expr = match self.peek()
+            {
+                Some('*') => { self.bump(); Expr::Mul(Box::new(expr), Box::new(self.unary()?)) },
+                Some('/') => {
+                    self.bump();
+                    let rounding = match self.peek_raw()
+                    {
+                        Some('^') => { self.bump(); Rounding::Up },
+                        Some('~') => { self.bump(); Rounding::Nearest },
+                        _         => Rounding::Down,
+                    };
+                    let start = self.pos;
+                    let divisor = self.unary()?;
+                    let (low, high) = divisor.bounds();
+                    if low <= 0 && high >= 0
+                    {
+                        let span = trimmed(self.text, start..self.pos);
+                        return Err(self.invalid(span.clone(), Error::DiceDivideByZero(self.text[span].to_owned())));
+                    }
+                    Expr::Div(Box::new(expr), Box::new(divisor), rounding)
+                },
+                _ => return Ok(expr),
+            };
+        }
+    }
+
+    /// Enter a paren or minus sign at the next character, unless that would
+    /// nest the expression too deeply.
+    fn nest(&mut self) -> crate::Result<()>
+    {
+        if self.depth >= MAX_NESTING
+        {
+            return Err(self.expected("less nesting"));
+        }
+        self.depth += 1;
+        self.bump();
+        Ok(())
+    }
+
+    fn unary(&mut self) -> crate::Result<Expr>
+    {
+        if self.peek() == Some('-')
+        {
+            self.nest()?;
+            let expr = self.unary()?;
+            self.depth -= 1;
+            return Ok(Expr::Neg(Box::new(expr)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> crate::Result<Expr>
+    {
+        match self.peek()
+        {
+            Some('(') => {
+                self.nest()?;
+                let expr = self.sum()?;
+                if !self.eat(')')
+                {
+                    return Err(self.expected("')'"));
+                }
+                self.depth -= 1;
+                Ok(expr)
+            },
+            Some(c) if c.is_ascii_digit() || "dDxX".contains(c) => {
+                let start = self.pos;
+                let expr = self.dice_or_number()?;
+                self.terms += 1;
+                if self.terms > self.limits.max_terms
+                {
+                    return Err(self.invalid(start..self.pos, Error::DiceTooManyTerms(self.limits.max_terms)));
+                }
+                Ok(expr)
+            },
+            _ => Err(self.expected("a number, dice, or '('")),
+        }
+    }
+
+    fn dice_or_number(&mut self) -> crate::Result<Expr>
+    {
+        self.counting_end = None;
+        let start = self.pos;
+        let count = self.number()?;
+        let count_end = self.pos;
+        let exploding = match self.peek_raw()
+        {
+            Some('d') | Some('D') => false,
+            Some('x') | Some('X') => true,
+            _ => return count.map(|n| Expr::Roll(Roll::Incr(n))).ok_or_else(|| self.expected("dice")),
+        };
+        self.bump();
+        let sides_start = self.pos;
+        let (sides, custom) = match self.peek_raw()
+        {
+            Some('%') => { self.bump(); (Some(100), None) },
+            Some(c) if c.is_ascii_alphabetic() && !exploding => (None, self.named_die()?),
+            _ => (Some(self.number()?.ok_or_else(|| self.expected("the number of sides"))?), None),
+        };
+        let sides_end = self.pos;
+        let count = count.unwrap_or(1);
+        let roll = match (sides, custom)
+        {
+            (Some(sides), _) if exploding => Roll::ExplodingDice(count, sides),
+            (Some(sides), _)  => Roll::Dice(count, sides),
+            (None, Some(die)) => Roll::Custom(count, die),
+            (None, None)      => Roll::Fudge(count),
+        };
+        let mut mods = self.modifiers()?;
+        if let Err((part, problem)) = super::check_term(&roll, &mods, self.limits)
+        {
+            let span = match part
+            {
+                TermPart::Count if count_end > start => start..count_end,
+                TermPart::Count                      => start..sides_end,
+                TermPart::Sides                      => sides_start..sides_end,
+                TermPart::Modifiers                  => sides_end..self.pos,
+            };
+            return Err(located(self.text, span, problem));
+        }
+        if super::explosion(&roll, &mods).is_some()
+        {
+            mods.max_explosions = self.limits.max_explosions;
+        }
+        if mods.successes.is_none() && !matches!(roll, Roll::Custom(..))
+        {
+            self.counting_end = Some(self.pos);
+        }
+        if mods == Modifiers::default()
+        {
+            return Ok(Expr::Roll(roll));
+        }
+        Ok(Expr::Roll(Roll::Modified(Box::new(roll), mods)))
+    }
+
+    /// Parse the name of a custom die, returning None for a Fudge die. The
+    /// whole name must match one of the dice, ignoring case.
+    fn named_die(&mut self) -> crate::Result<Option<CustomDie>>
+    {
+        let start = self.pos;
+        let rest = &self.text[start..];
+        let name: String = rest.chars().take_while(|c| c.is_ascii_alphanumeric() || *c == '_').collect();
+        let custom = self.dice.iter().find(|die| die.name.eq_ignore_ascii_case(&name));
+        if let Some(die) = custom
+        {
+            self.pos += die.name.len();
+            return Ok(Some(die.clone()));
+        }
+        if self.eat_word("f")
+        {
+            return Ok(None);
+        }
+        let span = start..start + name.len();
+        Err(self.invalid(span, Error::DiceBadType(name)))
+    }
+
+    /// Parse any modifiers written directly after the dice.
+    fn modifiers(&mut self) -> crate::Result<Modifiers>
+    {
+        let mut mods = Modifiers::default();
+        loop
+        {
+            let start = self.pos;
+            let repeated =
+                if let Some(keep) = self.keep()? { mods.keep.replace(keep).is_some() }
+                else if let Some(reroll) = self.reroll()? { mods.reroll.replace(reroll).is_some() }
+                else if let Some(minimum) = self.minimum()? { mods.minimum.replace(minimum).is_some() }
+                else if let Some(explode) = self.explode()? { mods.explode.replace(explode).is_some() }
+                else if let Some(success) = self.successes()? { mods.successes.replace(success).is_some() }
+                else if self.eat_word("f") { mods.failures.replace(self.condition()?).is_some() }
+                else { return Ok(mods); };
+            if repeated
+            {
+                return Err(located(self.text, start..self.pos, ParseProblem::Expected("each modifier at most once")));
+            }
+        }
+    }
+
+    /// Parse a keep or drop modifier, if one is next.
+    fn keep(&mut self) -> crate::Result<Option<Keep>>
+    {
+        let keep: fn(u32) -> Keep =
+            if self.eat_word("kh")      { Keep::Highest }
+            else if self.eat_word("kl") { Keep::Lowest }
+            else if self.eat_word("dh") { Keep::DropHighest }
+            else if self.eat_word("dl") { Keep::DropLowest }
+            else if self.eat_word("k")  { Keep::Highest }
+            else { return Ok(None); };
+        Ok(Some(keep(self.number()?.unwrap_or(1))))
+    }
+
+    /// Parse a reroll modifier, if one is next.
+    fn reroll(&mut self) -> crate::Result<Option<Reroll>>
+    {
+        let once = if self.eat_word("ro") { true }
+            else if self.eat_word("r") { false }
+            else { return Ok(None); };
+        Ok(Some(Reroll{ condition: self.condition()?, once }))
+    }
+
+    /// Parse an explode modifier, if one is next.
+    fn explode(&mut self) -> crate::Result<Option<Explode>>
+    {
+        if !self.eat_word("!")
+        {
+            return Ok(None);
+        }
+        let style = if self.eat_word("!") { ExplodeStyle::Compounding }
+            else if self.eat_word("p") { ExplodeStyle::Penetrating }
+            else { ExplodeStyle::Chained };
+        let condition = match self.compare()
+        {
+            Some(compare) => Some(self.condition_value(compare)?),
+            None if matches!(self.peek_raw(), Some(c) if c.is_ascii_digit()) =>
+                Some(self.condition_value(Compare::Equal)?),
+            None => None,
+        };
+        Ok(Some(Explode{ condition, style }))
+    }
+
+    /// Parse a minimum modifier, if one is next.
+    fn minimum(&mut self) -> crate::Result<Option<i64>>
+    {
+        if !self.eat_word("min")
+        {
+            return Ok(None);
+        }
+        let value = self.number()?.ok_or_else(|| self.expected("a number"))?;
+        Ok(Some(i64::from(value)))
+    }
+
+    /// Parse the condition for counting successes, if one is next.
+    fn successes(&mut self) -> crate::Result<Option<Condition>>
+    {
+        match self.compare()
+        {
+            Some(compare) => Ok(Some(self.condition_value(compare)?)),
+            None          => Ok(None),
+        }
+    }
+
+    /// Parse the comparison and value a die roll is checked against.
+    fn condition(&mut self) -> crate::Result<Condition>
+    {
+        let compare = self.compare().unwrap_or(Compare::Equal);
+        self.condition_value(compare)
+    }
+
+    fn condition_value(&mut self, compare: Compare) -> crate::Result<Condition>
+    {
+        let value = self.number()?.ok_or_else(|| self.expected("a number"))?;
+        Ok(Condition{ compare, value: i64::from(value) })
+    }
+
+    /// Parse a comparison, if one is next.
+    fn compare(&mut self) -> Option<Compare>
+    {
+        if self.eat_word("<=")      { Some(Compare::LessEqual) }
+        else if self.eat_word(">=") { Some(Compare::GreaterEqual) }
+        else if self.eat_word("<")  { Some(Compare::Less) }
+        else if self.eat_word(">")  { Some(Compare::Greater) }
+        else if self.eat_word("=")  { Some(Compare::Equal) }
+        else { None }
+    }
+
+    /// Parse an optional unsigned number.
+    fn number(&mut self) -> crate::Result<Option<u32>>
+    {
+        let start = self.pos;
+        while matches!(self.peek_raw(), Some(c) if c.is_ascii_digit())
+        {
+            self.bump();
+        }
+        match &self.text[start..self.pos]
+        {
+            ""     => Ok(None),
+            digits => digits.parse::<u32>()
+                .map(Some)
+                .map_err(|_| self.invalid(start..self.pos, Error::NotANumber(digits.to_owned()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use spectral::prelude::*;
+
+    use super::*;
+
+    fn parse_default(text: &str) -> crate::Result<Expr>
+    {
+        parse(text, &Limits::default())
+    }
+
+    fn syntax_error(text: &str, span: Range<usize>, expected: &'static str) -> Error
+    {
+        located(text, span, ParseProblem::Expected(expected))
+    }
+
+    fn invalid_term(text: &str, span: Range<usize>, error: Error) -> Error
+    {
+        located(text, span, ParseProblem::Invalid(Box::new(error)))
+    }
+
+    fn roll(roll: Roll) -> Box<Expr>
+    {
+        Box::new(Expr::Roll(roll))
+    }
+
+    #[test]
+    fn parse_precedence()
+    {
+        assert_that!(parse_default("1 + 2d6 * 3")).is_ok_containing(
+            Expr::Add(roll(Roll::Incr(1)), Box::new(Expr::Mul(roll(Roll::Dice(2, 6)), roll(Roll::Incr(3)))))
+        );
+        assert_that!(parse_default("(1 + 2d6) * 3")).is_ok_containing(
+            Expr::Mul(Box::new(Expr::Add(roll(Roll::Incr(1)), roll(Roll::Dice(2, 6)))), roll(Roll::Incr(3)))
+        );
+    }
+
+    #[test]
+    fn parse_left_associative()
+    {
+        assert_that!(parse_default("d20 - 2 - 1")).is_ok_containing(
+            Expr::Sub(Box::new(Expr::Sub(roll(Roll::Dice(1, 20)), roll(Roll::Incr(2)))), roll(Roll::Incr(1)))
+        );
+    }
+
+    #[test]
+    fn parse_unary_minus()
+    {
+        assert_that!(parse_default("-d4 * -2")).is_ok_containing(
+            Expr::Mul(Box::new(Expr::Neg(roll(Roll::Dice(1, 4)))), Box::new(Expr::Neg(roll(Roll::Incr(2)))))
+        );
+    }
+
+    #[test]
+    fn parse_division_rounding()
+    {
+        assert_that!(parse_default("d6/2")).is_ok_containing(
+            Expr::Div(roll(Roll::Dice(1, 6)), roll(Roll::Incr(2)), Rounding::Down)
+        );
+        assert_that!(parse_default("d6 /^ 2")).is_ok_containing(
+            Expr::Div(roll(Roll::Dice(1, 6)), roll(Roll::Incr(2)), Rounding::Up)
+        );
+        assert_that!(parse_default("d6 /~ 2")).is_ok_containing(
+            Expr::Div(roll(Roll::Dice(1, 6)), roll(Roll::Incr(2)), Rounding::Nearest)
+        );
+    }
+
+    #[test]
+    fn parse_errors()
+    {
+        assert_that!(parse_default("2d6 +")).is_err_containing(syntax_error("2d6 +", 5..5, "a number, dice, or '('"));
+        assert_that!(parse_default("2d6 + x")).is_err_containing(syntax_error("2d6 + x", 7..7, "the number of sides"));
+        assert_that!(parse_default("(2d6 + 1")).is_err_containing(syntax_error("(2d6 + 1", 8..8, "')'"));
+        assert_that!(parse_default("2d6 3")).is_err_containing(
+            syntax_error("2d6 3", 4..5, "an operator or the end of the expression"));
+        assert_that!(parse_default("1d0 + 2")).is_err_containing(syntax_error("1d0 + 2", 2..3, "at least one side"));
+        assert_that!(parse_default("2xF")).is_err_containing(syntax_error("2xF", 2..3, "the number of sides"));
+        assert_that!(parse_default("0d6")).is_err_containing(syntax_error("0d6", 0..1, "at least one die"));
+        assert_that!(parse_default("3 d6")).is_err_containing(
+            syntax_error("3 d6", 2..4, "an operator or the end of the expression"));
+        assert_that!(parse_default("3d7+?2")).is_err_containing(syntax_error("3d7+?2", 4..5, "a number, dice, or '('"));
+        assert_that!(parse_default("99999999999")).is_err_containing(
+            invalid_term("99999999999", 0..11, Error::NotANumber("99999999999".into())));
+    }
+
+    #[test]
+    fn parse_zero_divisor()
+    {
+        assert_that!(parse_default("d20 / (d6 - 3)")).is_err_containing(
+            invalid_term("d20 / (d6 - 3)", 6..14, Error::DiceDivideByZero("(d6 - 3)".into())));
+        assert_that!(parse_default("d20 / 0")).is_err_containing(
+            invalid_term("d20 / 0", 6..7, Error::DiceDivideByZero("0".into())));
+        assert_that!(parse_default("d20 / (d6 - 7)")).is_ok();
+    }
+
+    #[test]
+    fn parse_any_sides()
+    {
+        for sides in [1, 2, 5, 7, 30, 1000]
+        {
+            assert_that!(parse_default(&format!("d{}", sides))).is_ok_containing(Expr::Roll(Roll::Dice(1, sides)));
+        }
+        assert_that!(parse_default("3d%")).is_ok_containing(Expr::Roll(Roll::Dice(3, 100)));
+        assert_that!(parse_default("x%")).is_ok_containing(Expr::Roll(Roll::ExplodingDice(1, 100)));
+        assert_that!(parse_default("4dF")).is_ok_containing(Expr::Roll(Roll::Fudge(4)));
+        assert_that!(parse_default("df")).is_ok_containing(Expr::Roll(Roll::Fudge(1)));
+    }
+
+    #[test]
+    fn parse_keep()
+    {
+        let keep = |roll, keep| Expr::Roll(Roll::Modified(Box::new(roll), Modifiers{ keep: Some(keep), ..Modifiers::default() }));
+        assert_that!(parse_default("4d6kh3")).is_ok_containing(keep(Roll::Dice(4, 6), Keep::Highest(3)));
+        assert_that!(parse_default("2d20kl1")).is_ok_containing(keep(Roll::Dice(2, 20), Keep::Lowest(1)));
+        assert_that!(parse_default("2d20K")).is_ok_containing(keep(Roll::Dice(2, 20), Keep::Highest(1)));
+        assert_that!(parse_default("4d6DL")).is_ok_containing(keep(Roll::Dice(4, 6), Keep::DropLowest(1)));
+        assert_that!(parse_default("3x6dh1")).is_ok_containing(keep(Roll::ExplodingDice(3, 6), Keep::DropHighest(1)));
+        assert_that!(parse_default("4dFkh2")).is_ok_containing(keep(Roll::Fudge(4), Keep::Highest(2)));
+    }
+
+    #[test]
+    fn parse_keep_errors()
+    {
+        assert_that!(parse_default("4d6kh1kl1")).is_err_containing(
+            syntax_error("4d6kh1kl1", 6..9, "each modifier at most once"));
+        assert_that!(parse_default("4d6 kh3")).is_err_containing(
+            syntax_error("4d6 kh3", 4..7, "an operator or the end of the expression"));
+        assert_that!(parse_default("4d6d6")).is_err_containing(
+            syntax_error("4d6d6", 3..5, "an operator or the end of the expression"));
+        assert_that!(parse_default("4d6kh0")).is_err_containing(syntax_error("4d6kh0", 3..6, "at least one die kept"));
+        assert_that!(parse_default("4d6kl0")).is_err_containing(syntax_error("4d6kl0", 3..6, "at least one die kept"));
+        assert_that!(parse_default("4d6dl5")).is_err_containing(syntax_error("4d6dl5", 3..6, "at least one die kept"));
+        assert_that!(parse_default("4d6dh3")).is_ok();
+    }
+
+    #[test]
+    fn parse_reroll()
+    {
+        let modified = |roll, mods| Expr::Roll(Roll::Modified(Box::new(roll), mods));
+        let reroll = |compare, value, once| Some(Reroll{ condition: Condition{ compare, value }, once });
+        assert_that!(parse_default("2d6r1")).is_ok_containing(modified(Roll::Dice(2, 6),
+            Modifiers{ reroll: reroll(Compare::Equal, 1, false), ..Modifiers::default() }));
+        assert_that!(parse_default("2d6RO<3")).is_ok_containing(modified(Roll::Dice(2, 6),
+            Modifiers{ reroll: reroll(Compare::Less, 3, true), ..Modifiers::default() }));
+        assert_that!(parse_default("4d6r<=2kh3")).is_ok_containing(modified(Roll::Dice(4, 6),
+            Modifiers{ reroll: reroll(Compare::LessEqual, 2, false), keep: Some(Keep::Highest(3)), ..Modifiers::default() }));
+        assert_that!(parse_default("4d6min2")).is_ok_containing(modified(Roll::Dice(4, 6),
+            Modifiers{ minimum: Some(2), ..Modifiers::default() }));
+        assert_that!(parse_default("4dFr>=1")).is_ok_containing(modified(Roll::Fudge(4),
+            Modifiers{ reroll: reroll(Compare::GreaterEqual, 1, false), ..Modifiers::default() }));
+        assert_that!(parse_default("d6r=6")).is_ok_containing(modified(Roll::Dice(1, 6),
+            Modifiers{ reroll: reroll(Compare::Equal, 6, false), ..Modifiers::default() }));
+    }
+
+    #[test]
+    fn parse_reroll_errors()
+    {
+        assert_that!(parse_default("2d6r")).is_err_containing(syntax_error("2d6r", 4..4, "a number"));
+        assert_that!(parse_default("2d6r<")).is_err_containing(syntax_error("2d6r<", 5..5, "a number"));
+        assert_that!(parse_default("2d6min")).is_err_containing(syntax_error("2d6min", 6..6, "a number"));
+        assert_that!(parse_default("2d6r1r2")).is_err_containing(syntax_error("2d6r1r2", 5..7, "each modifier at most once"));
+        assert_that!(parse_default("d6r<7")).is_err_containing(syntax_error("d6r<7", 2..5, "a reroll that leaves some faces"));
+        assert_that!(parse_default("d1r1")).is_err_containing(syntax_error("d1r1", 2..4, "a reroll that leaves some faces"));
+        assert_that!(parse_default("d1ro1")).is_ok();
+    }
+
+    #[test]
+    fn parse_successes()
+    {
+        let modified = |roll, mods| Expr::Roll(Roll::Modified(Box::new(roll), mods));
+        let condition = |compare, value| Some(Condition{ compare, value });
+        assert_that!(parse_default("10d10>=8")).is_ok_containing(modified(Roll::Dice(10, 10),
+            Modifiers{ successes: condition(Compare::GreaterEqual, 8), ..Modifiers::default() }));
+        assert_that!(parse_default("10d10>=8f1 + 1")).is_ok_containing(Expr::Add(
+            Box::new(modified(Roll::Dice(10, 10), Modifiers{
+                successes: condition(Compare::GreaterEqual, 8),
+                failures: condition(Compare::Equal, 1),
+                ..Modifiers::default()
+            })),
+            roll(Roll::Incr(1)),
+        ));
+        assert_that!(parse_default("6d6=6")).is_ok_containing(modified(Roll::Dice(6, 6),
+            Modifiers{ successes: condition(Compare::Equal, 6), ..Modifiers::default() }));
+        assert_that!(parse_default("5x10kh3<4F<=9")).is_ok_containing(modified(Roll::ExplodingDice(5, 10),
+            Modifiers{
+                keep: Some(Keep::Highest(3)),
+                successes: condition(Compare::Less, 4),
+                failures: condition(Compare::LessEqual, 9),
+                ..Modifiers::default()
+            }));
+    }
+
+    #[test]
+    fn parse_successes_errors()
+    {
+        assert_that!(parse_default("10d10f1")).is_err_containing(
+            syntax_error("10d10f1", 5..7, "a success condition before the failures"));
+        assert_that!(parse_default("10d10>=")).is_err_containing(syntax_error("10d10>=", 7..7, "a number"));
+        assert_that!(parse_default("10d10>=8>=9")).is_err_containing(
+            syntax_error("10d10>=8>=9", 8..11, "each modifier at most once"));
+        assert_that!(parse_default("10d10 >= 8")).is_err_containing(
+            syntax_error("10d10 >= 8", 6..7, "an operator or the end of the expression"));
+    }
+
+    #[test]
+    fn parse_explode()
+    {
+        let exploding = |num, condition, style| Expr::Roll(Roll::Modified(Box::new(Roll::Dice(num, 6)),
+            Modifiers{ explode: Some(Explode{ condition, style }), ..Modifiers::default() }));
+        let condition = |compare, value| Some(Condition{ compare, value });
+        assert_that!(parse_default("4d6!")).is_ok_containing(exploding(4, None, ExplodeStyle::Chained));
+        assert_that!(parse_default("4d6!>=5")).is_ok_containing(
+            exploding(4, condition(Compare::GreaterEqual, 5), ExplodeStyle::Chained));
+        assert_that!(parse_default("4d6!!")).is_ok_containing(exploding(4, None, ExplodeStyle::Compounding));
+        assert_that!(parse_default("d6!P5")).is_ok_containing(
+            exploding(1, condition(Compare::Equal, 5), ExplodeStyle::Penetrating));
+        assert_that!(parse_default("d6!!>4 + 1")).is_ok_containing(Expr::Add(
+            Box::new(exploding(1, condition(Compare::Greater, 4), ExplodeStyle::Compounding)),
+            roll(Roll::Incr(1)),
+        ));
+    }
+
+    #[test]
+    fn parse_explode_errors()
+    {
+        let unexploding = "an explosion that some faces don't trigger";
+        assert_that!(parse_default("4d6!>=1")).is_err_containing(syntax_error("4d6!>=1", 3..7, unexploding));
+        assert_that!(parse_default("d1!")).is_err_containing(syntax_error("d1!", 2..3, unexploding));
+        assert_that!(parse_default("x1")).is_err_containing(syntax_error("x1", 1..2, unexploding));
+        assert_that!(parse_default("3x1kh1")).is_err_containing(syntax_error("3x1kh1", 2..3, unexploding));
+        assert_that!(parse_default("2x6!")).is_err_containing(syntax_error("2x6!", 3..4, "modifiers these dice accept"));
+        assert_that!(parse_default("4dF!")).is_err_containing(syntax_error("4dF!", 3..4, "modifiers these dice accept"));
+        assert_that!(parse_default("4d6!!!")).is_err_containing(syntax_error("4d6!!!", 5..6, "each modifier at most once"));
+        assert_that!(parse_default("4d6!>")).is_err_containing(syntax_error("4d6!>", 5..5, "a number"));
+    }
+
+    #[test]
+    fn parse_sides_limit()
+    {
+        let limits = Limits{ max_sides: 50, ..Limits::default() };
+        assert_that!(parse("d50", &limits)).is_ok();
+        assert_that!(parse("d51", &limits)).is_err_containing(invalid_term("d51", 1..3, Error::DiceTooManySides(51, 50)));
+        assert_that!(parse("d%", &limits)).is_err_containing(invalid_term("d%", 1..2, Error::DiceTooManySides(100, 50)));
+        assert_that!(parse_default("d2000000")).is_err_containing(
+            invalid_term("d2000000", 1..8, Error::DiceTooManySides(2_000_000, 1_000_000)));
+    }
+
+    #[test]
+    fn parse_dice_limit()
+    {
+        let limits = Limits{ max_dice: 10, ..Limits::default() };
+        assert_that!(parse("10d6 + 10x6", &limits)).is_ok();
+        assert_that!(parse("11d6", &limits)).is_err_containing(invalid_term("11d6", 0..2, Error::DiceTooManyDice(11, 10)));
+        assert_that!(parse("2d6 + 11dF", &limits)).is_err_containing(
+            invalid_term("2d6 + 11dF", 6..8, Error::DiceTooManyDice(11, 10)));
+        assert_that!(parse_default("4000000000d100")).is_err_containing(
+            invalid_term("4000000000d100", 0..10, Error::DiceTooManyDice(4_000_000_000, 10_000)));
+    }
+
+    #[test]
+    fn parse_custom_dice()
+    {
+        let dice: Vec<CustomDie> = ["Boo: 1 ghost, -", "Boost: success, -"].iter()
+            .map(|die| die.parse().unwrap())
+            .collect();
+        let parse_dice = |text: &str| parse_with_dice(text, &Limits::default(), &dice);
+        assert_that!(parse_dice("2dBOOST")).is_ok_containing(Expr::Roll(Roll::Custom(2, dice[1].clone())));
+        assert_that!(parse_dice("dboo+1")).is_ok_containing(
+            Expr::Add(roll(Roll::Custom(1, dice[0].clone())), roll(Roll::Incr(1))));
+        assert_that!(parse_dice("4dF")).is_ok_containing(Expr::Roll(Roll::Fudge(4)));
+        assert_that!(parse_dice("3dBoot")).is_err_containing(invalid_term("3dBoot", 2..6, Error::DiceBadType("Boot".into())));
+        assert_that!(parse_dice("4dBoost>=2")).is_err_containing(
+            syntax_error("4dBoost>=2", 7..10, "no modifiers on custom dice"));
+    }
+
+    #[test]
+    fn parse_explosion_limit()
+    {
+        let limits = Limits{ max_explosions: Some(10), ..Limits::default() };
+        let limited = |roll: Roll| Expr::Roll(Roll::Modified(Box::new(roll),
+            Modifiers{ max_explosions: Some(10), ..Modifiers::default() }));
+        assert_that!(parse("x6", &limits)).is_ok_containing(limited(Roll::ExplodingDice(1, 6)));
+        assert_that!(parse("4d6", &limits)).is_ok_containing(Expr::Roll(Roll::Dice(4, 6)));
+        assert_that!(parse("4d6!", &limits)).is_ok_containing(Expr::Roll(Roll::Modified(Box::new(Roll::Dice(4, 6)),
+            Modifiers{
+                explode: Some(Explode{ condition: None, style: ExplodeStyle::Chained }),
+                max_explosions: Some(10),
+                ..Modifiers::default()
+            })));
+        assert_that!(parse("4d6!", &limits).unwrap().to_string()).is_equal_to("4d6!".to_string());
+
+        // A limit is recorded whenever it is set, even to the default.
+        let limits = Limits{ max_explosions: Some(crate::dice::MAX_EXPLOSIONS), ..Limits::default() };
+        assert_that!(parse("x6", &limits)).is_ok_containing(Expr::Roll(Roll::Modified(Box::new(Roll::ExplodingDice(1, 6)),
+            Modifiers{ max_explosions: Some(100), ..Modifiers::default() })));
+        assert_that!(parse_default("x6")).is_ok_containing(Expr::Roll(Roll::ExplodingDice(1, 6)));
+    }
+
+    #[test]
+    fn parse_overflow()
+    {
+        let big = "10000d1000000 * 10000d1000000";
+        assert_that!(parse_default(big)).is_err_containing(invalid_term(big, 0..29, Error::DiceOverflow(big.into())));
+        let negated = " -(10000d1000000 * 1000d1000000)";
+        assert_that!(parse_default(negated))
+            .is_err_containing(invalid_term(negated, 1..32, Error::DiceOverflow(negated.trim().into())));
+        assert_that!(parse_default("1000d1000000 * 1000d1000000")).is_ok();
+
+        // Each exploding die is limited in how far it can explode.
+        let exploding = "10000x1000000 * 10000d1000";
+        assert_that!(parse_default(exploding)).is_err_containing(
+            invalid_term(exploding, 0..26, Error::DiceOverflow(exploding.into())));
+        assert_that!(parse(exploding, &Limits{ max_explosions: Some(10), ..Limits::default() })).is_ok();
+    }
+
+    #[test]
+    fn parse_nesting_limit()
+    {
+        let nested = |depth: usize| format!("{}d6{}", "(".repeat(depth), ")".repeat(depth));
+        assert_that!(parse_default(&nested(100))).is_ok_containing(Expr::Roll(Roll::Dice(1, 6)));
+        let deep = nested(100_000);
+        assert_that!(parse_default(&deep)).is_err_containing(syntax_error(&deep, 100..101, "less nesting"));
+
+        let negated = |depth: usize| format!("{}3", "-".repeat(depth));
+        assert_that!(parse_default(&negated(100))).is_ok();
+        let deep = negated(100_000);
+        assert_that!(parse_default(&deep)).is_err_containing(syntax_error(&deep, 100..101, "less nesting"));
+        let mixed = "-(".repeat(50) + "3" + &")".repeat(50);
+        assert_that!(parse_default(&mixed)).is_ok();
+        let mixed = "(-".repeat(51) + "3" + &")".repeat(51);
+        assert_that!(parse_default(&mixed)).is_err_containing(syntax_error(&mixed, 100..101, "less nesting"));
+    }
+
+    #[test]
+    fn parse_terms_limit()
+    {
+        let limits = Limits{ max_terms: 3, ..Limits::default() };
+        assert_that!(parse("d6 + 2 * (x4 - 1)", &limits)).is_err_containing(
+            invalid_term("d6 + 2 * (x4 - 1)", 15..16, Error::DiceTooManyTerms(3)));
+        assert_that!(parse("d6 + 2 * x4kh1", &limits)).is_ok();
+
+        let sum = |terms: usize| vec!["d6"; terms].join("+");
+        let most = parse_default(&sum(1000)).unwrap();
+        assert_that!(most.to_string()).is_equal_to(vec!["1d6"; 1000].join(" + "));
+        assert_that!(most.min()).is_equal_to(Some(1000));
+        let long = sum(200_000);
+        assert_that!(parse_default(&long)).is_err_containing(invalid_term(&long, 3000..3002, Error::DiceTooManyTerms(1000)));
+    }
+}
@@ -0,0 +1,261 @@
+use super::{check_term, check_total_dice, Compare, Contest, CustomDie, Expr, Face, Labelled, Limits, Modifiers,
+            ParseProblem, Repeat, Roll, RollSet, Rounding, Sort, DEFAULT_MAX_TERMS, MAX_REPEAT};
+use crate::Error;
+
+use serde::Deserialize;
+
+use std::convert::TryFrom;
+
+/// Unvalidated form of a Roll, used when deserializing.
+#[derive(Deserialize)]
+pub(super) enum UncheckedRoll
+{
+    Dice(u32, u32),
+    ExplodingDice(u32, u32),
+    Incr(u32),
+    Fudge(u32),
+    Modified(Box<Roll>, Modifiers),
+    Custom(u32, CustomDie),
+}
+
+/// Unvalidated form of a CustomDie, used when deserializing.
+#[derive(Deserialize)]
+pub(super) struct UncheckedDie
+{
+    name: String,
+    faces: Vec<Face>,
+}
+
+/// Unvalidated form of an Expr, used when deserializing.
+#[derive(Deserialize)]
+pub(super) enum UncheckedExpr
+{
+    Roll(Roll),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>, Rounding),
+}
+
+/// Unvalidated form of a Repeat, used when deserializing.
+#[derive(Deserialize)]
+pub(super) struct UncheckedRepeat
+{
+    count: u32,
+    sort: Option<Sort>,
+}
+
+/// Unvalidated form of a RollSet, used when deserializing.
+#[derive(Deserialize)]
+pub(super) struct UncheckedRollSet
+{
+    rolls: Vec<Labelled>,
+}
+
+/// Unvalidated form of a Contest, used when deserializing.
+#[derive(Deserialize)]
+pub(super) struct UncheckedContest
+{
+    left: Expr,
+    right: Expr,
+    compare: Option<Compare>,
+}
+
+impl TryFrom<UncheckedRoll> for Roll
+{
+    type Error = Error;
+
+    /// Accept only a term that `command()` could have parsed.
+    fn try_from(term: UncheckedRoll) -> crate::Result<Roll>
+    {
+        let roll = match term
+        {
+            UncheckedRoll::Dice(num, sides)          => Roll::Dice(num, sides),
+            UncheckedRoll::ExplodingDice(num, sides) => Roll::ExplodingDice(num, sides),
+            UncheckedRoll::Incr(num)                 => Roll::Incr(num),
+            UncheckedRoll::Fudge(num)                => Roll::Fudge(num),
+            UncheckedRoll::Modified(base, mods)      => Roll::Modified(base, mods),
+            UncheckedRoll::Custom(num, die)          => Roll::Custom(num, die),
+        };
+        let checked = match &roll
+        {
+            Roll::Incr(_) => Ok(()),
+            Roll::Modified(base, _) if matches!(**base, Roll::Incr(_) | Roll::Modified(..)) =>
+                return Err(Error::DiceBadExpr(roll.to_string())),
+            Roll::Modified(base, mods) => check_term(base, mods, &Limits::default()),
+            _ => check_term(&roll, &Modifiers::default(), &Limits::default()),
+        };
+        match checked
+        {
+            Ok(()) => Ok(roll),
+            Err((_, ParseProblem::Invalid(error))) => Err(*error),
+            Err((_, ParseProblem::Expected(_)))    => Err(Error::DiceBadExpr(roll.to_string())),
+        }
+    }
+}
+
+impl TryFrom<UncheckedDie> for CustomDie
+{
+    type Error = Error;
+
+    fn try_from(die: UncheckedDie) -> crate::Result<CustomDie>
+    {
+        CustomDie::new(&die.name, die.faces)
+    }
+}
+
+impl TryFrom<UncheckedRepeat> for Repeat
+{
+    type Error = Error;
+
+    fn try_from(UncheckedRepeat{ count, sort }: UncheckedRepeat) -> crate::Result<Repeat>
+    {
+        if count == 0 || count > MAX_REPEAT
+        {
+            return Err(Error::DiceBadExpr(format!("{}#", count)));
+        }
+        Ok(Repeat{ count, sort })
+    }
+}
+
+impl TryFrom<UncheckedRollSet> for RollSet
+{
+    type Error = Error;
+
+    /// Accept only a set that `command()` could have parsed, rolling no more
+    /// dice than the default limit.
+    fn try_from(UncheckedRollSet{ rolls }: UncheckedRollSet) -> crate::Result<RollSet>
+    {
+        let set = RollSet{ rolls };
+        check_total_dice(set.total_dice(), &Limits::default())?;
+        Ok(set)
+    }
+}
+
+impl TryFrom<UncheckedContest> for Contest
+{
+    type Error = Error;
+
+    /// Accept only a contest that `command()` could have parsed, with no more
+    /// terms or dice than the default limits.
+    fn try_from(UncheckedContest{ left, right, compare }: UncheckedContest) -> crate::Result<Contest>
+    {
+        let contest = Contest{ left, right, compare };
+        let terms = contest.left.terms().len() + contest.right.terms().len();
+        if terms > DEFAULT_MAX_TERMS as usize
+        {
+            return Err(Error::DiceTooManyTerms(DEFAULT_MAX_TERMS));
+        }
+        check_total_dice(contest.total_dice(), &Limits::default())?;
+        Ok(contest)
+    }
+}
+
+impl TryFrom<UncheckedExpr> for Expr
+{
+    type Error = Error;
+
+    /// Accept only an expression that `command()` could have parsed. Each
+    /// subexpression has already been checked.
+    fn try_from(expr: UncheckedExpr) -> crate::Result<Expr>
+    {
+        let expr = match expr
+        {
+            UncheckedExpr::Roll(roll)                 => Expr::Roll(roll),
+            UncheckedExpr::Neg(expr)                  => Expr::Neg(expr),
+            UncheckedExpr::Add(left, right)           => Expr::Add(left, right),
+            UncheckedExpr::Sub(left, right)           => Expr::Sub(left, right),
+            UncheckedExpr::Mul(left, right)           => Expr::Mul(left, right),
+            UncheckedExpr::Div(left, right, rounding) => Expr::Div(left, right, rounding),
+        };
+        if let Expr::Div(_, divisor, _) = &expr
+        {
+            let (low, high) = divisor.bounds();
+            if low <= 0 && high >= 0
+            {
+                return Err(Error::DiceDivideByZero(divisor.to_string()));
+            }
+        }
+        if expr.reachable_bounds().is_none()
+        {
+            return Err(Error::DiceOverflow(expr.to_string()));
+        }
+        if expr.terms().len() > DEFAULT_MAX_TERMS as usize
+        {
+            return Err(Error::DiceTooManyTerms(DEFAULT_MAX_TERMS));
+        }
+        check_total_dice(expr.total_dice(), &Limits::default())?;
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use spectral::prelude::*;
+
+    use crate::dice::*;
+
+    #[test]
+    fn serde_round_trip()
+    {
+        let cmd = command("2d12 + 3x6 + 2".into()).unwrap();
+        let json = serde_json::to_string(&cmd).unwrap();
+        assert_that!(json.as_str())
+            .is_equal_to(concat!(r#"{"command":"RollDice","params":{"Add":[{"Add":[{"Roll":{"Dice":[2,12]}},"#,
+                r#"{"Roll":{"ExplodingDice":[3,6]}}]},{"Roll":{"Incr":2}}]}}"#));
+        assert_that!(serde_json::from_str::<Command>(&json).unwrap()).is_equal_to(cmd);
+    }
+
+    #[test]
+    fn serde_validates()
+    {
+        let expr = |json: &str| serde_json::from_str::<Expr>(json);
+        assert_that!(expr(r#"{"Roll":{"Dice":[1,6]}}"#)).is_ok_containing(Expr::Roll(Roll::Dice(1, 6)));
+        assert_that!(expr(r#"{"Roll":{"Dice":[1,0]}}"#)).is_err();
+        assert_that!(expr(r#"{"Roll":{"Dice":[0,6]}}"#)).is_err();
+        assert_that!(expr(r#"{"Roll":{"Dice":[20000,6]}}"#)).is_err();
+        assert_that!(expr(r#"{"Roll":{"Fudge":0}}"#)).is_err();
+        assert_that!(expr(r#"{"Roll":{"Incr":0}}"#)).is_ok();
+
+        let reroll_all = r#"{"reroll":{"condition":{"compare":"Less","value":7},"once":false},"minimum":null,"#;
+        let mods = r#""explode":null,"keep":null,"successes":null,"failures":null,"max_explosions":null}"#;
+        let reroll_ones = reroll_all.replace("Less", "Equal").replace("7", "1");
+        assert_that!(expr(&format!(r#"{{"Roll":{{"Modified":[{{"Dice":[1,6]}},{}{}]}}}}"#, reroll_ones, mods))).is_ok();
+        assert_that!(expr(&format!(r#"{{"Roll":{{"Modified":[{{"Dice":[1,6]}},{}{}]}}}}"#, reroll_all, mods))).is_err();
+        assert_that!(expr(&format!(r#"{{"Roll":{{"Modified":[{{"Incr":1}},{}{}]}}}}"#, reroll_all, mods))).is_err();
+
+        let explode = concat!(r#"{"reroll":null,"minimum":null,"explode":{"condition":null,"style":"Chained"},"#,
+            r#""keep":null,"successes":null,"failures":null,"max_explosions":"#);
+        let limited = |max: u32| format!(r#"{{"Roll":{{"Modified":[{{"Dice":[1,6]}},{}{}}}]}}}}"#, explode, max);
+        assert_that!(expr(&limited(MAX_EXPLOSIONS))).is_ok();
+        let error = Error::DiceTooManyExplosions(MAX_EXPLOSIONS + 1, MAX_EXPLOSIONS).to_string();
+        assert_that!(expr(&limited(MAX_EXPLOSIONS + 1)).unwrap_err().to_string()).starts_with(error.as_str());
+        assert_that!(expr(&limited(u32::MAX))).is_err();
+
+        assert_that!(expr(r#"{"Div":[{"Roll":{"Dice":[1,20]}},{"Roll":{"Incr":0}},"Down"]}"#)).is_err();
+        assert_that!(expr(r#"{"Div":[{"Roll":{"Dice":[1,20]}},{"Roll":{"Incr":2}},"Down"]}"#)).is_ok();
+        let big = r#"{"Roll":{"Dice":[10000,1000000]}}"#;
+        assert_that!(expr(&format!(r#"{{"Mul":[{},{{"Mul":[{},{}]}}]}}"#, big, big, big))).is_err();
+
+        assert_that!(serde_json::from_str::<CustomDie>(r#"{"name":"F","faces":[{"value":1,"symbols":[]}]}"#)).is_err();
+        assert_that!(serde_json::from_str::<CustomDie>(r#"{"name":"Boo","faces":[]}"#)).is_err();
+        assert_that!(serde_json::from_str::<Repeat>(r#"{"count":0,"sort":null}"#)).is_err();
+
+        // The whole of a command is checked against the default limits.
+        let dice = r#"{"Roll":{"Dice":[10000,6]}}"#;
+        let sum = |count: usize| (1..count).fold(dice.to_owned(), |sum, _| format!(r#"{{"Add":[{},{}]}}"#, sum, dice));
+        assert_that!(expr(&sum(10))).is_ok();
+        let error = Error::DiceTooManyTotalDice(110_000, DEFAULT_MAX_TOTAL_DICE).to_string();
+        assert_that!(expr(&sum(11)).unwrap_err().to_string()).starts_with(error.as_str());
+        let set = |count: u32| serde_json::from_str::<RollSet>(
+            &format!(r#"{{"rolls":[{{"label":"","expr":{},"repeat":{{"count":{},"sort":null}}}}]}}"#, dice, count));
+        assert_that!(set(10)).is_ok();
+        assert_that!(set(11)).is_err();
+        let contest = |left: usize, right: usize| serde_json::from_str::<Contest>(
+            &format!(r#"{{"left":{},"right":{},"compare":null}}"#, sum(left), sum(right)));
+        assert_that!(contest(5, 5)).is_ok();
+        assert_that!(contest(5, 6)).is_err();
+    }
+}
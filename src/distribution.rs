@@ -0,0 +1,268 @@
+use crate::Decision;
+use crate::deck::Card;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// The largest number of outcomes a Distribution will be computed for. Deciders
+/// with more possible outcomes than this do not return a distribution.
+pub const MAX_OUTCOMES: usize = 1 << 20;
+
+/// A single possible outcome of a decision. Decisions are converted to the
+/// Outcome they represent by dropping any descriptive parts, such as the
+/// description of a dice roll or the label of the Oracle's answer.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Outcome
+{
+    Text(String),
+    Num(i64),
+    Bool(bool),
+    List(Vec<String>),
+    Card(Card),
+}
+
+impl Outcome
+{
+    /// Return the numeric value of the outcome, with booleans treated as 1 for
+    /// true and 0 for false. Other outcomes have no numeric value.
+    pub fn as_num(&self) -> Option<i64>
+    {
+        match self
+        {
+            Outcome::Num(num)    => Some(*num),
+            Outcome::Bool(value) => Some(i64::from(*value)),
+            _                    => None,
+        }
+    }
+}
+
+impl fmt::Display for Outcome
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self
+        {
+            Outcome::Text(text)  => write!(f, "{}", text),
+            Outcome::Num(num)    => write!(f, "{}", num),
+            Outcome::Bool(value) => write!(f, "{}", value),
+            Outcome::List(list)  => write!(f, "{}", list.join(", ")),
+            Outcome::Card(card)  => write!(f, "{}", card),
+        }
+    }
+}
+
+impl From<&Decision> for Outcome
+{
+    fn from(decision: &Decision) -> Outcome
+    {
+        match decision
+        {
+            Decision::Text(text)                 => Outcome::Text(text.clone()),
+            Decision::LabelledText{value, ..}    => Outcome::Text(value.clone()),
+            Decision::Num(num)                   => Outcome::Num(i64::from(*num)),
+            Decision::AnnotatedNum{value, ..}    => Outcome::Num(*value),
+            Decision::Bool(value)                => Outcome::Bool(*value),
+            Decision::List(list)                 => Outcome::List(list.clone()),
+            Decision::Card(card)                 => Outcome::Card(card.clone()),
+            Decision::LabelledNums(nums)         =>
+                Outcome::List(nums.iter().map(|num| format!("{}: {}", num.label, num.value)).collect()),
+            Decision::Opposed{result, ..}        => Outcome::Text(result.to_string()),
+            Decision::Compared{value, ..}        => Outcome::Bool(*value),
+        }
+    }
+}
+
+/// How exact the probabilities of a Distribution are. A decision that could go
+/// on indefinitely, such as rolling exploding dice, is only computed so far, so
+/// some of its probabilities may be approximate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Accuracy
+{
+    /// The probability of every outcome is exact.
+    Exact,
+    /// The probability of each numeric outcome below the value is exact, as is
+    /// the total probability of the value and above, but not how that is split
+    /// among them.
+    ExactBelow(i64),
+    /// Some of the probabilities are approximate.
+    Approximate,
+}
+
+/// The probability of each possible outcome of a decision, exact unless its
+/// accuracy says otherwise. Outcomes with a probability of zero are not
+/// included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution
+{
+    outcomes: Vec<(Outcome, f64)>,
+    accuracy: Accuracy,
+}
+
+impl Distribution
+{
+    /// Create a Distribution where each of the supplied outcomes is equally
+    /// likely. Repeated outcomes are combined into a single, more likely,
+    /// outcome in the order they were first seen.
+    pub fn uniform(outcomes: Vec<Outcome>) -> Distribution
+    {
+        let prob = 1.0 / outcomes.len() as f64;
+        let mut index: HashMap<Outcome, usize> = HashMap::new();
+        let mut merged: Vec<(Outcome, f64)> = Vec::new();
+        for outcome in outcomes
+        {
+            match index.get(&outcome)
+            {
+                Some(&i) => merged[i].1 += prob,
+                None     => {
+                    index.insert(outcome.clone(), merged.len());
+                    merged.push((outcome, prob));
+                },
+            }
+        }
+        Distribution{ outcomes: merged, accuracy: Accuracy::Exact }
+    }
+
+    /// Create a Distribution of numeric outcomes from a map of values to
+    /// probabilities.
+    pub(crate) fn from_nums(nums: &BTreeMap<i64, f64>) -> Distribution
+    {
+        Distribution{
+            outcomes: nums.iter()
+                .filter(|(_, &p)| p > 0.0)
+                .map(|(&n, &p)| (Outcome::Num(n), p))
+                .collect(),
+            accuracy: Accuracy::Exact,
+        }
+    }
+
+    /// Create a Distribution from outcomes and their probabilities.
+    pub(crate) fn from_outcomes(outcomes: Vec<(Outcome, f64)>) -> Distribution
+    {
+        Distribution{ outcomes, accuracy: Accuracy::Exact }
+    }
+
+    /// Return the Distribution with the supplied accuracy.
+    pub(crate) fn with_accuracy(self, accuracy: Accuracy) -> Distribution
+    {
+        Distribution{ accuracy, ..self }
+    }
+
+    /// Return the outcomes and their probabilities. Numeric outcomes are
+    /// sorted from lowest to highest.
+    pub fn outcomes(&self) -> &[(Outcome, f64)]
+    {
+        &self.outcomes
+    }
+
+    /// Return how exact the probabilities are.
+    pub fn accuracy(&self) -> Accuracy
+    {
+        self.accuracy
+    }
+
+    /// Return the number of possible outcomes.
+    pub fn len(&self) -> usize
+    {
+        self.outcomes.len()
+    }
+
+    /// Return true if there are no possible outcomes.
+    pub fn is_empty(&self) -> bool
+    {
+        self.outcomes.is_empty()
+    }
+
+    /// Return the probability of the supplied outcome.
+    pub fn probability(&self, outcome: &Outcome) -> f64
+    {
+        self.probability_where(|o| o == outcome)
+    }
+
+    /// Return the probability of any outcome matching the predicate.
+    pub fn probability_where<F>(&self, pred: F) -> f64
+        where F: Fn(&Outcome) -> bool
+    {
+        self.outcomes.iter()
+            .filter(|(o, _)| pred(o))
+            .map(|(_, p)| p)
+            .sum()
+    }
+}
+
+/// Return the distribution of the sum of values from the two distributions.
+pub(crate) fn convolve(a: &BTreeMap<i64, f64>, b: &BTreeMap<i64, f64>) -> BTreeMap<i64, f64>
+{
+    let (amin, bmin) = match (a.keys().next(), b.keys().next())
+    {
+        (Some(&amin), Some(&bmin)) => (amin, bmin),
+        _ => return BTreeMap::new(),
+    };
+    let dense = |dist: &BTreeMap<i64, f64>, min: i64| {
+        let max = *dist.keys().next_back().unwrap();
+        let mut probs = vec![0.0; (max - min + 1) as usize];
+        for (&n, &p) in dist { probs[(n - min) as usize] = p; }
+        probs
+    };
+    let (aprobs, bprobs) = (dense(a, amin), dense(b, bmin));
+
+    let mut sums = vec![0.0; aprobs.len() + bprobs.len() - 1];
+    for (i, &pa) in aprobs.iter().enumerate().filter(|(_, &p)| p > 0.0)
+    {
+        for (j, &pb) in bprobs.iter().enumerate()
+        {
+            sums[i + j] += pa * pb;
+        }
+    }
+    sums.into_iter()
+        .enumerate()
+        .filter(|&(_, p)| p > 0.0)
+        .map(|(i, p)| (amin + bmin + i as i64, p))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn uniform_merges_repeats()
+    {
+        let dist = Distribution::uniform(vec![
+            Outcome::Text("a".into()), Outcome::Text("b".into()),
+            Outcome::Text("a".into()), Outcome::Text("c".into()),
+        ]);
+        assert_that!(dist.len()).is_equal_to(3);
+        assert_that!(dist.probability(&Outcome::Text("a".into()))).is_equal_to(0.5);
+        assert_that!(dist.probability(&Outcome::Text("c".into()))).is_equal_to(0.25);
+        assert_that!(dist.probability(&Outcome::Text("d".into()))).is_equal_to(0.0);
+    }
+
+    #[test]
+    fn convolve_sums()
+    {
+        let die: BTreeMap<i64, f64> = (1..=2).map(|n| (n, 0.5)).collect();
+        let dist = Distribution::from_nums(&convolve(&die, &die));
+        assert_that!(dist.outcomes().to_vec())
+            .is_equal_to(vec![(Outcome::Num(2), 0.25), (Outcome::Num(3), 0.5), (Outcome::Num(4), 0.25)]);
+    }
+
+    #[test]
+    fn convolve_sparse()
+    {
+        let a: BTreeMap<i64, f64> = [(-1, 0.25), (2, 0.75)].iter().cloned().collect();
+        let b: BTreeMap<i64, f64> = [(0, 0.5), (5, 0.5)].iter().cloned().collect();
+        let expected: BTreeMap<i64, f64> = [(-1, 0.125), (2, 0.375), (4, 0.125), (7, 0.375)].iter().cloned().collect();
+        assert_that!(convolve(&a, &b)).is_equal_to(expected);
+    }
+
+    #[test]
+    fn outcome_from_decision()
+    {
+        assert_that!(Outcome::from(&Decision::AnnotatedNum{value: 7, extra: "2d6(3+4)".into()}))
+            .is_equal_to(Outcome::Num(7));
+        assert_that!(Outcome::from(&Decision::LabelledText{value: "Yes".into(), label: "Says".into()}))
+            .is_equal_to(Outcome::Text("Yes".into()));
+    }
+}
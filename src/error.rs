@@ -1,21 +1,39 @@
 use thiserror::Error;
 
+use crate::dice::{ParseError, ParseProblem};
+
 #[derive(Error, Debug, PartialEq, Clone)]
 pub enum QuikError {
     #[error("Unrecognized deck type {0}")]
     UnrecognizedDeck(String),
+    #[error("Unrecognized card {0}")]
+    UnrecognizedCard(String),
     #[error("Non-number somehow passed parsing {0}")]
     NotANumber(String),
     #[error("Missing dice expression")]
     DiceMissingExpr,
     #[error("Failed parsing dice expression {0}")]
     DiceBadExpr(String),
-    #[error("No sides specified")]
-    DiceBadSides,
     #[error("Unrecognized die type {0}")]
     DiceBadType(String),
-    #[error("Unparseable term")]
-    UnparseableTerm,
+    #[error("Invalid custom die {0}")]
+    DiceBadCustom(String),
+    #[error("Failed parsing dice expression: {0}")]
+    UnparseableTerm(ParseError),
+    #[error("Divisor {0} of dice expression could be zero")]
+    DiceDivideByZero(String),
+    #[error("Dice with {0} sides exceed the limit of {1} sides")]
+    DiceTooManySides(u32, u32),
+    #[error("Rolling {0} dice exceeds the limit of {1} dice")]
+    DiceTooManyDice(u32, u32),
+    #[error("Rolling {0} dice in all exceeds the limit of {1} dice")]
+    DiceTooManyTotalDice(u64, u32),
+    #[error("Dice expression has more terms than the limit of {0} terms")]
+    DiceTooManyTerms(u32),
+    #[error("Dice exploding {0} times exceed the limit of {1} explosions")]
+    DiceTooManyExplosions(u32, u32),
+    #[error("Total of dice expression {0} could overflow")]
+    DiceOverflow(String),
     #[error("percent arg cannot be 0")]
     PercentZero,
     #[error("percent arg cannot be 100 percent or greater {0}")]
@@ -26,6 +44,42 @@ pub enum QuikError {
     ListEmpty,
     #[error("Must supply at least two strings")]
     ListOne,
+    #[error("Missing command")]
+    MissingCommand,
+    #[error("Unrecognized command {0}")]
+    UnknownCommand(String),
+    #[error("Command {0} is missing the {1} argument")]
+    MissingArg(String, String),
+    #[error("Too many arguments for command {0}")]
+    ExtraArgs(String),
+    #[error("Invalid number {0}")]
+    InvalidNumber(String),
+    #[error("Unterminated {0} quote")]
+    UnterminatedQuote(char),
+    #[error("Escape character at end of command")]
+    TrailingEscape,
+    #[error("Command {0} is already registered")]
+    DuplicateCommand(String),
+}
+
+impl QuikError {
+    /// Return the error for a dice expression beyond one of the limits, or
+    /// that could overflow or divide by zero. It is the same whether returned
+    /// directly or within an UnparseableTerm giving where it was found. Any
+    /// other error gives None.
+    pub fn limit(&self) -> Option<&QuikError> {
+        match self {
+            QuikError::UnparseableTerm(ParseError{ problem: ParseProblem::Invalid(error), .. }) => error.limit(),
+            QuikError::DiceDivideByZero(_)
+            | QuikError::DiceTooManySides(..)
+            | QuikError::DiceTooManyDice(..)
+            | QuikError::DiceTooManyTotalDice(..)
+            | QuikError::DiceTooManyTerms(_)
+            | QuikError::DiceTooManyExplosions(..)
+            | QuikError::DiceOverflow(_) => Some(self),
+            _ => None,
+        }
+    }
 }
 
 impl From<QuikError> for String {
@@ -0,0 +1,359 @@
+//! Statistical checks that a Command makes decisions with the expected
+//! probabilities.
+//!
+//! Each check compares the outcomes of a seeded simulation of the Command with
+//! the exact distribution returned by `distribution()`, and reports the test
+//! statistic and its p-value. A very small p-value means the decisions are
+//! unlikely to have come from the expected distribution.
+//!
+//! ```rust
+//! use quikdecision::{coin, fairness};
+//!
+//! let fit = fairness::chi_square(&coin::command().unwrap(), 1000, 42).unwrap();
+//! assert!(fit.is_consistent(0.001));
+//! ```
+
+use crate::{Command, Decider};
+use crate::distribution::{Accuracy, Distribution, Outcome};
+use crate::simulation::Simulation;
+
+use std::collections::BTreeMap;
+
+/// The result of a goodness-of-fit test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fit
+{
+    pub statistic: f64,
+    pub p_value: f64,
+}
+
+impl Fit
+{
+    /// Return true if the observed outcomes are consistent with the expected
+    /// distribution at the supplied significance level, such as 0.01.
+    pub fn is_consistent(&self, alpha: f64) -> bool
+    {
+        self.p_value >= alpha
+    }
+}
+
+/// Run the Command the supplied number of times from the seed, and compare the
+/// outcomes with its distribution using Pearson's chi-square test. Returns
+/// None if the Command has no distribution, or only an approximate one.
+pub fn chi_square(command: &Command, trials: usize, seed: u64) -> Option<Fit>
+{
+    let expected = command.distribution()?;
+    chi_square_fit(&expected, &command.iter_seeded(seed).simulate(trials))
+}
+
+/// Run the Command the supplied number of times from the seed, and compare the
+/// outcomes with its distribution using the Kolmogorov-Smirnov test. Returns
+/// None if the Command has no distribution, only an approximate one, or its
+/// outcomes are not numeric.
+pub fn kolmogorov_smirnov(command: &Command, trials: usize, seed: u64) -> Option<Fit>
+{
+    let expected = command.distribution()?;
+    kolmogorov_smirnov_fit(&expected, &command.iter_seeded(seed).simulate(trials))
+}
+
+/// Compare the observed outcomes with the expected distribution using
+/// Pearson's chi-square test. An outcome that should be impossible gives a
+/// p-value of 0. With no trials there is nothing to compare, giving a
+/// statistic of 0 and a p-value of 1.
+///
+/// If the distribution is only exact below some value, the outcomes at or
+/// above it are counted together. Returns None if the distribution is
+/// approximate.
+pub fn chi_square_fit(expected: &Distribution, observed: &Simulation) -> Option<Fit>
+{
+    let buckets = buckets(expected, observed)?;
+    if observed.trials() == 0
+    {
+        return Some(Fit{ statistic: 0.0, p_value: 1.0 });
+    }
+    let trials = observed.trials() as f64;
+    if buckets.iter().any(|&(p, count)| p == 0.0 && count > 0)
+    {
+        return Some(Fit{ statistic: f64::INFINITY, p_value: 0.0 });
+    }
+
+    let statistic = buckets.iter()
+        .map(|&(p, count)| {
+            let exp = p * trials;
+            let diff = count as f64 - exp;
+            diff * diff / exp
+        })
+        .sum();
+    let freedom = buckets.len().saturating_sub(1);
+    let p_value = if freedom == 0 { 1.0 } else { gamma_q(freedom as f64 / 2.0, statistic / 2.0) };
+    Some(Fit{ statistic, p_value })
+}
+
+/// Compare the observed outcomes with the expected distribution using the
+/// Kolmogorov-Smirnov test. Returns None if any outcome is not numeric. As for
+/// `chi_square_fit()`, no trials give a statistic of 0 and a p-value of 1,
+/// only the outcomes below the value a distribution is exact below are
+/// compared, and an approximate distribution gives None.
+///
+/// The p-value assumes a continuous distribution, so it is conservative for
+/// the discrete outcomes of a decision.
+pub fn kolmogorov_smirnov_fit(expected: &Distribution, observed: &Simulation) -> Option<Fit>
+{
+    let end = match expected.accuracy()
+    {
+        Accuracy::Exact         => i64::MAX,
+        Accuracy::ExactBelow(t) => t,
+        Accuracy::Approximate   => return None,
+    };
+    let mut steps: BTreeMap<i64, (f64, f64)> = BTreeMap::new();
+    for (outcome, p) in expected.outcomes()
+    {
+        steps.entry(outcome.as_num()?).or_insert((0.0, 0.0)).0 += p;
+    }
+    if observed.trials() == 0
+    {
+        return Some(Fit{ statistic: 0.0, p_value: 1.0 });
+    }
+    let trials = observed.trials() as f64;
+    for (outcome, count) in observed.frequencies()
+    {
+        steps.entry(outcome.as_num()?).or_insert((0.0, 0.0)).1 += *count as f64 / trials;
+    }
+
+    let (mut exp_cdf, mut obs_cdf, mut statistic) = (0.0, 0.0, 0.0_f64);
+    for (exp, obs) in steps.range(..end).map(|(_, step)| step)
+    {
+        exp_cdf += exp;
+        obs_cdf += obs;
+        statistic = statistic.max((exp_cdf - obs_cdf).abs());
+    }
+    let root = trials.sqrt();
+    Some(Fit{ statistic, p_value: kolmogorov_q((root + 0.12 + 0.11 / root) * statistic) })
+}
+
+/// Pair the expected probability of each outcome with the number of times it
+/// was observed, adding any outcomes that should be impossible with a
+/// probability of 0. Outcomes at or above the value a distribution is exact
+/// below share one pair. Returns None if the distribution is approximate.
+fn buckets(expected: &Distribution, observed: &Simulation) -> Option<Vec<(f64, usize)>>
+{
+    let tail = match expected.accuracy()
+    {
+        Accuracy::Exact         => None,
+        Accuracy::ExactBelow(t) => Some(t),
+        Accuracy::Approximate   => return None,
+    };
+    let in_tail = |outcome: &Outcome| match (tail, outcome.as_num())
+    {
+        (Some(t), Some(n)) => n >= t,
+        _                  => false,
+    };
+
+    let mut buckets: Vec<(f64, usize)> = expected.outcomes().iter()
+        .filter(|(outcome, _)| !in_tail(outcome))
+        .map(|(outcome, p)| (*p, observed.count(outcome)))
+        .collect();
+    if tail.is_some()
+    {
+        let p = expected.probability_where(in_tail);
+        let count = observed.frequencies().iter()
+            .filter(|(outcome, _)| in_tail(outcome))
+            .map(|(_, count)| count)
+            .sum();
+        buckets.push((p, count));
+    }
+    buckets.extend(
+        observed.frequencies().iter()
+            .filter(|(outcome, _)| !in_tail(outcome) && expected.probability(outcome) == 0.0)
+            .map(|(_, count)| (0.0, *count))
+    );
+    buckets.retain(|&(p, count)| p > 0.0 || count > 0);
+    Some(buckets)
+}
+
+/// Return the natural log of the gamma function, using the Lanczos
+/// approximation.
+fn ln_gamma(x: f64) -> f64
+{
+    const COEFFS: [f64; 6] = [
+        76.180_091_729_471_46, -86.505_320_329_416_77, 24.014_098_240_830_91,
+        -1.231_739_572_450_155, 0.001_208_650_973_866_179, -0.000_005_395_239_384_953,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let series = COEFFS.iter()
+        .enumerate()
+        .fold(1.000_000_000_190_015, |sum, (i, c)| sum + c / (x + 1.0 + i as f64));
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+/// Return the regularized upper incomplete gamma function Q(a, x), which is
+/// the probability of a chi-square statistic of at least 2x with 2a degrees
+/// of freedom.
+fn gamma_q(a: f64, x: f64) -> f64
+{
+    const EPSILON: f64 = 1e-14;
+    const MAX_ITER: usize = 1000;
+
+    if x <= 0.0
+    {
+        return 1.0;
+    }
+    let scale = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0
+    {
+        // Series for the lower function P(a, x).
+        let (mut term, mut sum, mut ap) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..MAX_ITER
+        {
+            ap += 1.0;
+            term *= x / ap;
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON { break; }
+        }
+        (1.0 - sum * scale).max(0.0)
+    }
+    else
+    {
+        // Continued fraction for Q(a, x), using Lentz's method.
+        let tiny = f64::MIN_POSITIVE / EPSILON;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..MAX_ITER
+        {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny { d = tiny; }
+            c = b + an / c;
+            if c.abs() < tiny { c = tiny; }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < EPSILON { break; }
+        }
+        (scale * h).min(1.0)
+    }
+}
+
+/// Return the probability that the Kolmogorov distribution exceeds lambda.
+fn kolmogorov_q(lambda: f64) -> f64
+{
+    if lambda < 0.2
+    {
+        return 1.0;
+    }
+    let sum: f64 = (1..=100)
+        .map(|j| {
+            let sign = if j % 2 == 1 { 1.0 } else { -1.0 };
+            sign * (-2.0 * (j * j) as f64 * lambda * lambda).exp()
+        })
+        .sum();
+    (2.0 * sum).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use spectral::prelude::*;
+
+    use super::*;
+    use crate::Decision;
+    use crate::{dice, pick};
+
+    #[test]
+    fn gamma_q_matches_tables()
+    {
+        // Chi-square critical values for p = 0.05.
+        assert_that!(gamma_q(0.5, 3.841 / 2.0)).is_close_to(0.05, 1e-4);
+        assert_that!(gamma_q(2.5, 11.070 / 2.0)).is_close_to(0.05, 1e-4);
+        assert_that!(gamma_q(50.0, 124.342 / 2.0)).is_close_to(0.05, 1e-4);
+        assert_that!(gamma_q(1.0, 0.0)).is_equal_to(1.0);
+    }
+
+    #[test]
+    fn kolmogorov_q_matches_tables()
+    {
+        assert_that!(kolmogorov_q(1.358)).is_close_to(0.05, 1e-3);
+        assert_that!(kolmogorov_q(1.628)).is_close_to(0.01, 1e-3);
+        assert_that!(kolmogorov_q(0.1)).is_equal_to(1.0);
+    }
+
+    #[test]
+    fn chi_square_exact_counts()
+    {
+        let expected = pick::command(1, 4).unwrap().distribution().unwrap();
+        let observed = Simulation::from_decisions((1..=4).cycle().take(400).map(Decision::Num));
+        let fit = chi_square_fit(&expected, &observed).unwrap();
+        assert_that!(fit.statistic).is_equal_to(0.0);
+        assert_that!(fit.p_value).is_close_to(1.0, 1e-9);
+    }
+
+    #[test]
+    fn chi_square_detects_bias()
+    {
+        let expected = pick::command(1, 4).unwrap().distribution().unwrap();
+        let observed = Simulation::from_decisions(
+            [1, 1, 1, 2, 3, 4].iter().cycle().take(600).map(|&n| Decision::Num(n))
+        );
+        assert_that!(chi_square_fit(&expected, &observed).unwrap().is_consistent(0.001)).is_false();
+
+        let impossible = Simulation::from_decisions(vec![Decision::Num(5)]);
+        assert_that!(chi_square_fit(&expected, &impossible).unwrap().p_value).is_equal_to(0.0);
+    }
+
+    #[test]
+    fn kolmogorov_smirnov_detects_bias()
+    {
+        let expected = pick::command(1, 10).unwrap().distribution().unwrap();
+        let observed = Simulation::from_decisions((1..=5).cycle().take(500).map(Decision::Num));
+        let fit = kolmogorov_smirnov_fit(&expected, &observed).unwrap();
+        assert_that!(fit.statistic).is_close_to(0.5, 1e-9);
+        assert_that!(fit.is_consistent(0.001)).is_false();
+    }
+
+    #[test]
+    fn no_trials()
+    {
+        let expected = pick::command(1, 4).unwrap().distribution().unwrap();
+        let observed = Simulation::from_decisions(Vec::new());
+        assert_that!(chi_square_fit(&expected, &observed)).is_some().is_equal_to(Fit{ statistic: 0.0, p_value: 1.0 });
+        assert_that!(kolmogorov_smirnov_fit(&expected, &observed)).is_some().is_equal_to(Fit{ statistic: 0.0, p_value: 1.0 });
+        assert_that!(chi_square(&pick::command(1, 4).unwrap(), 0, 1)).is_some().is_equal_to(Fit{ statistic: 0.0, p_value: 1.0 });
+    }
+
+    #[test]
+    fn kolmogorov_smirnov_needs_numbers()
+    {
+        assert_that!(kolmogorov_smirnov(&crate::coin::command().unwrap(), 100, 1)).is_none();
+    }
+
+    #[test]
+    fn dice_are_fair()
+    {
+        let cmd = dice::command("2d6+1".into()).unwrap();
+        assert_that!(chi_square(&cmd, 5000, 42).unwrap().is_consistent(0.001)).is_true();
+        assert_that!(kolmogorov_smirnov(&cmd, 5000, 42).unwrap().is_consistent(0.001)).is_true();
+    }
+
+    #[test]
+    fn exploding_dice_are_fair()
+    {
+        for expr in &["d6!", "d2!"]
+        {
+            let cmd = dice::command(expr.to_string()).unwrap();
+            assert_that!(chi_square(&cmd, 5000, 42).unwrap().is_consistent(0.001)).is_true();
+            assert_that!(kolmogorov_smirnov(&cmd, 5000, 42).unwrap().is_consistent(0.001)).is_true();
+        }
+    }
+
+    #[test]
+    fn approximate_distributions_are_not_checked()
+    {
+        let cmd = dice::command("10 - x6".into()).unwrap();
+        assert_that!(chi_square(&cmd, 100, 1)).is_none();
+        assert_that!(kolmogorov_smirnov(&cmd, 100, 1)).is_none();
+    }
+}
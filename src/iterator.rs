@@ -1,16 +1,72 @@
 use crate::{Command, Decision, Decider};
+use crate::rng::{self, RngSource, SeededRng};
+
+use rand::rngs::OsRng;
 
 #[derive(Debug, PartialEq)]
 pub struct QdIter<'a>
 {
     decider: &'a Command,
+    source: Source,
+}
+
+/// Source of randomness for a QdIter.
+#[derive(Debug)]
+enum Source
+{
+    Thread,
+    Os(OsRng),
+    Seeded{ seed: u64, taken: u64, rng: SeededRng },
+}
+
+/// Seeded sources are equal if they started from the same seed and have
+/// produced the same number of decisions, because they will produce the
+/// same decisions from here on.
+impl PartialEq for Source
+{
+    fn eq(&self, other: &Source) -> bool
+    {
+        match (self, other)
+        {
+            (Source::Thread, Source::Thread) => true,
+            (Source::Os(_), Source::Os(_))   => true,
+            (Source::Seeded{seed, taken, ..}, Source::Seeded{seed: oseed, taken: otaken, ..}) =>
+                seed == oseed && taken == otaken,
+            _ => false,
+        }
+    }
 }
 
 impl<'a> QdIter<'a>
 {
     fn new(decider: &Command) -> QdIter
     {
-        QdIter { decider }
+        match rng::default_source()
+        {
+            RngSource::Os => QdIter::secure(decider),
+            _             => QdIter { decider, source: Source::Thread },
+        }
+    }
+
+    fn secure(decider: &Command) -> QdIter<'_>
+    {
+        QdIter { decider, source: Source::Os(rng::os()) }
+    }
+
+    fn seeded(decider: &Command, seed: u64) -> QdIter<'_>
+    {
+        QdIter { decider, source: Source::Seeded{ seed, taken: 0, rng: rng::seeded(seed) } }
+    }
+
+    /// Return the source of randomness used for the decisions.
+    pub fn source(&self) -> RngSource
+    {
+        match self.source
+        {
+            Source::Thread           => RngSource::Thread,
+            Source::Os(_)            => RngSource::Os,
+            Source::Seeded{seed, ..} => RngSource::Seeded(seed),
+        }
     }
 }
 
@@ -20,18 +76,40 @@ impl<'a> Iterator for QdIter<'a>
 
     fn next(&mut self) -> Option<Decision>
     {
-        Some(self.decider.decide())
+        match &mut self.source
+        {
+            Source::Thread => Some(self.decider.decide_with(&mut rand::thread_rng())),
+            Source::Os(rng) => Some(self.decider.decide_with(rng)),
+            Source::Seeded{ taken, rng, .. } => {
+                *taken += 1;
+                Some(self.decider.decide_with(rng))
+            },
+        }
     }
 }
 
 impl Command
 {
     /// Convert the Command into an infinite iterator that simplifies
-    /// calling it multiple times.
+    /// calling it multiple times, using the default random number generator.
     pub fn iter(&self) -> QdIter
     {
         QdIter::new(&self)
     }
+
+    /// Convert the Command into an infinite iterator that uses the operating
+    /// system's cryptographically secure random number generator.
+    pub fn iter_secure(&self) -> QdIter<'_>
+    {
+        QdIter::secure(self)
+    }
+
+    /// Convert the Command into an infinite iterator that always yields the
+    /// same sequence of decisions for the same seed.
+    pub fn iter_seeded(&self, seed: u64) -> QdIter<'_>
+    {
+        QdIter::seeded(self, seed)
+    }
 }
 
 #[cfg(test)]
@@ -43,6 +121,7 @@ mod tests
     use crate::{Command, Decision};
 
     use crate::coin;
+    use crate::dice;
 
     #[test]
     fn test_create()
@@ -63,4 +142,41 @@ mod tests
             None => assert!(false, "No value returned"),
         }
     }
+
+    #[test]
+    fn test_create_seeded()
+    {
+        let cmd = Command::CoinFlip(coin::Coin{});
+        assert_that!(cmd.iter_seeded(42)).is_equal_to(QdIter::seeded(&cmd, 42));
+        assert_that!(cmd.iter_seeded(42)).is_not_equal_to(QdIter::seeded(&cmd, 43));
+        assert_that!(cmd.iter_seeded(42)).is_not_equal_to(QdIter::new(&cmd));
+    }
+
+    #[test]
+    fn test_sources()
+    {
+        let cmd = Command::CoinFlip(coin::Coin{});
+        assert_that!(cmd.iter().source()).is_equal_to(rng::default_source());
+        assert_that!(cmd.iter_secure().source()).is_equal_to(RngSource::Os);
+        assert_that!(cmd.iter_seeded(42).source()).is_equal_to(RngSource::Seeded(42));
+        assert_that!(cmd.iter_secure().take(3).all(|d| d.is_text())).is_true();
+    }
+
+    #[test]
+    fn test_seeded_repeats()
+    {
+        let cmd = dice::command("3d6 + 2x8".into()).unwrap();
+        let first: Vec<String> = cmd.iter_seeded(42).take(20).map(|d| d.to_string()).collect();
+        let second: Vec<String> = cmd.iter_seeded(42).take(20).map(|d| d.to_string()).collect();
+        assert_that!(first).is_equal_to(second);
+    }
+
+    #[test]
+    fn test_seeded_sequence()
+    {
+        let cmd = Command::CoinFlip(coin::Coin{});
+        let flips: Vec<String> = cmd.iter_seeded(42).take(8).map(|d| d.to_string()).collect();
+        assert_that!(flips.join(","))
+            .is_equal_to("'Tails','Tails','Heads','Heads','Heads','Tails','Heads','Heads'".to_string());
+    }
 }
@@ -1,21 +1,28 @@
 extern crate derive_more;
 extern crate rand;
-extern crate regex;
 extern crate thiserror;
 
-use rand::Rng;
-use rand::seq::SliceRandom;
+use rand::RngCore;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 pub mod coin;
 pub mod deck;
 pub mod decision;
 pub mod dice;
+pub mod distribution;
 pub mod error;
+pub mod fairness;
 pub mod oracle;
+pub mod parse;
 pub mod percent;
 pub mod pick;
+pub mod registry;
+pub mod rng;
 pub mod select;
 pub mod shuffle;
+pub mod simulation;
 pub mod iterator;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -23,6 +30,7 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 /// Enum defining the types of quik decision commands, and the parameters that
 /// determine their functioning.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(tag = "command", content = "params"))]
 pub enum Command
 {
     CoinFlip(coin::Coin),
@@ -30,14 +38,20 @@ pub enum Command
     PickNumber(pick::Picker),
     PercentTrue(percent::Likely),
     RollDice(dice::Expr),
+    RollDiceSet(dice::RollSet),
+    RollContest(dice::Contest),
     Selection(select::Choices),
     Shuffle(shuffle::Choices),
     Oracle(oracle::Oracle),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(registry::Custom),
 }
 
 pub type Result<T> = std::result::Result<T,error::QuikError>;
 pub type Error = error::QuikError;
 pub type Decision = decision::Decision;
+pub use parse::parse;
+pub use registry::Registry;
 
 /// Structure containing the documentation for a quik decision command
 #[derive(Debug)]
@@ -52,16 +66,48 @@ pub struct ApiDoc
 /// trait for making a random decision.
 pub trait Decider
 {
-    fn decide(&self) -> Decision;
+    /// Make a decision using randomness from the supplied generator.
+    fn decide_with(&self, rng: &mut dyn RngCore) -> Decision;
+
+    /// Make a decision using the default random number generator, see
+    /// `rng::default_source()`.
+    fn decide(&self) -> Decision
+    {
+        rng::with_source(rng::default_source(), |rng| self.decide_with(rng))
+    }
+
+    /// Make a decision using the supplied source of randomness, and report
+    /// the source along with the decision.
+    fn decide_from(&self, source: rng::RngSource) -> decision::Report
+    {
+        decision::Report{
+            decision: rng::with_source(source, |rng| self.decide_with(rng)),
+            source,
+        }
+    }
+
+    /// Return the exact probability of each possible outcome of the decision,
+    /// or None if the distribution is not known or is too large to compute.
+    fn distribution(&self) -> Option<distribution::Distribution>
+    {
+        None
+    }
 }
 
 /// Trait implementation for making a random decision for a Command.
 impl Decider for Command
 {
-    /// Perform appropriate command returning a Decision object.
-    fn decide(&self) -> Decision
+    /// Perform appropriate command with the supplied generator returning a
+    /// Decision object.
+    fn decide_with(&self, rng: &mut dyn RngCore) -> Decision
     {
-        self.get_decider().decide()
+        self.get_decider().decide_with(rng)
+    }
+
+    /// Return the distribution of outcomes for the appropriate command.
+    fn distribution(&self) -> Option<distribution::Distribution>
+    {
+        self.get_decider().distribution()
     }
 }
 
@@ -74,9 +120,12 @@ impl Command {
             Command::PickNumber(range)   => range,
             Command::PercentTrue(likely) => likely,
             Command::RollDice(expr)      => expr,
+            Command::RollDiceSet(set)    => set,
+            Command::RollContest(vs)     => vs,
             Command::Selection(choices)  => choices,
             Command::Shuffle(choices)    => choices,
             Command::Oracle(oracle)      => oracle,
+            Command::Custom(custom)      => custom,
         }
     }
 }
@@ -89,19 +138,22 @@ pub fn version() -> &'static str
 
 /// Randomly select one of the supplied choices and return it as a String.
 ///
+/// rng:      the random number generator supplying the randomness.
 /// choices:  an array slice of a type that can be cloned and converted to a
 ///           String.
-pub fn pick_one<R, T>(mut rng: &mut R, choices: &[T]) -> String
-    where R: Rng,
+pub fn pick_one<R, T>(rng: &mut R, choices: &[T]) -> String
+    where R: RngCore + ?Sized,
           T: std::string::ToString + std::clone::Clone
 {
-    choices.choose(&mut rng).expect("Somehow ended up with no strings").to_string()
+    assert!(!choices.is_empty(), "Somehow ended up with no strings");
+    choices[rng::gen_index(rng, choices.len())].to_string()
 }
 
 #[cfg(test)]
 extern crate spectral;
 
 /// DecisionAssertions trait to support spectral tests on the Decision enum.
+#[cfg(test)]
 trait DecisionAssertions<'s>
 {
     /// Returns true if the Decision being tested matches the correct variant.
@@ -112,6 +164,7 @@ trait DecisionAssertions<'s>
     fn is_bool_decision(&self) -> bool;
     fn is_list_decision(&self) -> bool;
     fn is_card_decision(&self) -> bool;
+    fn is_labelled_nums_decision(&self) -> bool;
 }
 
 #[cfg(test)]
@@ -124,4 +177,5 @@ impl<'s> DecisionAssertions<'s> for spectral::Spec<'s, Decision>
     fn is_bool_decision(&self) -> bool { self.subject.is_bool() }
     fn is_list_decision(&self) -> bool { self.subject.is_list() }
     fn is_card_decision(&self) -> bool { self.subject.is_card() }
+    fn is_labelled_nums_decision(&self) -> bool { self.subject.is_labelled_nums() }
 }
@@ -1,7 +1,11 @@
-use rand::thread_rng;
+use rand::RngCore;
 
 use crate::{Command, Decision, Decider};
 use crate::ApiDoc;
+use crate::distribution::{Distribution, Outcome};
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 const ORACLE_ANSWERS: [&str; 24] = [
     // Positive answeers
@@ -47,6 +51,7 @@ const ORACLE_LABELS: [&str; 10] = [
 ];
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Oracle;
 
 /// Return an ApiDoc object containing a description of the Oracle
@@ -72,21 +77,31 @@ pub fn command() -> crate::Result<Command>
 
 impl Decider for Oracle {
     /// Perform the actual decision for the Oracle and return the Decision.
-    fn decide(&self) -> Decision
+    fn decide_with(&self, rng: &mut dyn RngCore) -> Decision
     {
-        let mut rng = thread_rng();
-        let value = crate::pick_one(&mut rng, &ORACLE_ANSWERS).to_string();
-        let label = crate::pick_one(&mut rng, &ORACLE_LABELS).to_string();
+        let value = crate::pick_one(rng, &ORACLE_ANSWERS).to_string();
+        let label = crate::pick_one(rng, &ORACLE_LABELS).to_string();
 
         Decision::LabelledText{value, label}
     }
+
+    /// Each of the answers is equally likely, the label is not part of the
+    /// outcome.
+    fn distribution(&self) -> Option<Distribution>
+    {
+        Some(Distribution::uniform(
+            ORACLE_ANSWERS.iter().map(|answer| Outcome::Text(answer.to_string())).collect()
+        ))
+    }
 }
 
 #[cfg(test)]
 mod tests
 {
     use spectral::prelude::*;
+    use rand::rngs::mock::StepRng;
 
+    use crate::Decision;
     use crate::DecisionAssertions;
     use crate::Decider;
     use crate::Command;
@@ -105,4 +120,21 @@ mod tests
         assert_that!(Command::Oracle(Oracle{}).decide())
             .is_labelled_text_decision();
     }
+
+    #[test]
+    fn lowest_draws_give_first_answer_and_label()
+    {
+        assert_that!(Oracle{}.decide_with(&mut StepRng::new(0, 0))).is_equal_to(Decision::LabelledText{
+            value: "It is certain".into(),
+            label: "Thus spoke the Oracle".into(),
+        });
+    }
+
+    #[test]
+    fn oracle_distribution()
+    {
+        let dist = Command::Oracle(Oracle{}).distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(24);
+        assert_that!(dist.probability(&Outcome::Text("Yes".into()))).is_close_to(1.0 / 24.0, 1e-12);
+    }
 }
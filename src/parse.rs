@@ -0,0 +1,279 @@
+use crate::{Command, ApiDoc};
+use crate::Error;
+use crate::registry::Registry;
+use crate::{coin, deck, dice, oracle, percent, pick, select, shuffle};
+
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+pub(crate) type Builder = fn(&str, &[String]) -> crate::Result<Command>;
+
+/// Each built-in decider's documentation paired with the function that builds
+/// its Command from the arguments on the command line.
+pub(crate) const BUILTINS: [(fn() -> ApiDoc, Builder); 8] = [
+    (coin::api_doc,    coin_args),
+    (deck::api_doc,    deck_args),
+    (oracle::api_doc,  oracle_args),
+    (percent::api_doc, percent_args),
+    (pick::api_doc,    pick_args),
+    (dice::api_doc,    dice_args),
+    (select::api_doc,  select_args),
+    (shuffle::api_doc, shuffle_args),
+];
+
+/// Parse a command line such as "roll 3d6+2" or "select 'red wine' beer" into
+/// the matching built-in Command. The first word is the name of the command
+/// from its ApiDoc, and the remaining words are its arguments. Arguments
+/// containing spaces may be quoted with single or double quotes, and a
+/// backslash outside of single quotes escapes the next character.
+///
+/// Use `Registry::parse()` to also parse custom commands.
+pub fn parse(line: &str) -> crate::Result<Command>
+{
+    builtins().parse(line)
+}
+
+/// Return the Registry of the built-in commands shared by every call to
+/// `parse()`, building it on first use.
+fn builtins() -> &'static Registry
+{
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::new)
+}
+
+impl FromStr for Command
+{
+    type Err = Error;
+
+    /// Parse a command line into a Command, see `parse()`.
+    fn from_str(line: &str) -> crate::Result<Command>
+    {
+        parse(line)
+    }
+}
+
+/// Split a line into words on whitespace, respecting quotes and escapes.
+pub(crate) fn split_words(line: &str) -> crate::Result<Vec<String>>
+{
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next()
+    {
+        match (quote, c)
+        {
+            (Some('\''), '\'') => quote = None,
+            (Some('"'), '"')   => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                let escaped = chars.next().ok_or(Error::TrailingEscape)?;
+                word.get_or_insert_with(String::new).push(escaped);
+            },
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            },
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(q) = quote
+    {
+        return Err(Error::UnterminatedQuote(q));
+    }
+    words.extend(word.take());
+    Ok(words)
+}
+
+/// Return the only argument, or an error naming the missing parameter.
+fn one_arg<'a>(name: &str, param: &str, args: &'a [String]) -> crate::Result<&'a str>
+{
+    match args
+    {
+        []    => Err(Error::MissingArg(name.to_owned(), param.to_owned())),
+        [arg] => Ok(arg),
+        _     => Err(Error::ExtraArgs(name.to_owned())),
+    }
+}
+
+fn number<T: FromStr>(arg: &str) -> crate::Result<T>
+{
+    arg.parse::<T>().map_err(|_| Error::InvalidNumber(arg.to_owned()))
+}
+
+/// Return an error if any arguments were supplied.
+fn no_args(name: &str, args: &[String]) -> crate::Result<()>
+{
+    match args
+    {
+        [] => Ok(()),
+        _  => Err(Error::ExtraArgs(name.to_owned())),
+    }
+}
+
+fn coin_args(name: &str, args: &[String]) -> crate::Result<Command>
+{
+    no_args(name, args)?;
+    coin::command()
+}
+
+fn oracle_args(name: &str, args: &[String]) -> crate::Result<Command>
+{
+    no_args(name, args)?;
+    oracle::command()
+}
+
+fn deck_args(name: &str, args: &[String]) -> crate::Result<Command>
+{
+    deck::command(one_arg(name, "type", args)?)
+}
+
+fn percent_args(name: &str, args: &[String]) -> crate::Result<Command>
+{
+    percent::command(number(one_arg(name, "num", args)?)?)
+}
+
+fn pick_args(name: &str, args: &[String]) -> crate::Result<Command>
+{
+    match args
+    {
+        []          => Err(Error::MissingArg(name.to_owned(), "low".to_owned())),
+        [_]         => Err(Error::MissingArg(name.to_owned(), "high".to_owned())),
+        [low, high] => pick::command(number(low)?, number(high)?),
+        _           => Err(Error::ExtraArgs(name.to_owned())),
+    }
+}
+
+fn dice_args(_name: &str, args: &[String]) -> crate::Result<Command>
+{
+    dice::command(args.join(" "))
+}
+
+fn select_args(_name: &str, args: &[String]) -> crate::Result<Command>
+{
+    select::command(args.to_vec())
+}
+
+fn shuffle_args(_name: &str, args: &[String]) -> crate::Result<Command>
+{
+    shuffle::command(args.to_vec())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use spectral::prelude::*;
+
+    use super::*;
+
+    fn strings(strs: &[&str]) -> Vec<String>
+    {
+        strs.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn split_plain_words()
+    {
+        assert_that!(split_words("  select a  b\tc "))
+            .is_ok_containing(strings(&["select", "a", "b", "c"]));
+    }
+
+    #[test]
+    fn split_quoted_words()
+    {
+        assert_that!(split_words(r#"select "red wine" 'pale ale' it\'s """#))
+            .is_ok_containing(strings(&["select", "red wine", "pale ale", "it's", ""]));
+    }
+
+    #[test]
+    fn split_bad_quotes()
+    {
+        assert_that!(split_words("select 'red wine"))
+            .is_err_containing(Error::UnterminatedQuote('\''));
+        assert_that!(split_words("select red\\"))
+            .is_err_containing(Error::TrailingEscape);
+    }
+
+    #[test]
+    fn parse_each_command()
+    {
+        assert_that!(parse("coin")).is_ok_containing(coin::command().unwrap());
+        assert_that!(parse("deck tarot")).is_ok_containing(deck::command("tarot").unwrap());
+        assert_that!(parse("oracle")).is_ok_containing(oracle::command().unwrap());
+        assert_that!(parse("percent 35")).is_ok_containing(percent::command(35).unwrap());
+        assert_that!(parse("pick 1 10")).is_ok_containing(pick::command(1, 10).unwrap());
+        assert_that!(parse("roll 3d6 + 2")).is_ok_containing(dice::command("3d6 + 2".into()).unwrap());
+        assert_that!(parse("roll hit: d20+5; dmg: 2d6+3"))
+            .is_ok_containing(dice::command("hit: d20+5; dmg: 2d6+3".into()).unwrap());
+        assert_that!(parse("select a 'b c'"))
+            .is_ok_containing(select::command(strings(&["a", "b c"])).unwrap());
+        assert_that!(parse("shuffle a b"))
+            .is_ok_containing(shuffle::command(strings(&["a", "b"])).unwrap());
+    }
+
+    #[test]
+    fn from_str_command()
+    {
+        assert_that!("pick -5 5".parse::<Command>())
+            .is_ok_containing(pick::command(-5, 5).unwrap());
+    }
+
+    #[test]
+    fn builtins_are_built_once()
+    {
+        assert_that!(std::ptr::eq(builtins(), builtins())).is_true();
+    }
+
+    #[test]
+    fn parse_errors()
+    {
+        assert_that!(parse("   ")).is_err_containing(Error::MissingCommand);
+        assert_that!(parse("flip")).is_err_containing(Error::UnknownCommand("flip".into()));
+        assert_that!(parse("coin heads")).is_err_containing(Error::ExtraArgs("coin".into()));
+        assert_that!(parse("deck")).is_err_containing(Error::MissingArg("deck".into(), "type".into()));
+        assert_that!(parse("pick 1")).is_err_containing(Error::MissingArg("pick".into(), "high".into()));
+        assert_that!(parse("pick 1 x")).is_err_containing(Error::InvalidNumber("x".into()));
+        assert_that!(parse("roll")).is_err_containing(Error::DiceMissingExpr);
+        assert_that!(parse("select a")).is_err_containing(Error::ListOne);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip()
+    {
+        let commands = [
+            ("coin", r#"{"command":"CoinFlip","params":null}"#),
+            ("deck jokers", r#"{"command":"DrawCard","params":"Jokers"}"#),
+            ("oracle", r#"{"command":"Oracle","params":null}"#),
+            ("percent 35", r#"{"command":"PercentTrue","params":35}"#),
+            ("pick 1 10", r#"{"command":"PickNumber","params":{"low":1,"high":10}}"#),
+            ("select david mark", r#"{"command":"Selection","params":["david","mark"]}"#),
+            ("shuffle david mark", r#"{"command":"Shuffle","params":["david","mark"]}"#),
+        ];
+        for (text, json) in commands
+        {
+            let cmd = parse(text).unwrap();
+            assert_that!(serde_json::to_string(&cmd).unwrap().as_str()).is_equal_to(json);
+            assert_that!(serde_json::from_str::<Command>(json).unwrap()).is_equal_to(cmd);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_validates()
+    {
+        let invalid = [
+            r#"{"command":"PercentTrue","params":0}"#,
+            r#"{"command":"PercentTrue","params":100}"#,
+            r#"{"command":"PickNumber","params":{"low":1,"high":1}}"#,
+            r#"{"command":"Selection","params":["fred"]}"#,
+            r#"{"command":"Shuffle","params":["fred"]}"#,
+        ];
+        for json in invalid
+        {
+            assert_that!(serde_json::from_str::<Command>(json)).is_err();
+        }
+    }
+}
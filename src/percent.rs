@@ -1,29 +1,56 @@
 use crate::{Command, Decision, Decider};
 use crate::Error;
 use crate::ApiDoc;
+use crate::distribution::{Distribution, Outcome};
 
-use rand::Rng;
+use rand::{Rng, RngCore};
+
+use std::convert::TryFrom;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "u32"))]
 pub struct Likely(u32);
 
+impl TryFrom<u32> for Likely {
+    type Error = Error;
+
+    /// Create a Likely from a percent value between 1 and 99.
+    fn try_from(likely: u32) -> crate::Result<Likely>
+    {
+        match likely
+        {
+            0 => Err(Error::PercentZero),
+            num if num >= 100 => Err(Error::PercentOverflow(num)),
+            num => Ok(Likely(num))
+        }
+    }
+}
+
 /// Create a PercentTrue Command based on the supplied percent value.
 /// Returns the command or an error specifying an invald parameter.
 pub fn command(likely: u32) -> crate::Result<Command>
 {
-    match likely
-    {
-        0 => Err(Error::PercentZero),
-        num if num >= 100 => Err(Error::PercentOverflow(num)),
-        num => Ok(Command::PercentTrue(Likely(num)))
-    }
+    Likely::try_from(likely).map(Command::PercentTrue)
 }
 
 impl Decider for Likely {
     /// Return a boolean Decision with a true value likely% of the time.
-    fn decide(&self) -> Decision
+    fn decide_with(&self, rng: &mut dyn RngCore) -> Decision
+    {
+        Decision::Bool(rng.gen_bool(f64::from(self.0) / 100.0))
+    }
+
+    /// True with a probability of likely%, otherwise false.
+    fn distribution(&self) -> Option<Distribution>
     {
-        Decision::Bool(rand::thread_rng().gen_bool(f64::from(self.0) / 100.0))
+        let prob = f64::from(self.0) / 100.0;
+        Some(Distribution::from_outcomes(vec![
+            (Outcome::Bool(true), prob),
+            (Outcome::Bool(false), 1.0 - prob),
+        ]))
     }
 }
 
@@ -46,12 +73,14 @@ pub fn api_doc() -> ApiDoc
 mod tests
 {
     use spectral::prelude::*;
+    use rand::rngs::mock::StepRng;
 
     use crate::Decision;
     use crate::DecisionAssertions;
     use crate::Decider;
     use crate::Command;
     use super::*;
+    use crate::distribution::Outcome;
 
     #[test]
     fn command_0_percent()
@@ -99,4 +128,18 @@ mod tests
         assert_that!(&choices).is_greater_than_or_equal_to(300);
         assert_that!(&choices).is_less_than_or_equal_to(400);
     }
+
+    #[test]
+    fn lowest_draw_is_within_one_percent()
+    {
+        assert_that!(Likely(1).decide_with(&mut StepRng::new(0, 0))).is_equal_to(Decision::Bool(true));
+    }
+
+    #[test]
+    fn percent_distribution()
+    {
+        let dist = Likely(35).distribution().unwrap();
+        assert_that!(dist.probability(&Outcome::Bool(true))).is_close_to(0.35, 1e-12);
+        assert_that!(dist.probability(&Outcome::Bool(false))).is_close_to(0.65, 1e-12);
+    }
 }
@@ -1,33 +1,64 @@
 use crate::{Command, Decision, Decider};
 use crate::Error;
 use crate::ApiDoc;
+use crate::distribution::{self, Distribution, Outcome};
 
 use std::cmp::Ordering;
-use rand::Rng;
+use std::convert::TryFrom;
+use rand::{Rng, RngCore};
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "Range"))]
 pub struct Picker {
     low: i32,
     high: i32
 }
 
+/// Unvalidated form of a Picker, used when deserializing.
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+struct Range {
+    low: i32,
+    high: i32
+}
+
+impl TryFrom<Range> for Picker {
+    type Error = Error;
+
+    /// Create a Picker covering the range, ordering the ends as needed.
+    fn try_from(Range{low, high}: Range) -> crate::Result<Picker>
+    {
+        match low.cmp(&high)
+        {
+            Ordering::Equal   => Err(Error::EmptyRange),
+            Ordering::Greater => Ok(Picker{low: high, high: low}),
+            Ordering::Less    => Ok(Picker{low, high}),
+        }
+    }
+}
+
 /// Create a PickNumber command based on the two supplied values
 /// Return either the command or an error if the parameters are not appropriate.
 pub fn command(low: i32, high: i32) -> crate::Result<Command>
 {
-    match low.cmp(&high)
-    {
-        Ordering::Equal   => Err(Error::EmptyRange),
-        Ordering::Greater => Ok(Command::PickNumber(Picker{low: high, high: low})),
-        Ordering::Less    => Ok(Command::PickNumber(Picker{low, high})),
-    }
+    Picker::try_from(Range{low, high}).map(Command::PickNumber)
 }
 
 impl Decider for Picker {
     /// Return a numeric Decision with a value between low and high (inclusive).
-    fn decide(&self) -> Decision
+    fn decide_with(&self, rng: &mut dyn RngCore) -> Decision
+    {
+        Decision::Num(rng.gen_range(self.low, self.high + 1))
+    }
+
+    /// Each number in the range is equally likely.
+    fn distribution(&self) -> Option<Distribution>
     {
-        Decision::Num(rand::thread_rng().gen_range(self.low, self.high + 1))
+        let size = i64::from(self.high) - i64::from(self.low) + 1;
+        if size > distribution::MAX_OUTCOMES as i64 { return None; }
+        Some(Distribution::uniform((self.low..=self.high).map(|n| Outcome::Num(i64::from(n))).collect()))
     }
 }
 
@@ -50,6 +81,7 @@ pub fn api_doc() -> ApiDoc
 mod tests
 {
     use spectral::prelude::*;
+    use rand::rngs::mock::StepRng;
 
     const NUM_TRIES: u32 = 3;
     use crate::Decision;
@@ -57,6 +89,7 @@ mod tests
     use crate::Decider;
     use crate::Command;
     use super::*;
+    use crate::distribution::Outcome;
 
     #[test]
     fn command_with_equal_params()
@@ -119,4 +152,38 @@ mod tests
             }
         }
     }
+
+    #[test]
+    fn lowest_draw_picks_low()
+    {
+        assert_that!(Picker{low: 5, high: 10}.decide_with(&mut StepRng::new(0, 0))).is_equal_to(Decision::Num(5));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_orders_bounds()
+    {
+        assert_that!(serde_json::from_str::<Picker>(r#"{"low":10,"high":1}"#).unwrap())
+            .is_equal_to(Picker{low: 1, high: 10});
+    }
+
+    #[test]
+    fn pick_distribution()
+    {
+        let dist = Picker{low: -2, high: 2}.distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(5);
+        assert_that!(dist.probability(&Outcome::Num(-2))).is_equal_to(0.2);
+        assert_that!(dist.probability(&Outcome::Num(3))).is_equal_to(0.0);
+        assert_that!(Picker{low: 0, high: i32::MAX}.distribution()).is_none();
+    }
+
+    #[test]
+    fn pick_is_fair()
+    {
+        let cmd = command(1, 20).unwrap();
+        let fit = crate::fairness::chi_square(&cmd, 4000, 42).unwrap();
+        assert_that!(fit.is_consistent(0.001)).is_true();
+        let fit = crate::fairness::kolmogorov_smirnov(&cmd, 4000, 42).unwrap();
+        assert_that!(fit.is_consistent(0.001)).is_true();
+    }
 }
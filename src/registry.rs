@@ -0,0 +1,294 @@
+use crate::{Command, Decision, Decider};
+use crate::ApiDoc;
+use crate::Error;
+use crate::parse;
+
+use rand::RngCore;
+
+use std::fmt;
+use std::sync::Arc;
+
+type EntryBuilder = Box<dyn Fn(&str, &[String]) -> crate::Result<Command> + Send + Sync>;
+
+/// A registered command: its documentation and the function that builds the
+/// Command from its arguments.
+struct Entry
+{
+    doc: ApiDoc,
+    builder: EntryBuilder,
+}
+
+/// Collection of the documentation and constructors for all of the commands
+/// that can be parsed. A new Registry contains all of the built-in commands,
+/// and custom deciders can be added with `register()`.
+pub struct Registry
+{
+    entries: Vec<Entry>,
+}
+
+/// A Command wrapping a Decider registered by another crate.
+#[derive(Clone)]
+pub struct Custom
+{
+    name: &'static str,
+    args: Vec<String>,
+    decider: Arc<dyn Decider + Send + Sync>,
+}
+
+impl Custom
+{
+    /// Return the name of the command that built this decider.
+    pub fn name(&self) -> &'static str
+    {
+        self.name
+    }
+
+    /// Return the arguments used to build this decider.
+    pub fn args(&self) -> &[String]
+    {
+        &self.args
+    }
+}
+
+impl fmt::Debug for Custom
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Custom")
+            .field("name", &self.name)
+            .field("args", &self.args)
+            .finish()
+    }
+}
+
+/// Custom deciders are equal if they were built by the same command from the
+/// same arguments.
+impl PartialEq for Custom
+{
+    fn eq(&self, other: &Custom) -> bool
+    {
+        self.name == other.name && self.args == other.args
+    }
+}
+
+impl Decider for Custom {
+    /// Delegate the decision to the registered decider.
+    fn decide_with(&self, rng: &mut dyn RngCore) -> Decision
+    {
+        self.decider.decide_with(rng)
+    }
+
+    /// Return the distribution of the registered decider.
+    fn distribution(&self) -> Option<crate::distribution::Distribution>
+    {
+        self.decider.distribution()
+    }
+}
+
+impl Registry
+{
+    /// Create a Registry containing all of the built-in commands.
+    pub fn new() -> Registry
+    {
+        let entries = parse::BUILTINS.iter()
+            .map(|&(doc, builder)| Entry{ doc: doc(), builder: Box::new(builder) })
+            .collect();
+        Registry { entries }
+    }
+
+    /// Add a custom command to the Registry. The builder converts the
+    /// arguments from the command line into a Decider. Returns an error if a
+    /// command with the same name is already registered.
+    pub fn register<F>(&mut self, doc: ApiDoc, builder: F) -> crate::Result<()>
+        where F: Fn(&[String]) -> crate::Result<Box<dyn Decider + Send + Sync>> + Send + Sync + 'static
+    {
+        if self.doc(doc.name).is_some()
+        {
+            return Err(Error::DuplicateCommand(doc.name.to_owned()));
+        }
+        let name = doc.name;
+        self.entries.push(Entry{
+            doc,
+            builder: Box::new(move |_, args| Ok(Command::Custom(Custom{
+                name,
+                args: args.to_vec(),
+                decider: Arc::from(builder(args)?),
+            }))),
+        });
+        Ok(())
+    }
+
+    /// Return the documentation for all of the registered commands in the
+    /// order they were registered.
+    pub fn docs(&self) -> impl Iterator<Item=&ApiDoc>
+    {
+        self.entries.iter().map(|e| &e.doc)
+    }
+
+    /// Return the documentation for the named command.
+    pub fn doc(&self, name: &str) -> Option<&ApiDoc>
+    {
+        self.docs().find(|doc| doc.name == name)
+    }
+
+    /// Build the named command from the supplied arguments.
+    pub fn command(&self, name: &str, args: &[String]) -> crate::Result<Command>
+    {
+        let entry = self.entries.iter()
+            .find(|e| e.doc.name == name)
+            .ok_or_else(|| Error::UnknownCommand(name.to_owned()))?;
+        (entry.builder)(name, args)
+    }
+
+    /// Parse a command line into the matching Command, as described in
+    /// `parse::parse()`.
+    pub fn parse(&self, line: &str) -> crate::Result<Command>
+    {
+        let words = parse::split_words(line)?;
+        let (name, args) = words.split_first().ok_or(Error::MissingCommand)?;
+        self.command(name, args)
+    }
+}
+
+impl Default for Registry
+{
+    fn default() -> Registry
+    {
+        Registry::new()
+    }
+}
+
+/// Return the ApiDoc objects for all of the built-in commands.
+pub fn api_docs() -> Vec<ApiDoc>
+{
+    parse::BUILTINS.iter().map(|(doc, _)| doc()).collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use spectral::prelude::*;
+    use rand::rngs::mock::StepRng;
+
+    use super::*;
+    use crate::coin;
+
+    #[derive(Debug)]
+    struct Count(u32);
+
+    impl Decider for Count {
+        fn decide_with(&self, _rng: &mut dyn RngCore) -> Decision
+        {
+            Decision::Num(self.0 as i32)
+        }
+    }
+
+    fn count_doc() -> ApiDoc
+    {
+        ApiDoc {
+            name: "count",
+            params: vec!["num"],
+            hint: "Always return {num}",
+            help: vec!["Returns the supplied number."],
+        }
+    }
+
+    fn count_registry() -> Registry
+    {
+        let mut registry = Registry::new();
+        registry.register(count_doc(), |args| match args
+            {
+                [num] => num.parse::<u32>()
+                    .map(|n| Box::new(Count(n)) as Box<dyn Decider + Send + Sync>)
+                    .map_err(|_| Error::InvalidNumber(num.to_owned())),
+                [] => Err(Error::MissingArg("count".into(), "num".into())),
+                _  => Err(Error::ExtraArgs("count".into())),
+            })
+            .unwrap();
+        registry
+    }
+
+    #[test]
+    fn builtin_docs()
+    {
+        let names: Vec<&str> = api_docs().iter().map(|d| d.name).collect();
+        assert_that!(names)
+            .is_equal_to(vec!["coin", "deck", "oracle", "percent", "pick", "roll", "select", "shuffle"]);
+        let rnames: Vec<&str> = Registry::new().docs().map(|d| d.name).collect();
+        assert_that!(rnames).is_equal_to(names);
+    }
+
+    #[test]
+    fn lookup_doc()
+    {
+        let registry = Registry::new();
+        assert_that!(registry.doc("roll").map(|d| d.hint))
+            .is_some()
+            .is_equal_to("Roll the described combination of dice");
+        assert_that!(registry.doc("count")).is_none();
+    }
+
+    #[test]
+    fn builtin_command()
+    {
+        assert_that!(Registry::new().command("coin", &[]))
+            .is_ok_containing(coin::command().unwrap());
+    }
+
+    #[test]
+    fn custom_command()
+    {
+        let registry = count_registry();
+        assert_that!(registry.doc("count")).is_some();
+        assert_that!(registry.docs().count()).is_equal_to(9);
+
+        let cmd = registry.parse("count 7").unwrap();
+        assert_that!(cmd).is_equal_to(registry.parse("count '7'").unwrap());
+        assert_that!(cmd).is_not_equal_to(registry.parse("count 8").unwrap());
+        match cmd.decide_with(&mut StepRng::new(0, 0))
+        {
+            Decision::Num(num) => assert_that!(num).is_equal_to(7),
+            _ => panic!("Wrong decision type"),
+        }
+    }
+
+    #[test]
+    fn custom_command_errors()
+    {
+        let registry = count_registry();
+        assert_that!(registry.parse("count x"))
+            .is_err_containing(Error::InvalidNumber("x".into()));
+        assert_that!(crate::parse("count 7"))
+            .is_err_containing(Error::UnknownCommand("count".into()));
+    }
+
+    #[test]
+    fn duplicate_command()
+    {
+        let mut registry = count_registry();
+        assert_that!(registry.register(count_doc(), |_| Ok(Box::new(Count(1)))))
+            .is_err_containing(Error::DuplicateCommand("count".into()));
+        assert_that!(registry.register(coin::api_doc(), |_| Ok(Box::new(Count(1)))))
+            .is_err_containing(Error::DuplicateCommand("coin".into()));
+    }
+
+    #[test]
+    fn custom_distribution()
+    {
+        let mut registry = count_registry();
+        let doc = ApiDoc{ name: "d6", params: vec![], hint: "Roll a d6", help: vec![] };
+        registry.register(doc, |_| Ok(Box::new(crate::dice::command("d6".into())?))).unwrap();
+
+        let cmd = registry.parse("d6").unwrap();
+        assert_that!(cmd.distribution().map(|dist| dist.len())).is_some().is_equal_to(6);
+        assert_that!(crate::fairness::chi_square(&cmd, 1000, 42)).is_some();
+        assert_that!(registry.parse("count 7").unwrap().distribution()).is_none();
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn custom_not_serialized()
+    {
+        let cmd = count_registry().parse("count 7").unwrap();
+        assert_that!(serde_json::to_string(&cmd)).is_err();
+    }
+}
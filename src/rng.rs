@@ -0,0 +1,194 @@
+//! Support for reproducible random number generation.
+//!
+//! A generator built with `seeded()` produces the same sequence of values on
+//! every run and every platform, so any Decider driven by it produces the same
+//! sequence of Decisions. The guarantee for each Decider is:
+//!
+//! - coin: stable, one index drawn per flip.
+//! - deck: stable, one index drawn per card. `deck::shuffled_with()` is also
+//!   stable.
+//! - oracle: stable, one index drawn for the answer and one for the label.
+//! - percent: stable, one 64-bit value drawn per decision.
+//! - pick: stable, one value drawn per decision.
+//! - roll: stable, one value drawn per die rolled, including dice re-rolled
+//!   by a reroll modifier or by exploding, and one index drawn per custom die.
+//!   The expressions of a set of rolls are rolled in the order written, each
+//!   repeated expression as many times as it is repeated before any sorting,
+//!   and the left expression of a contest before the right.
+//! - select: stable, one index drawn per decision.
+//! - shuffle: stable, one index drawn per string after the first.
+//! - custom: as stable as the Decider registered for the command.
+//!
+//! Decisions made without a supplied generator, through `decide()`,
+//! `Command::iter()` or `deck::shuffled()`, use the thread-local generator.
+//! With the `secure` feature enabled they use the operating system's
+//! cryptographically secure generator instead. The `RngSource` of a decision
+//! can be chosen at run time with `Decider::decide_from()`, which reports the
+//! source used alongside the Decision.
+//!
+//! Indexes are always drawn as `u32` values, so the results do not depend on
+//! the size of `usize` on the platform. The sequences are only guaranteed
+//! within a release series of this library; a change to the sequence will be
+//! treated as a breaking change.
+
+use rand::{Rng, RngCore, SeedableRng};
+use rand::rngs::OsRng;
+use rand_chacha::ChaChaRng;
+
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+/// The random number generator used for seeded decisions.
+pub type SeededRng = ChaChaRng;
+
+/// Construct a reproducible random number generator from the supplied seed.
+pub fn seeded(seed: u64) -> SeededRng
+{
+    SeededRng::seed_from_u64(seed)
+}
+
+/// The source of randomness used to make a decision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RngSource
+{
+    /// The thread-local generator from `rand::thread_rng()`.
+    Thread,
+    /// The operating system's cryptographically secure generator.
+    Os,
+    /// The reproducible generator from `seeded()` with the given seed.
+    Seeded(u64),
+}
+
+impl RngSource
+{
+    /// Return true if the source is cryptographically secure.
+    pub fn is_secure(&self) -> bool
+    {
+        *self == RngSource::Os
+    }
+}
+
+impl fmt::Display for RngSource
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self
+        {
+            RngSource::Thread       => write!(f, "thread"),
+            RngSource::Os           => write!(f, "os"),
+            RngSource::Seeded(seed) => write!(f, "seeded({})", seed),
+        }
+    }
+}
+
+/// Return the source used when no generator is supplied: `Os` if the `secure`
+/// feature is enabled, otherwise `Thread`.
+pub fn default_source() -> RngSource
+{
+    if cfg!(feature = "secure") { RngSource::Os } else { RngSource::Thread }
+}
+
+/// Construct the operating system's cryptographically secure generator.
+///
+/// Panics if the operating system cannot supply random numbers.
+pub fn os() -> OsRng
+{
+    OsRng::new().expect("Operating system random number generator unavailable")
+}
+
+/// Call the function with a generator from the supplied source.
+pub(crate) fn with_source<T, F>(source: RngSource, f: F) -> T
+    where F: FnOnce(&mut dyn RngCore) -> T
+{
+    match source
+    {
+        RngSource::Thread       => f(&mut rand::thread_rng()),
+        RngSource::Os           => f(&mut os()),
+        RngSource::Seeded(seed) => f(&mut seeded(seed)),
+    }
+}
+
+/// Return a random index in the range 0..len, independent of platform.
+pub(crate) fn gen_index<R>(rng: &mut R, len: usize) -> usize
+    where R: RngCore + ?Sized
+{
+    rng.gen_range(0, len as u32) as usize
+}
+
+/// Shuffle the slice in place, independent of platform.
+pub(crate) fn shuffle<R, T>(rng: &mut R, items: &mut [T])
+    where R: RngCore + ?Sized
+{
+    for i in (1..items.len()).rev()
+    {
+        items.swap(i, gen_index(rng, i + 1));
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn seeded_is_repeatable()
+    {
+        let (mut rng1, mut rng2) = (seeded(42), seeded(42));
+        let first: Vec<u32> = (0..10).map(|_| rng1.next_u32()).collect();
+        let second: Vec<u32> = (0..10).map(|_| rng2.next_u32()).collect();
+        assert_that!(first).is_equal_to(second);
+    }
+
+    #[test]
+    fn seeded_differs_by_seed()
+    {
+        assert_that!(seeded(42).next_u64()).is_not_equal_to(seeded(43).next_u64());
+    }
+
+    #[test]
+    fn source_display()
+    {
+        assert_that!(RngSource::Thread.to_string()).is_equal_to("thread".to_string());
+        assert_that!(RngSource::Os.to_string()).is_equal_to("os".to_string());
+        assert_that!(RngSource::Seeded(42).to_string()).is_equal_to("seeded(42)".to_string());
+        assert_that!(RngSource::Os.is_secure()).is_true();
+        assert_that!(RngSource::Seeded(42).is_secure()).is_false();
+    }
+
+    #[test]
+    fn default_source_matches_feature()
+    {
+        assert_that!(default_source().is_secure()).is_equal_to(cfg!(feature = "secure"));
+    }
+
+    #[test]
+    fn with_seeded_source()
+    {
+        let first = with_source(RngSource::Seeded(42), |rng| rng.next_u64());
+        assert_that!(first).is_equal_to(seeded(42).next_u64());
+        with_source(RngSource::Os, |rng| rng.next_u64());
+    }
+
+    #[test]
+    fn index_in_range()
+    {
+        let mut rng = seeded(1);
+        for _ in 0..100
+        {
+            assert_that!(gen_index(&mut rng, 3)).is_less_than(3);
+        }
+    }
+
+    #[test]
+    fn shuffle_keeps_items()
+    {
+        let mut items = vec![1, 2, 3, 4, 5];
+        shuffle(&mut seeded(7), &mut items);
+        items.sort();
+        assert_that!(items).is_equal_to(vec![1, 2, 3, 4, 5]);
+    }
+}
@@ -1,22 +1,39 @@
-use rand::thread_rng;
+use rand::RngCore;
 
 use crate::{Command, Decision, Decider};
 use crate::Error;
 use crate::ApiDoc;
+use crate::distribution::{Distribution, Outcome};
+
+use std::convert::TryFrom;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "Vec<String>"))]
 pub struct Choices(Vec<String>);
 
+impl TryFrom<Vec<String>> for Choices {
+    type Error = Error;
+
+    /// Create Choices from a Vec of at least two Strings.
+    fn try_from(strings: Vec<String>) -> crate::Result<Choices>
+    {
+        match strings.len()
+        {
+            0 => Err(Error::ListEmpty),
+            1 => Err(Error::ListOne),
+            _ => Ok(Choices(strings)),
+        }
+    }
+}
+
 /// Create a Selection Command variant from the supplied
 /// Vec of Strings.
 pub fn command(strings: Vec<String>) -> crate::Result<Command>
 {
-    match strings.len()
-    {
-        0 => Err(Error::ListEmpty),
-        1 => Err(Error::ListOne),
-        _ => Ok(Command::Selection(Choices(strings))),
-    }
+    Choices::try_from(strings).map(Command::Selection)
 }
 
 /// Return an ApiDoc object containing a description of the Selection
@@ -37,10 +54,15 @@ pub fn api_doc() -> ApiDoc
 impl Decider for Choices {
     /// Return a Text Decision containing one of the strings from the
     /// Vec chosen at random.
-    fn decide(&self) -> Decision
+    fn decide_with(&self, rng: &mut dyn RngCore) -> Decision
+    {
+        Decision::Text(super::pick_one(rng, &self.0))
+    }
+
+    /// Each of the strings is equally likely.
+    fn distribution(&self) -> Option<Distribution>
     {
-        let mut rng = thread_rng();
-        Decision::Text(super::pick_one(&mut rng, &self.0))
+        Some(Distribution::uniform(self.0.iter().cloned().map(Outcome::Text).collect()))
     }
 }
 
@@ -48,12 +70,14 @@ impl Decider for Choices {
 mod tests
 {
     use spectral::prelude::*;
+    use rand::rngs::mock::StepRng;
 
     use crate::Decision;
     use crate::DecisionAssertions;
     use crate::Decider;
     use crate::Command;
     use super::*;
+    use crate::distribution::Outcome;
 
     #[test]
     fn command_empty_vector()
@@ -95,4 +119,29 @@ mod tests
             _ => assert!(false, "Unexpected Decision"),
         }
     }
+
+    #[test]
+    fn lowest_draw_selects_first()
+    {
+        let names = vec!["david".to_string(), "mark".to_string(), "kirsten".to_string()];
+        assert_that!(Choices(names).decide_with(&mut StepRng::new(0, 0))).is_equal_to(Decision::Text("david".into()));
+    }
+
+    #[test]
+    fn select_distribution()
+    {
+        let names = vec!["david".to_string(), "mark".to_string(), "david".to_string(), "connie".to_string()];
+        let dist = Choices(names).distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(3);
+        assert_that!(dist.probability(&Outcome::Text("david".into()))).is_equal_to(0.5);
+        assert_that!(dist.probability(&Outcome::Text("mark".into()))).is_equal_to(0.25);
+    }
+
+    #[test]
+    fn select_is_fair()
+    {
+        let cmd = command(vec!["a".into(), "b".into(), "c".into(), "d".into(), "e".into()]).unwrap();
+        let fit = crate::fairness::chi_square(&cmd, 2500, 42).unwrap();
+        assert_that!(fit.is_consistent(0.001)).is_true();
+    }
 }
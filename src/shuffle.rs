@@ -1,23 +1,45 @@
 use crate::{Command, Decision, Decider};
 use crate::ApiDoc;
+use crate::distribution::{Distribution, Outcome};
 use crate::Error;
 
-use rand::thread_rng;
-use rand::seq::SliceRandom;
+use rand::RngCore;
+
+use std::convert::TryFrom;
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+/// The most strings whose orderings are listed by `distribution()`. Every
+/// ordering holds its own copy of the strings, so seven strings of up to 100
+/// bytes take at most about 5040 * 7 * 124 bytes, or 4.4 MB, and each string
+/// more multiplies that by the new number of strings.
+pub const MAX_DISTRIBUTED: usize = 7;
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "Vec<String>"))]
 pub struct Choices(Vec<String>);
 
-/// Create a Selection Command variant from the supplied
+impl TryFrom<Vec<String>> for Choices {
+    type Error = Error;
+
+    /// Create Choices from a Vec of at least two Strings.
+    fn try_from(strings: Vec<String>) -> crate::Result<Choices>
+    {
+        match strings.len()
+        {
+            0 => Err(Error::ListEmpty),
+            1 => Err(Error::ListOne),
+            _ => Ok(Choices(strings)),
+        }
+    }
+}
+
+/// Create a Shuffle Command variant from the supplied
 /// Vec of Strings.
 pub fn command(strings: Vec<String>) -> crate::Result<Command>
 {
-    match strings.len()
-    {
-        0 => Err(Error::ListEmpty),
-        1 => Err(Error::ListOne),
-        _ => Ok(Command::Shuffle(Choices(strings))),
-    }
+    Choices::try_from(strings).map(Command::Shuffle)
 }
 
 /// Return an ApiDoc object containing a description of the Selection
@@ -37,25 +59,55 @@ pub fn api_doc() -> ApiDoc
 
 impl Decider for Choices {
     /// Return a List containing the strings in a random order.
-    fn decide(&self) -> Decision
+    fn decide_with(&self, rng: &mut dyn RngCore) -> Decision
     {
-        let mut rng = thread_rng();
         let mut strvec = self.0.to_owned();
-        strvec.as_mut_slice().shuffle(&mut rng);
+        crate::rng::shuffle(rng, &mut strvec);
         Decision::List(strvec)
     }
+
+    /// Each ordering of the strings is equally likely. Only computed for at
+    /// most MAX_DISTRIBUTED strings, checked before listing any orderings.
+    fn distribution(&self) -> Option<Distribution>
+    {
+        if self.0.len() > MAX_DISTRIBUTED { return None; }
+
+        let count = (1..=self.0.len()).product();
+
+        let mut orders = Vec::with_capacity(count);
+        permute(&mut self.0.to_owned(), 0, &mut orders);
+        Some(Distribution::uniform(orders.into_iter().map(Outcome::List).collect()))
+    }
+}
+
+/// Collect every ordering of the strings after position start.
+fn permute(strings: &mut Vec<String>, start: usize, orders: &mut Vec<Vec<String>>)
+{
+    if start == strings.len()
+    {
+        orders.push(strings.clone());
+        return;
+    }
+    for i in start..strings.len()
+    {
+        strings.swap(start, i);
+        permute(strings, start + 1, orders);
+        strings.swap(start, i);
+    }
 }
 
 #[cfg(test)]
 mod tests
 {
     use spectral::prelude::*;
+    use rand::rngs::mock::StepRng;
 
     use crate::Decision;
     use crate::DecisionAssertions;
     use crate::Decider;
     use crate::Command;
     use super::*;
+    use crate::distribution::Outcome;
 
     #[test]
     fn command_empty_vector()
@@ -101,4 +153,37 @@ mod tests
             _ => assert!(false, "Unexpected Decision"),
         }
     }
+
+    #[test]
+    fn lowest_draws_rotate_left()
+    {
+        // Each draw swaps the first string to the end of the unshuffled part.
+        let names = vec!["david".to_string(), "mark".to_string(), "kirsten".to_string(), "connie".to_string()];
+        assert_that!(Choices(names).decide_with(&mut StepRng::new(0, 0))).is_equal_to(Decision::List(vec![
+            "mark".into(), "kirsten".into(), "connie".into(), "david".into(),
+        ]));
+    }
+
+    #[test]
+    fn shuffle_distribution()
+    {
+        let names = vec!["david".to_string(), "mark".to_string(), "connie".to_string()];
+        let dist = Choices(names).distribution().unwrap();
+        assert_that!(dist.len()).is_equal_to(6);
+        let order = vec!["connie".to_string(), "david".to_string(), "mark".to_string()];
+        assert_that!(dist.probability(&Outcome::List(order))).is_close_to(1.0 / 6.0, 1e-12);
+
+        let most: Vec<String> = (1..=MAX_DISTRIBUTED).map(|n| n.to_string()).collect();
+        assert_that!(Choices(most).distribution().unwrap().len()).is_equal_to(5040);
+        let many: Vec<String> = (0..=MAX_DISTRIBUTED).map(|n| n.to_string()).collect();
+        assert_that!(Choices(many).distribution()).is_none();
+    }
+
+    #[test]
+    fn shuffle_permutations_are_fair()
+    {
+        let cmd = command(vec!["a".into(), "b".into(), "c".into(), "d".into()]).unwrap();
+        let fit = crate::fairness::chi_square(&cmd, 4800, 42).unwrap();
+        assert_that!(fit.is_consistent(0.001)).is_true();
+    }
 }
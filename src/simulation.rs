@@ -0,0 +1,294 @@
+//! Monte Carlo simulation of a Command.
+//!
+//! A Simulation records the Outcome of each of a number of decisions, and
+//! reports how often each outcome occurred. When every outcome is numeric
+//! (numbers, dice totals, or booleans counted as 1 and 0) it also reports
+//! summary statistics and percentiles.
+//!
+//! ```rust
+//! let dice = quikdecision::dice::command("2d6".to_string()).unwrap();
+//! let sim = dice.iter_seeded(42).simulate(1000);
+//! let stats = sim.stats().unwrap();
+//! assert!(stats.min >= 2 && stats.max <= 12);
+//! ```
+
+use crate::Decision;
+use crate::distribution::Outcome;
+use crate::iterator::QdIter;
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// The outcomes of a number of decisions and how often each occurred.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation
+{
+    trials: usize,
+    counts: Vec<(Outcome, usize)>,
+    /// The position of each outcome in counts.
+    index: HashMap<Outcome, usize>,
+}
+
+/// Summary statistics of the numeric outcomes of a Simulation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats
+{
+    pub count: usize,
+    pub mean: f64,
+    pub variance: f64,
+    pub min: i64,
+    pub max: i64,
+}
+
+impl Stats
+{
+    /// Return the standard deviation of the outcomes.
+    pub fn std_dev(&self) -> f64
+    {
+        self.variance.sqrt()
+    }
+}
+
+impl Simulation
+{
+    /// Record the outcome of each of the supplied decisions.
+    pub fn from_decisions<I>(decisions: I) -> Simulation
+        where I: IntoIterator<Item=Decision>
+    {
+        let mut index: HashMap<Outcome, usize> = HashMap::new();
+        let mut counts: Vec<(Outcome, usize)> = Vec::new();
+        let mut trials = 0;
+        for decision in decisions
+        {
+            trials += 1;
+            let outcome = Outcome::from(&decision);
+            match index.get(&outcome)
+            {
+                Some(&i) => counts[i].1 += 1,
+                None     => {
+                    index.insert(outcome.clone(), counts.len());
+                    counts.push((outcome, 1));
+                },
+            }
+        }
+        counts.sort_by_key(|(o, count)| (o.as_num(), Reverse(*count)));
+        for (i, (outcome, _)) in counts.iter().enumerate()
+        {
+            index.insert(outcome.clone(), i);
+        }
+        Simulation{ trials, counts, index }
+    }
+
+    /// Return the number of decisions made.
+    pub fn trials(&self) -> usize
+    {
+        self.trials
+    }
+
+    /// Return each outcome and the number of times it occurred. Numeric
+    /// outcomes are sorted from lowest to highest, forming a histogram. Other
+    /// outcomes are sorted from most to least frequent.
+    pub fn frequencies(&self) -> &[(Outcome, usize)]
+    {
+        &self.counts
+    }
+
+    /// Return the number of times the supplied outcome occurred.
+    pub fn count(&self, outcome: &Outcome) -> usize
+    {
+        self.index.get(outcome).map_or(0, |&i| self.counts[i].1)
+    }
+
+    /// Return true if every outcome has a numeric value.
+    pub fn is_numeric(&self) -> bool
+    {
+        self.counts.iter().all(|(o, _)| o.as_num().is_some())
+    }
+
+    /// Return the summary statistics of the outcomes, or None if there were no
+    /// decisions or any outcome is not numeric. The variance is that of the
+    /// simulated outcomes themselves, not an estimate of a larger population.
+    pub fn stats(&self) -> Option<Stats>
+    {
+        let nums = self.nums()?;
+        let count = self.trials as f64;
+        let mean = nums.iter().map(|&(n, c)| n as f64 * c as f64).sum::<f64>() / count;
+        let variance = nums.iter()
+            .map(|&(n, c)| (n as f64 - mean).powi(2) * c as f64)
+            .sum::<f64>() / count;
+        Some(Stats{
+            count: self.trials,
+            mean,
+            variance,
+            min: nums.first()?.0,
+            max: nums.last()?.0,
+        })
+    }
+
+    /// Return the smallest outcome that at least `pct` percent of the outcomes
+    /// are less than or equal to (the nearest-rank percentile). Returns None if
+    /// `pct` is not between 0 and 100, or if `stats()` would return None.
+    pub fn percentile(&self, pct: f64) -> Option<i64>
+    {
+        if !(0.0..=100.0).contains(&pct)
+        {
+            return None;
+        }
+        let nums = self.nums()?;
+        let rank = ((pct / 100.0 * self.trials as f64).ceil() as usize).max(1);
+        let mut seen = 0;
+        nums.iter()
+            .find(|&&(_, c)| { seen += c; seen >= rank })
+            .map(|&(n, _)| n)
+    }
+
+    /// Return the frequency table as CSV, with a header line followed by one
+    /// line per outcome giving the outcome, its count, and the fraction of
+    /// decisions that produced it.
+    pub fn to_csv(&self) -> String
+    {
+        let mut csv = String::from("outcome,count,frequency\n");
+        for (outcome, count) in &self.counts
+        {
+            csv.push_str(&format!("{},{},{}\n",
+                csv_field(&outcome.to_string()), count, *count as f64 / self.trials as f64));
+        }
+        csv
+    }
+
+    /// Return the numeric value and count of each outcome, in order.
+    fn nums(&self) -> Option<Vec<(i64, usize)>>
+    {
+        if self.trials == 0
+        {
+            return None;
+        }
+        self.counts.iter()
+            .map(|(o, count)| o.as_num().map(|n| (n, *count)))
+            .collect()
+    }
+}
+
+/// Quote a CSV field if it contains a comma, quote, or line break.
+fn csv_field(field: &str) -> String
+{
+    if field.contains(&[',', '"', '\n', '\r'][..])
+    {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    }
+    else
+    {
+        field.to_owned()
+    }
+}
+
+impl<'a> QdIter<'a>
+{
+    /// Make the supplied number of decisions and record their outcomes.
+    pub fn simulate(self, trials: usize) -> Simulation
+    {
+        Simulation::from_decisions(self.take(trials))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use spectral::prelude::*;
+
+    use super::*;
+    use crate::{coin, dice, percent};
+
+    fn nums(values: &[i32]) -> Simulation
+    {
+        Simulation::from_decisions(values.iter().map(|&n| Decision::Num(n)))
+    }
+
+    #[test]
+    fn histogram_is_sorted()
+    {
+        let sim = nums(&[3, 1, 2, 3, 3, 1]);
+        assert_that!(sim.trials()).is_equal_to(6);
+        assert_that!(sim.frequencies().to_vec())
+            .is_equal_to(vec![(Outcome::Num(1), 2), (Outcome::Num(2), 1), (Outcome::Num(3), 3)]);
+        assert_that!(sim.count(&Outcome::Num(3))).is_equal_to(3);
+        assert_that!(sim.count(&Outcome::Num(4))).is_equal_to(0);
+    }
+
+    #[test]
+    fn numeric_stats()
+    {
+        let stats = nums(&[2, 4, 4, 4, 5, 5, 7, 9]).stats().unwrap();
+        assert_that!(stats).is_equal_to(Stats{ count: 8, mean: 5.0, variance: 4.0, min: 2, max: 9 });
+        assert_that!(stats.std_dev()).is_equal_to(2.0);
+    }
+
+    #[test]
+    fn percentiles()
+    {
+        let sim = nums(&[15, 20, 35, 40, 50]);
+        assert_that!(sim.percentile(0.0)).is_some().is_equal_to(15);
+        assert_that!(sim.percentile(30.0)).is_some().is_equal_to(20);
+        assert_that!(sim.percentile(40.0)).is_some().is_equal_to(20);
+        assert_that!(sim.percentile(50.0)).is_some().is_equal_to(35);
+        assert_that!(sim.percentile(100.0)).is_some().is_equal_to(50);
+        assert_that!(sim.percentile(101.0)).is_none();
+    }
+
+    #[test]
+    fn empty_simulation()
+    {
+        let sim = nums(&[]);
+        assert_that!(sim.stats()).is_none();
+        assert_that!(sim.percentile(50.0)).is_none();
+        assert_that!(sim.to_csv()).is_equal_to("outcome,count,frequency\n".to_string());
+    }
+
+    #[test]
+    fn simulate_dice()
+    {
+        let sim = dice::command("2d6".into()).unwrap().iter_seeded(42).simulate(2000);
+        let stats = sim.stats().unwrap();
+        assert_that!(stats.count).is_equal_to(2000);
+        assert_that!(stats.min).is_greater_than_or_equal_to(2);
+        assert_that!(stats.max).is_less_than_or_equal_to(12);
+        assert_that!(stats.mean).is_close_to(7.0, 0.25);
+        assert_that!(sim.percentile(50.0)).is_some().is_equal_to(7);
+    }
+
+    #[test]
+    fn simulate_bools()
+    {
+        let sim = percent::command(25).unwrap().iter_seeded(7).simulate(2000);
+        assert_that!(sim.is_numeric()).is_true();
+        assert_that!(sim.frequencies()[0].0.clone()).is_equal_to(Outcome::Bool(false));
+        assert_that!(sim.stats().unwrap().mean).is_close_to(0.25, 0.03);
+    }
+
+    #[test]
+    fn simulate_text()
+    {
+        let sim = coin::command().unwrap().iter_seeded(42).simulate(100);
+        assert_that!(sim.is_numeric()).is_false();
+        assert_that!(sim.stats()).is_none();
+        assert_that!(sim.frequencies().len()).is_equal_to(2);
+        assert_that!(sim.frequencies().iter().map(|(_, c)| c).sum::<usize>()).is_equal_to(100);
+        assert_that!(sim.frequencies()[0].1).is_greater_than_or_equal_to(sim.frequencies()[1].1);
+    }
+
+    #[test]
+    fn csv_export()
+    {
+        let sim = Simulation::from_decisions(vec![
+            Decision::Text("red, white".into()),
+            Decision::Text("say \"hi\"".into()),
+            Decision::Text("red, white".into()),
+            Decision::Text("red, white".into()),
+        ]);
+        assert_that!(sim.to_csv())
+            .is_equal_to("outcome,count,frequency\n\"red, white\",3,0.75\n\"say \"\"hi\"\"\",1,0.25\n".to_string());
+
+        assert_that!(nums(&[1, 1, 2, 2]).to_csv())
+            .is_equal_to("outcome,count,frequency\n1,2,0.5\n2,2,0.5\n".to_string());
+    }
+}